@@ -0,0 +1,5 @@
+DROP TRIGGER IF EXISTS set_updated_at ON manufacturers;
+ALTER TABLE manufacturers DROP COLUMN created_at, DROP COLUMN updated_at;
+
+DROP TRIGGER IF EXISTS set_updated_at ON categories;
+ALTER TABLE categories DROP COLUMN created_at, DROP COLUMN updated_at;
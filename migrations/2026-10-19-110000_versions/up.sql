@@ -0,0 +1,3 @@
+ALTER TABLE products ADD COLUMN version integer NOT NULL DEFAULT 1;
+ALTER TABLE categories ADD COLUMN version integer NOT NULL DEFAULT 1;
+ALTER TABLE manufacturers ADD COLUMN version integer NOT NULL DEFAULT 1;
@@ -0,0 +1 @@
+ALTER TABLE products ADD COLUMN sku text UNIQUE;
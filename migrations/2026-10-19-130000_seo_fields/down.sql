@@ -0,0 +1,11 @@
+ALTER TABLE categories
+    DROP COLUMN meta_title,
+    DROP COLUMN meta_description,
+    DROP COLUMN canonical_url,
+    DROP COLUMN noindex;
+
+ALTER TABLE products
+    DROP COLUMN meta_title,
+    DROP COLUMN meta_description,
+    DROP COLUMN canonical_url,
+    DROP COLUMN noindex;
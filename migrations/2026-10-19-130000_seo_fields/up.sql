@@ -0,0 +1,11 @@
+ALTER TABLE products
+    ADD COLUMN meta_title text,
+    ADD COLUMN meta_description text,
+    ADD COLUMN canonical_url text,
+    ADD COLUMN noindex boolean NOT NULL DEFAULT false;
+
+ALTER TABLE categories
+    ADD COLUMN meta_title text,
+    ADD COLUMN meta_description text,
+    ADD COLUMN canonical_url text,
+    ADD COLUMN noindex boolean NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+DROP TABLE slug_history;
+
+ALTER TABLE products DROP CONSTRAINT products_slug_key;
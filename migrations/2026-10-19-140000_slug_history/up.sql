@@ -0,0 +1,9 @@
+ALTER TABLE products ADD CONSTRAINT products_slug_key UNIQUE (slug);
+
+CREATE TABLE slug_history (
+    slug text PRIMARY KEY,
+    product_id uuid NOT NULL REFERENCES products (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    retired_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX slug_history_product_id_idx ON slug_history (product_id);
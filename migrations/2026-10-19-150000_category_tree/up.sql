@@ -0,0 +1,4 @@
+ALTER TABLE categories
+    ADD COLUMN parent_id uuid REFERENCES categories (id) ON UPDATE RESTRICT ON DELETE RESTRICT;
+
+CREATE INDEX categories_parent_id_idx ON categories (parent_id);
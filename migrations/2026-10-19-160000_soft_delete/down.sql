@@ -0,0 +1,3 @@
+ALTER TABLE manufacturers DROP COLUMN deleted_at;
+ALTER TABLE categories DROP COLUMN deleted_at;
+ALTER TABLE products DROP COLUMN deleted_at;
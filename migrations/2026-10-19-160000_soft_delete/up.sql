@@ -0,0 +1,7 @@
+ALTER TABLE products ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE categories ADD COLUMN deleted_at TIMESTAMP;
+ALTER TABLE manufacturers ADD COLUMN deleted_at TIMESTAMP;
+
+CREATE INDEX products_deleted_at_idx ON products (deleted_at);
+CREATE INDEX categories_deleted_at_idx ON categories (deleted_at);
+CREATE INDEX manufacturers_deleted_at_idx ON manufacturers (deleted_at);
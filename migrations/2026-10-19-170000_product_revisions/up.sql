@@ -0,0 +1,10 @@
+CREATE TABLE product_revisions (
+    product_id uuid NOT NULL REFERENCES products (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    revision integer NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_issuer text NOT NULL,
+    user_subject text NOT NULL,
+    user_name text,
+    data jsonb NOT NULL,
+    PRIMARY KEY (product_id, revision)
+);
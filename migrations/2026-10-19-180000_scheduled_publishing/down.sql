@@ -0,0 +1,6 @@
+ALTER TABLE categories
+    DROP COLUMN publish_at,
+    DROP COLUMN unpublish_at;
+ALTER TABLE products
+    DROP COLUMN publish_at,
+    DROP COLUMN unpublish_at;
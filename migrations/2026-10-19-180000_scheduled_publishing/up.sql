@@ -0,0 +1,9 @@
+ALTER TABLE products
+    ADD COLUMN publish_at TIMESTAMP,
+    ADD COLUMN unpublish_at TIMESTAMP;
+ALTER TABLE categories
+    ADD COLUMN publish_at TIMESTAMP,
+    ADD COLUMN unpublish_at TIMESTAMP;
+
+CREATE INDEX products_publish_at_idx ON products (publish_at);
+CREATE INDEX products_unpublish_at_idx ON products (unpublish_at);
@@ -0,0 +1,6 @@
+DROP TABLE promotions;
+
+ALTER TABLE products
+    DROP COLUMN sale_price,
+    DROP COLUMN sale_starts_at,
+    DROP COLUMN sale_ends_at;
@@ -0,0 +1,20 @@
+ALTER TABLE products
+    ADD COLUMN sale_price integer,
+    ADD COLUMN sale_starts_at TIMESTAMP,
+    ADD COLUMN sale_ends_at TIMESTAMP;
+
+CREATE TABLE promotions (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    version integer NOT NULL DEFAULT 1,
+    name text NOT NULL,
+    discount_type text NOT NULL,
+    discount_value integer NOT NULL,
+    category_id uuid REFERENCES categories (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+    manufacturer_id uuid REFERENCES manufacturers (id) ON UPDATE RESTRICT ON DELETE RESTRICT,
+    product_ids uuid[] NOT NULL DEFAULT '{}',
+    starts_at TIMESTAMP,
+    ends_at TIMESTAMP
+);
+SELECT diesel_manage_updated_at('promotions');
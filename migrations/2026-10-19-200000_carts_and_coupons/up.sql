@@ -0,0 +1,56 @@
+CREATE TABLE coupons (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    version integer NOT NULL DEFAULT 1,
+    code text UNIQUE NOT NULL,
+    discount_type text NOT NULL,
+    discount_value integer NOT NULL DEFAULT 0,
+    minimum_order_value integer NOT NULL DEFAULT 0,
+    category_ids uuid[] NOT NULL DEFAULT '{}',
+    manufacturer_ids uuid[] NOT NULL DEFAULT '{}',
+    usage_limit integer,
+    usage_limit_per_customer integer,
+    starts_at TIMESTAMP,
+    ends_at TIMESTAMP
+);
+SELECT diesel_manage_updated_at('coupons');
+
+CREATE TABLE carts (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    coupon_id uuid REFERENCES coupons (id) ON UPDATE RESTRICT ON DELETE SET NULL
+);
+SELECT diesel_manage_updated_at('carts');
+
+CREATE TABLE cart_items (
+    cart_id uuid NOT NULL REFERENCES carts (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    product_id uuid NOT NULL REFERENCES products (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    quantity integer NOT NULL,
+    PRIMARY KEY (cart_id, product_id)
+);
+
+CREATE TABLE orders (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    customer_email text NOT NULL,
+    coupon_id uuid REFERENCES coupons (id) ON UPDATE RESTRICT ON DELETE SET NULL,
+    coupon_code text,
+    subtotal integer NOT NULL,
+    discount integer NOT NULL,
+    shipping integer NOT NULL,
+    total integer NOT NULL
+);
+CREATE INDEX orders_coupon_id ON orders (coupon_id);
+
+CREATE TABLE order_items (
+    order_id uuid NOT NULL REFERENCES orders (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    line integer NOT NULL,
+    product_id uuid REFERENCES products (id) ON UPDATE RESTRICT ON DELETE SET NULL,
+    name text NOT NULL,
+    quantity integer NOT NULL,
+    unit_price integer NOT NULL,
+    total integer NOT NULL,
+    PRIMARY KEY (order_id, line)
+);
@@ -0,0 +1,13 @@
+DROP TABLE order_taxes;
+
+ALTER TABLE order_items
+    DROP COLUMN tax_rate,
+    DROP COLUMN net,
+    DROP COLUMN tax;
+
+ALTER TABLE orders
+    DROP COLUMN prices_include_tax,
+    DROP COLUMN net,
+    DROP COLUMN tax;
+
+ALTER TABLE products DROP COLUMN tax_rate;
@@ -0,0 +1,25 @@
+ALTER TABLE products ADD COLUMN tax_rate smallint NOT NULL DEFAULT 21;
+
+-- Orders placed before taxes were tracked have prices including 21% tax
+ALTER TABLE orders
+    ADD COLUMN prices_include_tax boolean NOT NULL DEFAULT true,
+    ADD COLUMN net integer NOT NULL DEFAULT 0,
+    ADD COLUMN tax integer NOT NULL DEFAULT 0;
+UPDATE orders SET tax = total - (total * 100 + 60) / 121, net = (total * 100 + 60) / 121;
+
+ALTER TABLE order_items
+    ADD COLUMN tax_rate smallint NOT NULL DEFAULT 21,
+    ADD COLUMN net integer NOT NULL DEFAULT 0,
+    ADD COLUMN tax integer NOT NULL DEFAULT 0;
+UPDATE order_items SET tax = total - (total * 100 + 60) / 121, net = (total * 100 + 60) / 121;
+
+CREATE TABLE order_taxes (
+    order_id uuid NOT NULL REFERENCES orders (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    rate smallint NOT NULL,
+    net integer NOT NULL,
+    tax integer NOT NULL,
+    gross integer NOT NULL,
+    PRIMARY KEY (order_id, rate)
+);
+INSERT INTO order_taxes (order_id, rate, net, tax, gross)
+    SELECT id, 21, net, tax, total FROM orders;
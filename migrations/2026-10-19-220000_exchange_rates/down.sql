@@ -0,0 +1,3 @@
+ALTER TABLE orders DROP COLUMN currency;
+
+DROP TABLE exchange_rates;
@@ -0,0 +1,16 @@
+CREATE TABLE exchange_rates (
+    id uuid PRIMARY KEY DEFAULT gen_random_uuid(),
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    currency text UNIQUE NOT NULL,
+    rate double precision NOT NULL,
+    decimals smallint NOT NULL DEFAULT 2,
+    rounding text NOT NULL DEFAULT 'NEAREST',
+    rounding_increment integer NOT NULL DEFAULT 1
+);
+SELECT diesel_manage_updated_at('exchange_rates');
+
+-- Orders are settled in the base currency at the moment of the order. Existing
+-- orders were placed in euro, new orders are given the configured currency.
+ALTER TABLE orders ADD COLUMN currency text NOT NULL DEFAULT 'EUR';
+ALTER TABLE orders ALTER COLUMN currency DROP DEFAULT;
@@ -0,0 +1,28 @@
+-- Content in other locales than the default locale, which is stored in the
+-- entities themselves. Fields which are null fall back to another locale.
+CREATE TABLE product_translations (
+    product_id uuid NOT NULL REFERENCES products (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    locale text NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    name text,
+    slug text,
+    description_short text,
+    description_long text,
+    PRIMARY KEY (product_id, locale),
+    UNIQUE (locale, slug)
+);
+SELECT diesel_manage_updated_at('product_translations');
+
+CREATE TABLE category_translations (
+    category_id uuid NOT NULL REFERENCES categories (id) ON UPDATE RESTRICT ON DELETE CASCADE,
+    locale text NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    name text,
+    slug text,
+    description text,
+    PRIMARY KEY (category_id, locale),
+    UNIQUE (locale, slug)
+);
+SELECT diesel_manage_updated_at('category_translations');
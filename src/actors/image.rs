@@ -1,12 +1,16 @@
+use std::collections::HashMap;
 use std::fs;
 use std::path::PathBuf;
 
 use actix::{Actor, Handler, Message, SyncContext};
 use failure::{format_err, Error};
 use image::imageops::FilterType;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 const SAMPLING_FILTER: FilterType = FilterType::Lanczos3;
+const IMAGE_EXTENSION: &str = "png";
+const HASH_LENGTH: usize = 16;
 const THUMBNAIL_SPECS: [ThumbnailSpec; 4] = thumbnail_specs();
 const fn thumbnail_specs() -> [ThumbnailSpec; 4] {
     [
@@ -54,9 +58,105 @@ impl ImageActor {
         Self { path: images_path }
     }
 
-    /// Generates an image path based on an image ID
-    pub fn get_image_path(&self, id: Uuid) -> PathBuf {
-        self.path.join(id.to_string()).with_extension("png")
+    /// Generates an image path based on an image name
+    pub fn get_image_path(&self, name: &ImageName) -> PathBuf {
+        self.path.join(name.file_name())
+    }
+
+    /// Deletes the image and thumbnails of the owner, except the ones with the provided hash
+    fn delete_images(&self, id: Uuid, keep_hash: Option<&str>) -> Result<(), Error> {
+        // Get directory entries
+        let entries = fs::read_dir(&self.path).map_err(|e| {
+            log::warn!("Failed to read images directory to delete image: {}", e);
+            Error::from(e)
+        })?;
+
+        // Find and delete image and thumbnails
+        for entry in entries {
+            // Skip entry on error
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    log::info!("Failed to read images directory entry: {}", e);
+                    continue;
+                }
+            };
+
+            // Check if related
+            let name = ImageName::parse(&entry.file_name().to_string_lossy());
+            let is_related = match name {
+                Some(name) => {
+                    name.id == id && (keep_hash.is_none() || name.hash.as_deref() != keep_hash)
+                }
+                None => false,
+            };
+
+            // Delete file if image or thumbnail
+            if is_related {
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    log::warn!("Failed to delete image at {:?}: {}", entry.path(), e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of an image or thumbnail file, e.g. "<id>-<hash>-400-400-fit.png".
+/// The hash is derived from the content of the uploaded image, which allows
+/// clients to cache the files indefinitely. Images uploaded before hashes were
+/// introduced don't have a hash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageName {
+    pub id: Uuid,
+    pub hash: Option<String>,
+    pub thumbnail: Option<String>,
+}
+
+impl ImageName {
+    pub fn parse(file_name: &str) -> Option<Self> {
+        let stem = file_name.strip_suffix(&format!(".{}", IMAGE_EXTENSION))?;
+        let id = Uuid::parse_str(stem.get(..36)?).ok()?;
+        let mut rest = stem.get(36..)?;
+
+        // Parse hash
+        let hash = match rest.get(1..=HASH_LENGTH) {
+            Some(hash)
+                if hash.chars().all(|c| c.is_ascii_hexdigit())
+                    && matches!(rest.as_bytes().get(HASH_LENGTH + 1), None | Some(b'-')) =>
+            {
+                rest = &rest[HASH_LENGTH + 1..];
+                Some(hash.to_string())
+            }
+            _ => None,
+        };
+
+        // Parse thumbnail
+        let thumbnail = match rest {
+            "" => None,
+            _ => Some(rest.strip_prefix('-')?.to_string()),
+        };
+        Some(Self {
+            id,
+            hash,
+            thumbnail,
+        })
+    }
+
+    pub fn file_name(&self) -> String {
+        let mut name = self.id.to_string();
+        for part in self.hash.iter().chain(self.thumbnail.iter()) {
+            name.push('-');
+            name.push_str(part);
+        }
+        format!("{}.{}", name, IMAGE_EXTENSION)
+    }
+
+    fn with_thumbnail(&self, thumbnail: String) -> Self {
+        Self {
+            thumbnail: Some(thumbnail),
+            ..self.clone()
+        }
     }
 }
 
@@ -74,18 +174,84 @@ impl Handler<UploadImage> for ImageActor {
 
     fn handle(&mut self, msg: UploadImage, context: &mut Self::Context) -> Self::Result {
         // Load image and write as png
-        let path = self.get_image_path(msg.id).to_string_lossy().to_string();
+        let hash = format!("{:x}", Sha256::digest(&msg.data))[..HASH_LENGTH].to_string();
+        let name = ImageName {
+            id: msg.id,
+            hash: Some(hash.clone()),
+            thumbnail: None,
+        };
+        let path = self.get_image_path(&name);
         let image = image::load_from_memory(&msg.data).map_err(Error::from)?;
         image.save(path).map_err(Error::from)?;
 
+        // Delete previous versions
+        self.delete_images(msg.id, Some(&hash))?;
+
         // Request thumbnail creation
         context
             .address()
-            .try_send(GenerateThumbnails { id: msg.id })
+            .try_send(GenerateThumbnails { name })
             .map_err(|_| format_err!("Failed to request thumbnail generation for {}", msg.id))
     }
 }
 
+/// Finds the hash of the current image of the owner
+pub struct GetImageHash {
+    pub id: Uuid,
+}
+
+impl Message for GetImageHash {
+    type Result = Result<Option<String>, Error>;
+}
+
+impl Handler<GetImageHash> for ImageActor {
+    type Result = Result<Option<String>, Error>;
+
+    fn handle(&mut self, msg: GetImageHash, _: &mut Self::Context) -> Self::Result {
+        for entry in fs::read_dir(&self.path)? {
+            let name = ImageName::parse(&entry?.file_name().to_string_lossy());
+            if let Some(ImageName {
+                id,
+                hash: Some(hash),
+                thumbnail: None,
+            }) = name
+            {
+                if id == msg.id {
+                    return Ok(Some(hash));
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Finds the hashes of the current images of all owners
+pub struct ListImageHashes {}
+
+impl Message for ListImageHashes {
+    type Result = Result<HashMap<Uuid, String>, Error>;
+}
+
+impl Handler<ListImageHashes> for ImageActor {
+    type Result = Result<HashMap<Uuid, String>, Error>;
+
+    fn handle(&mut self, _msg: ListImageHashes, _: &mut Self::Context) -> Self::Result {
+        let mut hashes = HashMap::new();
+        for entry in fs::read_dir(&self.path)? {
+            let name = ImageName::parse(&entry?.file_name().to_string_lossy());
+            if let Some(ImageName {
+                id,
+                hash: Some(hash),
+                thumbnail: None,
+            }) = name
+            {
+                hashes.insert(id, hash);
+            }
+        }
+        Ok(hashes)
+    }
+}
+
 struct ThumbnailSpec {
     /// Max width of thumbnail
     pub width: u32,
@@ -97,8 +263,16 @@ struct ThumbnailSpec {
     pub fill: bool,
 }
 
+impl ThumbnailSpec {
+    /// Suffix of the thumbnail in the file name, e.g. "400-400-fit"
+    fn name(&self) -> String {
+        let fill_fit = if self.fill { "fill" } else { "fit" };
+        format!("{}-{}-{}", self.width, self.height, fill_fit)
+    }
+}
+
 pub struct GenerateThumbnails {
-    pub id: Uuid,
+    pub name: ImageName,
 }
 
 impl Message for GenerateThumbnails {
@@ -109,12 +283,12 @@ impl Handler<GenerateThumbnails> for ImageActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: GenerateThumbnails, _: &mut Self::Context) -> Self::Result {
-        let image_path = self.get_image_path(msg.id);
+        let image_path = self.get_image_path(&msg.name);
         for spec in &THUMBNAIL_SPECS {
             // Load image from file
             let img = image::open(&image_path).map_err(|e| {
                 log::error!("Failed to generate thumbnail for {:?}: {}", image_path, e);
-                return Error::from(e);
+                Error::from(e)
             })?;
 
             // Generate thumbnail
@@ -125,16 +299,7 @@ impl Handler<GenerateThumbnails> for ImageActor {
             };
 
             // Build filename
-            let fill_fit = if spec.fill { "fill" } else { "fit" };
-            let thumbnail_name = format!(
-                "{}-{}-{}-{}.{}",
-                msg.id,
-                spec.width,
-                spec.height,
-                fill_fit,
-                image_path.extension().unwrap().to_string_lossy(),
-            );
-            let thumbnail_path = image_path.with_file_name(thumbnail_name);
+            let thumbnail_path = self.get_image_path(&msg.name.with_thumbnail(spec.name()));
 
             // Write thumbnail as png
             thumbnail.save(&thumbnail_path).map_err(|e| {
@@ -163,37 +328,38 @@ impl Handler<DeleteImage> for ImageActor {
     type Result = Result<(), Error>;
 
     fn handle(&mut self, msg: DeleteImage, _: &mut Self::Context) -> Self::Result {
-        // Get directory entries
-        let entries = fs::read_dir(&self.path).map_err(|e| {
-            log::warn!("Failed to read images directory to delete image: {}", e);
-            Error::from(e)
-        })?;
-
-        // Find and delete image and thumbnails
-        for entry in entries {
-            // Skip entry on error
-            if entry.is_err() {
-                log::info!(
-                    "Failed to read images directory entry: {}",
-                    entry.unwrap_err()
-                );
-                continue;
-            }
+        self.delete_images(msg.id, None)
+    }
+}
 
-            // Check if related
-            let entry = entry.unwrap();
-            let is_related = entry
-                .file_name()
-                .to_string_lossy()
-                .starts_with(&msg.id.to_string());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            // Delete file if image or thumbnail
-            if is_related {
-                if let Err(e) = fs::remove_file(entry.path()) {
-                    log::warn!("Failed to delete image at {:?}: {}", entry.path(), e);
-                }
-            }
+    #[test]
+    fn test_image_name() {
+        let id = "6f2a1d3c-4b5e-4f60-8a7b-9c0d1e2f3a4b";
+        let cases = vec![
+            (format!("{}.png", id), None, None),
+            (format!("{}-400-400-fit.png", id), None, Some("400-400-fit")),
+            (
+                format!("{}-0123456789abcdef.png", id),
+                Some("0123456789abcdef"),
+                None,
+            ),
+            (
+                format!("{}-0123456789abcdef-150-150-fit.png", id),
+                Some("0123456789abcdef"),
+                Some("150-150-fit"),
+            ),
+        ];
+        for (file_name, hash, thumbnail) in cases {
+            let name = ImageName::parse(&file_name).unwrap();
+            assert_eq!(name.id.to_string(), id);
+            assert_eq!(name.hash.as_deref(), hash);
+            assert_eq!(name.thumbnail.as_deref(), thumbnail);
+            assert_eq!(name.file_name(), file_name);
         }
-        Ok(())
+        assert_eq!(ImageName::parse("index.html"), None);
     }
 }
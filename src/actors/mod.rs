@@ -1,3 +1,7 @@
 mod image;
+mod purge;
+mod scheduler;
 
 pub use self::image::*;
+pub use self::purge::*;
+pub use self::scheduler::*;
@@ -0,0 +1,82 @@
+use std::time::Duration;
+
+use actix::{Actor, Addr, AsyncContext, Context};
+use chrono::Utc;
+
+use super::{DeleteImage, ImageActor};
+use crate::db::carts::PurgeCarts;
+use crate::db::trash::PurgeTrash;
+use crate::db::DbActor;
+
+/// Periodically deletes the entities which are in the trash for longer than
+/// the retention, together with their images, and the carts which weren't
+/// changed for longer than the cart retention
+pub struct PurgeActor {
+    db: Addr<DbActor>,
+    image: Addr<ImageActor>,
+    retention: chrono::Duration,
+    cart_retention: chrono::Duration,
+    interval: Duration,
+}
+
+impl PurgeActor {
+    pub fn new(
+        db: Addr<DbActor>,
+        image: Addr<ImageActor>,
+        retention: Duration,
+        cart_retention: Duration,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            db,
+            image,
+            retention: chrono::Duration::from_std(retention)
+                .expect("Trash retention is out of range"),
+            cart_retention: chrono::Duration::from_std(cart_retention)
+                .expect("Cart retention is out of range"),
+            interval,
+        }
+    }
+
+    fn purge(&self) {
+        let db = self.db.clone();
+        let image = self.image.clone();
+        let now = Utc::now().naive_utc();
+        let before = now - self.retention;
+        let carts_before = now - self.cart_retention;
+        actix::spawn(async move {
+            match db
+                .send(PurgeCarts {
+                    before: carts_before,
+                })
+                .await
+            {
+                Ok(Ok(0)) => (),
+                Ok(Ok(purged)) => log::info!("Purged {} abandoned carts", purged),
+                Ok(Err(e)) => log::warn!("Failed to purge carts: {}", e),
+                Err(e) => return log::warn!("Failed to contact DbActor: {}", e),
+            }
+
+            let purged = match db.send(PurgeTrash { before }).await {
+                Ok(Ok(purged)) => purged,
+                Ok(Err(e)) => return log::warn!("Failed to purge trash: {}", e),
+                Err(e) => return log::warn!("Failed to contact DbActor: {}", e),
+            };
+            if !purged.is_empty() {
+                log::info!("Purged {} entities from the trash", purged.len());
+            }
+            for id in purged {
+                image.do_send(DeleteImage { id });
+            }
+        });
+    }
+}
+
+impl Actor for PurgeActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.purge();
+        ctx.run_interval(self.interval, |actor, _| actor.purge());
+    }
+}
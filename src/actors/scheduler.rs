@@ -0,0 +1,41 @@
+use std::time::Duration;
+
+use actix::{Actor, Addr, AsyncContext, Context};
+use chrono::Utc;
+
+use crate::db::schedule::ApplySchedule;
+use crate::db::DbActor;
+
+/// Periodically applies the scheduled publishing of products
+pub struct SchedulerActor {
+    db: Addr<DbActor>,
+    interval: Duration,
+}
+
+impl SchedulerActor {
+    pub fn new(db: Addr<DbActor>, interval: Duration) -> Self {
+        Self { db, interval }
+    }
+
+    fn apply(&self) {
+        let db = self.db.clone();
+        let now = Utc::now().naive_utc();
+        actix::spawn(async move {
+            match db.send(ApplySchedule { now }).await {
+                Ok(Ok(0)) => (),
+                Ok(Ok(changed)) => log::info!("Applied schedule of {} products", changed),
+                Ok(Err(e)) => log::warn!("Failed to apply schedule: {}", e),
+                Err(e) => log::warn!("Failed to contact DbActor: {}", e),
+            }
+        });
+    }
+}
+
+impl Actor for SchedulerActor {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        self.apply();
+        ctx.run_interval(self.interval, |actor, _| actor.apply());
+    }
+}
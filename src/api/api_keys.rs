@@ -0,0 +1,76 @@
+use actix_web::{delete, get, post, web, Error, HttpResponse, Scope};
+
+use crate::auth::{generate_api_key, Principal};
+use crate::db::api_keys::*;
+use crate::models;
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(list_api_keys)
+        .service(add_api_key)
+        .service(revoke_api_key)
+}
+
+/// List all API keys
+#[get("")]
+async fn list_api_keys(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let api_keys = ctx
+        .db
+        .send(ListApiKeys {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch API keys");
+    Ok(HttpResponse::Ok().json(api_keys))
+}
+
+/// Create new API key from form. The key itself is only returned once.
+/// Callers can't grant scopes they don't hold themselves.
+#[post("")]
+async fn add_api_key(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    form: web::Json<models::ApiKeyData>,
+) -> Result<HttpResponse, Error> {
+    let mut data = form.into_inner();
+    principal.check_scopes(&data.scopes)?;
+    let (key, prefix, key_hash) = generate_api_key();
+    data.prefix = prefix;
+    data.key_hash = key_hash;
+
+    let msg = InsertApiKey {
+        author: principal.into_inner(),
+        data,
+    };
+    let api_key = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to insert API key");
+
+    Ok(HttpResponse::Ok().json(models::ApiKeyWithSecret { api_key, key }))
+}
+
+/// Revoke API key with ID
+#[delete("/{api_key_id}")]
+async fn revoke_api_key(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    api_key_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let api_key_id = api_key_id.into_inner();
+    let msg = RevokeApiKey {
+        author: principal.into_inner(),
+        id: api_key_id,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    if result.is_ok() {
+        Ok(HttpResponse::Ok().finish())
+    } else {
+        let res = HttpResponse::NotFound()
+            .body(format!("No active API key found with id: {}", api_key_id));
+        Ok(res)
+    }
+}
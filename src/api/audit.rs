@@ -0,0 +1,29 @@
+use actix_web::{get, web, Error, HttpResponse, Scope};
+use validator::Validate;
+
+use crate::db::audit::*;
+use crate::models::{self, ValidationErrors};
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path).service(list_audit_entries)
+}
+
+/// List audit entries, newest first
+#[get("")]
+async fn list_audit_entries(
+    ctx: web::Data<Context>,
+    filter: web::Query<models::AuditFilter>,
+) -> Result<HttpResponse, Error> {
+    filter.validate().map_err(ValidationErrors::from)?;
+    let msg = ListAuditEntries {
+        filter: filter.into_inner(),
+    };
+    let entries = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch audit entries");
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -0,0 +1,145 @@
+use actix_web::{delete, get, post, put, web, Error, HttpResponse, Scope};
+use diesel::result::Error::NotFound;
+use serde::Serialize;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::api::typed_error_response;
+use crate::db::carts::*;
+use crate::models::{self, ValidationErrors};
+use crate::Context;
+
+pub fn public_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(create_cart)
+        .service(get_cart)
+        .service(set_cart_item)
+        .service(remove_cart_item)
+        .service(apply_coupon)
+        .service(remove_coupon)
+        .service(checkout)
+}
+
+/// Create an empty cart. Its ID is needed for all other cart requests.
+#[post("")]
+async fn create_cart(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let msg = CreateCart {
+        shipping_cost: ctx.config.shipping_cost,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, Uuid::nil()))
+}
+
+/// Find cart by ID
+#[get("/{cart_id}")]
+async fn get_cart(
+    ctx: web::Data<Context>,
+    cart_id: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let cart_id = cart_id.into_inner();
+    let msg = GetCart {
+        id: cart_id,
+        shipping_cost: ctx.config.shipping_cost,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, cart_id))
+}
+
+/// Add product to cart or change its quantity
+#[put("/{cart_id}/items/{product_id}")]
+async fn set_cart_item(
+    ctx: web::Data<Context>,
+    path: web::Path<(Uuid, Uuid)>,
+    form: web::Json<models::CartItemData>,
+) -> Result<HttpResponse, Error> {
+    let (cart_id, product_id) = path.into_inner();
+    form.validate().map_err(ValidationErrors::from)?;
+    let msg = SetCartItem {
+        cart_id,
+        product_id,
+        quantity: form.quantity,
+        shipping_cost: ctx.config.shipping_cost,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, cart_id))
+}
+
+/// Remove product from cart
+#[delete("/{cart_id}/items/{product_id}")]
+async fn remove_cart_item(
+    ctx: web::Data<Context>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, Error> {
+    let (cart_id, product_id) = path.into_inner();
+    let msg = RemoveCartItem {
+        cart_id,
+        product_id,
+        shipping_cost: ctx.config.shipping_cost,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, cart_id))
+}
+
+/// Apply coupon code to cart. The reason of a rejection is returned as
+/// validation error of the code.
+#[put("/{cart_id}/coupon")]
+async fn apply_coupon(
+    ctx: web::Data<Context>,
+    cart_id: web::Path<Uuid>,
+    form: web::Json<models::CouponCode>,
+) -> Result<HttpResponse, Error> {
+    let cart_id = cart_id.into_inner();
+    let msg = ApplyCoupon {
+        cart_id,
+        code: form.into_inner().code,
+        shipping_cost: ctx.config.shipping_cost,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, cart_id))
+}
+
+/// Remove coupon from cart
+#[delete("/{cart_id}/coupon")]
+async fn remove_coupon(
+    ctx: web::Data<Context>,
+    cart_id: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let cart_id = cart_id.into_inner();
+    let msg = RemoveCoupon {
+        cart_id,
+        shipping_cost: ctx.config.shipping_cost,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, cart_id))
+}
+
+/// Place order for the products in the cart. The cart is deleted afterwards.
+#[post("/{cart_id}/checkout")]
+async fn checkout(
+    ctx: web::Data<Context>,
+    cart_id: web::Path<Uuid>,
+    form: web::Json<models::CheckoutData>,
+) -> Result<HttpResponse, Error> {
+    let cart_id = cart_id.into_inner();
+    let form = form.into_inner();
+    form.validate().map_err(ValidationErrors::from)?;
+    let msg = Checkout {
+        cart_id,
+        customer_email: form.customer_email,
+        shipping_cost: ctx.config.shipping_cost,
+        currency: ctx.config.currency.clone(),
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    Ok(cart_response(result, cart_id))
+}
+
+fn cart_response<T: Serialize>(result: Result<T, failure::Error>, cart_id: Uuid) -> HttpResponse {
+    match result {
+        Ok(value) => HttpResponse::Ok().json(value),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            HttpResponse::NotFound().body(format!("No cart found with id: {}", cart_id))
+        }
+        Err(e) => typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
@@ -1,46 +1,89 @@
-use actix_web::{delete, get, post, put, web, Error, HttpResponse, Scope};
+use actix_web::{
+    delete, get, http::header, patch, post, put, web, Error, HttpRequest, HttpResponse, Scope,
+};
+use diesel::result::Error::NotFound;
+use validator::Validate;
 
-use crate::actors::DeleteImage;
+use crate::api::exchange_rates::{convert_prices, requested_exchange_rate};
+use crate::api::products::VARY;
+use crate::api::translations::{
+    requested_locales, translate_categories, translate_products, tree_categories,
+};
+use crate::api::typed_error_response;
+use crate::auth::Principal;
 use crate::db::categories::*;
-use crate::models;
+use crate::http_cache::{http_date, if_match_version, versioned_json};
+use crate::models::{self, CurrencyQuery, LocaleQuery, ValidationErrors};
 use crate::Context;
 
 pub fn public_scope(path: &str) -> Scope {
     web::scope(path)
         .service(list_categories)
+        .service(get_category_tree)
         .service(get_category)
+        .service(get_category_by_slug)
+        .service(get_category_subtree)
+        .service(get_breadcrumbs)
+        .service(list_category_products)
 }
 
 pub fn admin_scope(path: &str) -> Scope {
     web::scope(path)
         .service(list_categories)
+        .service(get_category_tree)
         .service(get_category)
+        .service(get_category_by_slug)
+        .service(get_category_subtree)
+        .service(get_breadcrumbs)
+        .service(list_category_products)
         .service(add_category)
+        .service(reorder_categories)
         .service(update_category)
+        .service(patch_category)
         .service(delete_category)
+        .service(restore_category)
 }
 
-/// List all categories
+/// List all categories. Visitors only see published categories. Content is
+/// translated to the locale requested with `lang` or `Accept-Language`.
 #[get("")]
-async fn list_categories(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
-    let categories = ctx
+async fn list_categories(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+) -> Result<HttpResponse, Error> {
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let msg = ListCategories {
+        published_only: principal.is_none(),
+    };
+    let mut categories = ctx
         .db
-        .send(ListCategories {})
+        .send(msg)
         .await
         .expect("Failed to contact DbActor")
         .expect("Failed to fetch category");
-    Ok(HttpResponse::Ok().json(categories))
+    translate_categories(&ctx, categories.iter_mut().collect(), &locales).await?;
+    Ok(HttpResponse::Ok()
+        .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+        .header(header::VARY, header::ACCEPT_LANGUAGE.as_str())
+        .json(categories))
 }
 
 /// Find category by ID
 #[get("/{category_id}")]
 async fn get_category(
     ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
     category_id: web::Path<uuid::Uuid>,
 ) -> Result<HttpResponse, Error> {
     let category_id = category_id.into_inner();
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
     let msg = GetCategory {
-        id: category_id.clone(),
+        id: category_id,
+        published_only: principal.is_none(),
     };
     let category = ctx
         .db
@@ -48,8 +91,13 @@ async fn get_category(
         .await
         .expect("Failed to contact DbActor")
         .expect("Failed to fetch category");
-    if let Some(category) = category {
-        Ok(HttpResponse::Ok().json(category))
+    if let Some(mut category) = category {
+        translate_categories(&ctx, vec![&mut category], &locales).await?;
+        let mut res = HttpResponse::Ok();
+        res.header(header::LAST_MODIFIED, http_date(category.updated_at))
+            .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+            .header(header::VARY, header::ACCEPT_LANGUAGE.as_str());
+        Ok(versioned_json(&mut res, category.version, &category))
     } else {
         let res =
             HttpResponse::NotFound().body(format!("No category found with id: {}", category_id));
@@ -57,70 +105,378 @@ async fn get_category(
     }
 }
 
+/// Find category by slug. Its visible products are embedded if the query
+/// contains `products=true`, paginated by `limit` and `offset`, with prices
+/// in the currency requested with `currency` or `Accept-Currency`. The
+/// current slugs of all locales are served.
+#[get("/slug/{category_slug}")]
+async fn get_category_by_slug(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+    currency: web::Query<CurrencyQuery>,
+    category_slug: web::Path<String>,
+    query: web::Query<models::EmbedProductsQuery>,
+) -> Result<HttpResponse, Error> {
+    let category_slug = category_slug.into_inner();
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let rate = if query.products {
+        requested_exchange_rate(&ctx, &req, &currency).await?
+    } else {
+        None
+    };
+    let msg = GetCategoryBySlug {
+        slug: category_slug.clone(),
+        products: query.page(),
+        descendants: query.descendants,
+        published_only: principal.is_none(),
+    };
+    let mut category = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch category by slug");
+    if let Some(category) = category.as_mut() {
+        translate_categories(&ctx, vec![&mut category.entity], &locales).await?;
+        if let Some(products) = category.products.as_mut() {
+            translate_products(&ctx, &mut products.items, &locales).await?;
+            convert_prices(&mut products.items, rate.as_ref());
+        }
+    }
+    match category {
+        Some(category) => {
+            let mut res = HttpResponse::Ok();
+            res.header(header::CONTENT_LANGUAGE, locales[0].as_str());
+            if category.products.is_none() {
+                res.header(header::LAST_MODIFIED, http_date(category.entity.updated_at))
+                    .header(header::VARY, header::ACCEPT_LANGUAGE.as_str());
+            } else {
+                res.header(header::VARY, VARY);
+            }
+            Ok(versioned_json(&mut res, category.entity.version, &category))
+        }
+        None => {
+            let res = HttpResponse::NotFound()
+                .body(format!("No category found with slug: {}", category_slug));
+            Ok(res)
+        }
+    }
+}
+
+/// Get all categories as a tree. Subcategories are sorted by sort order and name.
+#[get("/tree")]
+async fn get_category_tree(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+) -> Result<HttpResponse, Error> {
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let msg = GetCategoryTree {
+        root: None,
+        published_only: principal.is_none(),
+    };
+    let mut tree = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch category tree")
+        .unwrap_or_default();
+    translate_categories(&ctx, tree_categories(&mut tree), &locales).await?;
+    Ok(HttpResponse::Ok()
+        .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+        .header(header::VARY, header::ACCEPT_LANGUAGE.as_str())
+        .json(tree))
+}
+
+/// Get the category with all categories below it
+#[get("/{category_id}/tree")]
+async fn get_category_subtree(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+    category_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let category_id = category_id.into_inner();
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let msg = GetCategoryTree {
+        root: Some(category_id),
+        published_only: principal.is_none(),
+    };
+    let tree = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch category tree");
+    match tree.and_then(|mut nodes| nodes.pop()) {
+        Some(mut node) => {
+            translate_categories(
+                &ctx,
+                tree_categories(std::slice::from_mut(&mut node)),
+                &locales,
+            )
+            .await?;
+            Ok(HttpResponse::Ok()
+                .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+                .header(header::VARY, header::ACCEPT_LANGUAGE.as_str())
+                .json(node))
+        }
+        None => Ok(
+            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id))
+        ),
+    }
+}
+
+/// Get the categories from the top level down to the category
+#[get("/{category_id}/breadcrumbs")]
+async fn get_breadcrumbs(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+    category_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let category_id = category_id.into_inner();
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let msg = GetBreadcrumbs {
+        id: category_id,
+        published_only: principal.is_none(),
+    };
+    let breadcrumbs = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch breadcrumbs");
+    match breadcrumbs {
+        Some(mut breadcrumbs) => {
+            translate_categories(&ctx, breadcrumbs.iter_mut().collect(), &locales).await?;
+            Ok(HttpResponse::Ok()
+                .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+                .header(header::VARY, header::ACCEPT_LANGUAGE.as_str())
+                .json(breadcrumbs))
+        }
+        None => Ok(
+            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id))
+        ),
+    }
+}
+
+/// List the visible products of the category, optionally including the
+/// products of its subcategories. Prices are converted to the currency
+/// requested with `currency` or `Accept-Currency`.
+#[get("/{category_id}/products")]
+async fn list_category_products(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+    currency: web::Query<CurrencyQuery>,
+    category_id: web::Path<uuid::Uuid>,
+    query: web::Query<models::CategoryProductsQuery>,
+) -> Result<HttpResponse, Error> {
+    let category_id = category_id.into_inner();
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let rate = requested_exchange_rate(&ctx, &req, &currency).await?;
+    let msg = ListCategoryProducts {
+        id: category_id,
+        page: models::PageRequest::new(query.limit, query.offset),
+        descendants: query.descendants,
+        published_only: principal.is_none(),
+    };
+    let products = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch products of category");
+    match products {
+        Some(mut products) => {
+            translate_products(&ctx, &mut products.items, &locales).await?;
+            convert_prices(&mut products.items, rate.as_ref());
+            Ok(HttpResponse::Ok()
+                .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+                .header(header::VARY, VARY)
+                .json(products))
+        }
+        None => Ok(
+            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id))
+        ),
+    }
+}
+
+/// Change the order of all subcategories of a parent
+#[post("/reorder")]
+async fn reorder_categories(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    form: web::Json<models::CategoryOrder>,
+) -> Result<HttpResponse, Error> {
+    let msg = ReorderCategories {
+        author: principal.into_inner(),
+        order: form.into_inner(),
+    };
+    let categories = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+    match categories {
+        Ok(categories) => Ok(HttpResponse::Ok().json(categories)),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
 /// Insert new category from form
 #[post("")]
 async fn add_category(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
     form: web::Json<models::CategoryData>,
 ) -> Result<HttpResponse, Error> {
+    let form = form.into_inner();
+    form.validate().map_err(ValidationErrors::from)?;
     let msg = InsertCategory {
-        data: form.into_inner(),
+        author: principal.into_inner(),
+        data: form,
     };
-    let category = ctx
-        .db
-        .send(msg)
-        .await
-        .expect("Failed to contact DbActor")
-        .expect("Failed to fetch category");
+    let category = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    Ok(HttpResponse::Ok().json(category))
+    match category {
+        Ok(category) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            category.version,
+            &category,
+        )),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
 }
 
 /// Update category from form
 #[put("/{category_id}")]
 async fn update_category(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
     category_id: web::Path<uuid::Uuid>,
-    form: web::Json<models::CategoryData>,
+    form: web::Json<models::Versioned<models::CategoryData>>,
 ) -> Result<HttpResponse, Error> {
     let category_id = category_id.into_inner();
+    let form = form.into_inner();
+    form.data.validate().map_err(ValidationErrors::from)?;
     let msg = UpdateCategory {
-        id: category_id.clone(),
-        data: form.into_inner(),
+        author: principal.into_inner(),
+        id: category_id,
+        version: if_match_version(&req).or(form.version),
+        data: form.data,
     };
     let category = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    if let Ok(category) = category {
-        Ok(HttpResponse::Ok().json(category))
-    } else {
-        let res =
-            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id));
-        Ok(res)
+    match category {
+        Ok(category) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            category.version,
+            &category,
+        )),
+        Err(e) => match typed_error_response(&e) {
+            Some(res) => Ok(res),
+            None => {
+                let res = HttpResponse::NotFound()
+                    .body(format!("No category found with id: {}", category_id));
+                Ok(res)
+            }
+        },
+    }
+}
+
+/// Update provided fields of category from JSON Merge Patch
+#[patch("/{category_id}")]
+async fn patch_category(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
+    category_id: web::Path<uuid::Uuid>,
+    patch: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    let category_id = category_id.into_inner();
+    let patch = models::CategoryPatch::from_merge_patch(&patch)?;
+    let msg = PatchCategory {
+        author: principal.into_inner(),
+        id: category_id,
+        version: if_match_version(&req).or(patch.version),
+        patch: patch.data,
+    };
+    let category = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match category {
+        Ok(category) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            category.version,
+            &category,
+        )),
+        Err(e) => match typed_error_response(&e) {
+            Some(res) => Ok(res),
+            None => {
+                let res = HttpResponse::NotFound()
+                    .body(format!("No category found with id: {}", category_id));
+                Ok(res)
+            }
+        },
     }
 }
 
-/// Delete category with ID
+/// Move category with ID to the trash
 #[delete("/{category_id}")]
 async fn delete_category(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
     category_id: web::Path<uuid::Uuid>,
+    query: web::Query<models::DeleteQuery>,
 ) -> Result<HttpResponse, Error> {
     let category_id = category_id.into_inner();
     let msg = DeleteCategory {
-        id: category_id.clone(),
+        author: principal.into_inner(),
+        id: category_id,
+        policy: query.policy()?,
     };
     let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    if result.is_ok() {
-        // Request deletion of image and thumbnails
-        let msg = DeleteImage { id: category_id };
-        ctx.image.do_send(msg);
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(
+            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id))
+        ),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
 
-        // Send success response
-        Ok(HttpResponse::Ok().finish())
-    } else {
-        let res =
-            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id));
-        Ok(res)
+/// Restore category with ID from the trash
+#[post("/{category_id}/restore")]
+async fn restore_category(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    category_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let category_id = category_id.into_inner();
+    let msg = RestoreCategory {
+        author: principal.into_inner(),
+        id: category_id,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(category) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            category.version,
+            &category,
+        )),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(HttpResponse::NotFound().body(
+            format!("No category found in the trash with id: {}", category_id),
+        )),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
     }
 }
@@ -0,0 +1,140 @@
+use actix_web::{delete, get, post, put, web, Error, HttpRequest, HttpResponse, Scope};
+use diesel::result::Error::NotFound;
+
+use crate::api::typed_error_response;
+use crate::auth::Principal;
+use crate::db::coupons::*;
+use crate::http_cache::{if_match_version, versioned_json};
+use crate::models;
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(list_coupons)
+        .service(get_coupon)
+        .service(add_coupon)
+        .service(update_coupon)
+        .service(delete_coupon)
+}
+
+/// List all coupons, including the ones which expired or didn't start yet
+#[get("")]
+async fn list_coupons(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let coupons = ctx
+        .db
+        .send(ListCoupons {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch coupons");
+    Ok(HttpResponse::Ok().json(coupons))
+}
+
+/// Find coupon by ID
+#[get("/{coupon_id}")]
+async fn get_coupon(
+    ctx: web::Data<Context>,
+    coupon_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let coupon_id = coupon_id.into_inner();
+    let coupon = ctx
+        .db
+        .send(GetCoupon { id: coupon_id })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch coupon");
+    match coupon {
+        Some(coupon) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            coupon.version,
+            &coupon,
+        )),
+        None => {
+            Ok(HttpResponse::NotFound().body(format!("No coupon found with id: {}", coupon_id)))
+        }
+    }
+}
+
+/// Insert new coupon from form
+#[post("")]
+async fn add_coupon(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    form: web::Json<models::CouponData>,
+) -> Result<HttpResponse, Error> {
+    let mut form = form.into_inner();
+    form.code = models::normalize_coupon_code(&form.code);
+    form.check()?;
+    let msg = InsertCoupon {
+        author: principal.into_inner(),
+        data: form,
+    };
+    let coupon = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match coupon {
+        Ok(coupon) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            coupon.version,
+            &coupon,
+        )),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Update coupon from form
+#[put("/{coupon_id}")]
+async fn update_coupon(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
+    coupon_id: web::Path<uuid::Uuid>,
+    form: web::Json<models::Versioned<models::CouponData>>,
+) -> Result<HttpResponse, Error> {
+    let coupon_id = coupon_id.into_inner();
+    let mut form = form.into_inner();
+    form.data.code = models::normalize_coupon_code(&form.data.code);
+    form.data.check()?;
+    let msg = UpdateCoupon {
+        author: principal.into_inner(),
+        id: coupon_id,
+        version: if_match_version(&req).or(form.version),
+        data: form.data,
+    };
+    let coupon = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match coupon {
+        Ok(coupon) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            coupon.version,
+            &coupon,
+        )),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!("No coupon found with id: {}", coupon_id)))
+        }
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Delete coupon with ID
+#[delete("/{coupon_id}")]
+async fn delete_coupon(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    coupon_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let coupon_id = coupon_id.into_inner();
+    let msg = DeleteCoupon {
+        author: principal.into_inner(),
+        id: coupon_id,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!("No coupon found with id: {}", coupon_id)))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
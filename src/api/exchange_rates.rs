@@ -0,0 +1,174 @@
+use actix_web::{delete, get, put, web, Error, HttpRequest, HttpResponse, Scope};
+use diesel::result::Error::NotFound;
+
+use crate::auth::Principal;
+use crate::db::exchange_rates::*;
+use crate::models::{self, CurrencyQuery, ExchangeRate, ProductWithMeta, ValidationErrors};
+use crate::Context;
+
+/// Header with the currency in which a visitor wants to see prices
+pub const ACCEPT_CURRENCY: &str = "Accept-Currency";
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(list_exchange_rates)
+        .service(get_exchange_rate)
+        .service(set_exchange_rate)
+        .service(delete_exchange_rate)
+}
+
+/// Returns the exchange rate of the currency requested with the `currency`
+/// query parameter or the `Accept-Currency` header. Returns `None` if no
+/// currency or the base currency is requested. A currency without exchange
+/// rate in the query parameter is rejected with a `ValidationErrors`, while
+/// the header falls back to the next supported currency, or the base currency.
+pub async fn requested_exchange_rate(
+    ctx: &Context,
+    req: &HttpRequest,
+    query: &CurrencyQuery,
+) -> Result<Option<ExchangeRate>, Error> {
+    if let Some(currency) = query.currency.as_deref() {
+        let currency = currency.trim().to_uppercase();
+        if currency.is_empty() || currency == ctx.config.currency {
+            return Ok(None);
+        }
+
+        let rate = ctx
+            .db
+            .send(GetExchangeRate {
+                currency: currency.clone(),
+            })
+            .await
+            .expect("Failed to contact DbActor")
+            .expect("Failed to fetch exchange rate");
+        return match rate {
+            Some(rate) => Ok(Some(rate)),
+            None => {
+                let mut errors = ValidationErrors::new();
+                errors.add(
+                    "currency",
+                    format!("Prices aren't available in {}", currency),
+                );
+                Err(errors.into())
+            }
+        };
+    }
+
+    let currencies = match req
+        .headers()
+        .get(ACCEPT_CURRENCY)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(header) => models::parse_accept_currency(header),
+        None => return Ok(None),
+    };
+    if currencies.is_empty() {
+        return Ok(None);
+    }
+
+    let mut rates = ctx
+        .db
+        .send(ListExchangeRates {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch exchange rates");
+    for currency in currencies {
+        if currency == ctx.config.currency {
+            return Ok(None);
+        }
+        if let Some(i) = rates.iter().position(|rate| rate.currency == currency) {
+            return Ok(Some(rates.swap_remove(i)));
+        }
+    }
+    Ok(None)
+}
+
+/// Adds the prices in the requested currency to the products
+pub fn convert_prices(products: &mut [ProductWithMeta], rate: Option<&ExchangeRate>) {
+    if let Some(rate) = rate {
+        for pricing in products.iter_mut().filter_map(|p| p.pricing.as_mut()) {
+            pricing.convert(rate);
+        }
+    }
+}
+
+/// List the exchange rates of all currencies
+#[get("")]
+async fn list_exchange_rates(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let rates = ctx
+        .db
+        .send(ListExchangeRates {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch exchange rates");
+    Ok(HttpResponse::Ok().json(rates))
+}
+
+/// Find exchange rate by currency
+#[get("/{currency}")]
+async fn get_exchange_rate(
+    ctx: web::Data<Context>,
+    currency: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let currency = currency.into_inner().to_uppercase();
+    let rate = ctx
+        .db
+        .send(GetExchangeRate {
+            currency: currency.clone(),
+        })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch exchange rate");
+    match rate {
+        Some(rate) => Ok(HttpResponse::Ok().json(rate)),
+        None => Ok(HttpResponse::NotFound()
+            .body(format!("No exchange rate found for currency: {}", currency))),
+    }
+}
+
+/// Add or replace the exchange rate of a currency
+#[put("/{currency}")]
+async fn set_exchange_rate(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    currency: web::Path<String>,
+    form: web::Json<models::ExchangeRateData>,
+) -> Result<HttpResponse, Error> {
+    let mut form = form.into_inner();
+    form.currency = currency.into_inner().to_uppercase();
+    form.check(&ctx.config.currency)?;
+    let msg = SetExchangeRates {
+        author: principal.into_inner(),
+        rates: vec![form],
+    };
+    let rate = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to save exchange rate")
+        .pop();
+    Ok(HttpResponse::Ok().json(rate))
+}
+
+/// Delete exchange rate of a currency
+#[delete("/{currency}")]
+async fn delete_exchange_rate(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    currency: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let currency = currency.into_inner().to_uppercase();
+    let msg = DeleteExchangeRate {
+        author: principal.into_inner(),
+        currency: currency.clone(),
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(HttpResponse::NotFound()
+            .body(format!("No exchange rate found for currency: {}", currency))),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
@@ -0,0 +1,155 @@
+use actix::Addr;
+use actix_files::NamedFile;
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{error, get, web, Error, HttpRequest, HttpResponse, Scope};
+use futures::stream;
+use uuid::Uuid;
+
+use crate::db::products::ExportProducts;
+use crate::db::DbActor;
+use crate::models::{ExportFormat, ExportQuery, ExportedProduct};
+use crate::spreadsheet::{write_csv, XlsxWriter};
+use crate::Context;
+
+/// Number of products fetched from the database at once
+const CHUNK_SIZE: i64 = 500;
+
+/// Columns of an export which are written as numbers in XLSX files
+const NUMERIC_COLUMNS: [usize; 3] = [5, 6, 7];
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path).service(export_products)
+}
+
+/// Export all products with the names of their manufacturer and categories.
+/// Products are fetched in chunks, so the catalogue is never loaded at once.
+#[get("/products")]
+async fn export_products(
+    ctx: web::Data<Context>,
+    req: HttpRequest,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse, Error> {
+    let format = query.format;
+    let file_name = format!("products.{}", format.extension());
+    if format == ExportFormat::Xlsx {
+        return export_xlsx(ctx.db.clone(), &req, &file_name).await;
+    }
+
+    let state = ExportState {
+        db: ctx.db.clone(),
+        format,
+        after: None,
+        first: true,
+        done: false,
+    };
+    let body = stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        let chunk = state.next_chunk().await;
+        if chunk.is_err() {
+            state.done = true;
+        }
+        Some((chunk, state))
+    });
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .set(attachment(file_name))
+        .streaming(Box::pin(body)))
+}
+
+/// Progress of a streamed CSV or JSON export
+struct ExportState {
+    db: Addr<DbActor>,
+    format: ExportFormat,
+    after: Option<Uuid>,
+    first: bool,
+    done: bool,
+}
+
+impl ExportState {
+    /// Fetches and encodes the next chunk of products
+    async fn next_chunk(&mut self) -> Result<Bytes, Error> {
+        let products = fetch_chunk(&self.db, self.after).await?;
+        self.after = products.last().map(|p| p.id);
+        self.done = (products.len() as i64) < CHUNK_SIZE;
+
+        let mut data = Vec::new();
+        match self.format {
+            ExportFormat::Csv => {
+                let headers = Some(&ExportedProduct::HEADERS[..]).filter(|_| self.first);
+                let records: Vec<_> = products.iter().map(ExportedProduct::to_record).collect();
+                data = write_csv(headers, &records).map_err(error::ErrorInternalServerError)?;
+            }
+            _ => {
+                if self.first {
+                    data.push(b'[');
+                }
+                for (i, product) in products.iter().enumerate() {
+                    if !self.first || i > 0 {
+                        data.push(b',');
+                    }
+                    serde_json::to_writer(&mut data, product)?;
+                }
+                if self.done {
+                    data.push(b']');
+                }
+            }
+        }
+        self.first = false;
+        Ok(Bytes::from(data))
+    }
+}
+
+/// XLSX files are ZIP archives, which can only be streamed once complete.
+/// The file is assembled in a temporary file instead of in memory.
+async fn export_xlsx(
+    db: Addr<DbActor>,
+    req: &HttpRequest,
+    file_name: &str,
+) -> Result<HttpResponse, Error> {
+    let mut writer = XlsxWriter::new(&ExportedProduct::HEADERS, &NUMERIC_COLUMNS)
+        .map_err(error::ErrorInternalServerError)?;
+    let mut after = None;
+    loop {
+        let products = fetch_chunk(&db, after).await?;
+        let records: Vec<_> = products.iter().map(ExportedProduct::to_record).collect();
+        writer
+            .write_rows(&records)
+            .map_err(error::ErrorInternalServerError)?;
+        after = products.last().map(|p| p.id);
+        if (products.len() as i64) < CHUNK_SIZE {
+            break;
+        }
+    }
+
+    let file = web::block(move || writer.finish()).await?;
+    let res = NamedFile::from_file(file, file_name)?
+        .set_content_type(ExportFormat::Xlsx.content_type().parse().unwrap())
+        .set_content_disposition(attachment(file_name.to_string()))
+        .into_response(req)?;
+    Ok(res)
+}
+
+async fn fetch_chunk(
+    db: &Addr<DbActor>,
+    after: Option<Uuid>,
+) -> Result<Vec<ExportedProduct>, Error> {
+    let msg = ExportProducts {
+        after,
+        limit: CHUNK_SIZE,
+    };
+    db.send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .map_err(error::ErrorInternalServerError)
+}
+
+fn attachment(file_name: String) -> ContentDisposition {
+    ContentDisposition {
+        disposition: DispositionType::Attachment,
+        parameters: vec![DispositionParam::Filename(file_name)],
+    }
+}
@@ -0,0 +1,56 @@
+use actix_web::{get, web, Error, HttpResponse, Scope};
+
+use crate::actors::ListImageHashes;
+use crate::db::products::ListFeedProducts;
+use crate::feed::{facebook_csv, google_rss, FeedItem};
+use crate::Context;
+
+pub fn public_scope(path: &str) -> Scope {
+    web::scope(path).service(google_feed).service(facebook_feed)
+}
+
+/// Product feed for Google Merchant Center
+#[get("/google.xml")]
+async fn google_feed(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let items = feed_items(&ctx).await?;
+    let config = &ctx.config;
+    let xml = google_rss(&config.shop_name, &config.shop_url, &items);
+    Ok(HttpResponse::Ok()
+        .content_type("application/rss+xml; charset=utf-8")
+        .body(xml))
+}
+
+/// Product feed for Facebook Catalog
+#[get("/facebook.csv")]
+async fn facebook_feed(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let items = feed_items(&ctx).await?;
+    let csv = facebook_csv(&items).expect("Failed to write feed");
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .body(csv))
+}
+
+/// Fetches all products which aren't archived, together with their images
+async fn feed_items(ctx: &Context) -> Result<Vec<FeedItem>, Error> {
+    let products = ctx
+        .db
+        .send(ListFeedProducts {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch products");
+    let mut hashes = ctx
+        .image
+        .send(ListImageHashes {})
+        .await
+        .expect("Failed to call ImageActor")
+        .expect("Failed to list images");
+
+    let items = products
+        .into_iter()
+        .map(|(product, manufacturer, price)| {
+            let hash = hashes.remove(&product.id);
+            FeedItem::new(&ctx.config, product, price, manufacturer, hash)
+        })
+        .collect();
+    Ok(items)
+}
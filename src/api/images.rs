@@ -1,23 +1,93 @@
 // Based on https://github.com/actix/examples/blob/master/multipart/src/main.rs
 
+use actix_files as fs;
 use actix_multipart::Multipart;
-use actix_web::{put, web, Error, HttpResponse, Scope};
+use actix_web::dev::{HttpServiceFactory, Service};
+use actix_web::http::header::{self, HeaderValue};
+use actix_web::{put, web, Error, HttpRequest, HttpResponse, Scope};
 use futures::{StreamExt, TryStreamExt};
+use serde_json::json;
 
-use crate::actors::UploadImage;
+use crate::actors::{GetImageHash, ImageName, UploadImage};
+use crate::auth::Principal;
+use crate::db::audit::{Action, EntityType, InsertAuditEntry};
 use crate::Context;
 
 pub fn admin_scope(path: &str) -> Scope {
     web::scope(path).service(upload_image)
 }
 
+/// Serves the uploaded images and thumbnails. Files with a content hash in
+/// their name never change and get the `immutable_cache_control` policy.
+/// Names without hash are redirected to the current content-hashed file.
+pub fn files_scope(
+    path: &str,
+    cache_control: &str,
+    immutable_cache_control: &str,
+) -> impl HttpServiceFactory {
+    let cache_control = HeaderValue::from_str(cache_control).expect("Invalid Cache-Control");
+    let immutable_cache_control =
+        HeaderValue::from_str(immutable_cache_control).expect("Invalid Cache-Control");
+    web::scope(path)
+        .wrap_fn(move |req, srv| {
+            let is_hashed = image_name(req.path()).is_some_and(|n| n.hash.is_some());
+            let value = if is_hashed {
+                immutable_cache_control.clone()
+            } else {
+                cache_control.clone()
+            };
+            let res = srv.call(req);
+            async move {
+                let mut res = res.await?;
+                if !res.status().is_client_error() && !res.status().is_server_error() {
+                    res.headers_mut().insert(header::CACHE_CONTROL, value);
+                }
+                Ok(res)
+            }
+        })
+        .service(fs::Files::new("", "images").default_handler(web::to(redirect_image)))
+}
+
+fn image_name(path: &str) -> Option<ImageName> {
+    path.rsplit('/').next().and_then(ImageName::parse)
+}
+
+/// Redirect to the content-hashed file of an image or thumbnail
+async fn redirect_image(ctx: web::Data<Context>, req: HttpRequest) -> Result<HttpResponse, Error> {
+    let name = match image_name(req.path()) {
+        Some(name) if name.hash.is_none() => name,
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let result = ctx
+        .image
+        .send(GetImageHash { id: name.id })
+        .await
+        .expect("Failed to call ImageActor");
+    match result {
+        Ok(Some(hash)) => {
+            let location = ImageName {
+                hash: Some(hash),
+                ..name
+            }
+            .file_name();
+            Ok(HttpResponse::Found()
+                .header(header::LOCATION, location)
+                .finish())
+        }
+        Ok(None) => Ok(HttpResponse::NotFound().finish()),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
+
 /// Upload a new image
 #[put("/{image_id}")]
 async fn upload_image(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
     image_id: web::Path<uuid::Uuid>,
     mut payload: Multipart,
 ) -> Result<HttpResponse, Error> {
+    let image_id = image_id.into_inner();
     // Extract field from multipart
     if let Ok(Some(mut field)) = payload.try_next().await {
         // Collect data into vector
@@ -28,8 +98,9 @@ async fn upload_image(
         }
 
         // Call actor
+        let size = image.len();
         let msg = UploadImage {
-            id: image_id.into_inner(),
+            id: image_id,
             data: image,
         };
         let result = ctx
@@ -46,6 +117,20 @@ async fn upload_image(
                 return Ok(HttpResponse::InternalServerError().body(e.to_string()));
             }
         }
+
+        // Record upload
+        let msg = InsertAuditEntry {
+            author: principal.into_inner(),
+            entity_type: EntityType::Image,
+            entity_id: image_id,
+            action: Action::Upload,
+            after: json!({ "size": size }),
+        };
+        ctx.db
+            .send(msg)
+            .await
+            .expect("Failed to contact DbActor")
+            .expect("Failed to record image upload");
     }
     Ok(HttpResponse::Ok().into())
 }
@@ -0,0 +1,150 @@
+use actix_multipart::Multipart;
+use actix_web::{post, web, Error, HttpResponse, Scope};
+use futures::{StreamExt, TryStreamExt};
+
+use crate::auth::Principal;
+use crate::db::exchange_rates::SetExchangeRates;
+use crate::db::import::ImportProducts;
+use crate::models::{ExchangeRateData, ImportOptions, ValidationErrors};
+use crate::spreadsheet::{read_table, Table};
+use crate::Context;
+
+/// Max size of an uploaded import file
+const MAX_FILE_SIZE: usize = 10 * 1024 * 1024;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(import_products)
+        .service(import_exchange_rates)
+}
+
+/// Create or update products from a CSV or XLSX file. The multipart body
+/// contains the file in part `file` and the JSON encoded options in `options`.
+#[post("/products")]
+async fn import_products(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    // Collect parts
+    let (file, options) = match read_parts(payload).await {
+        Ok(parts) => parts,
+        Err(res) => return Ok(res),
+    };
+
+    // Parse options and file
+    let file = match file {
+        Some(file) => file,
+        None => return Ok(HttpResponse::BadRequest().body("Part file is missing")),
+    };
+    let options = match options {
+        Some(options) => match serde_json::from_slice::<ImportOptions>(&options) {
+            Ok(options) => options,
+            Err(e) => return Ok(HttpResponse::BadRequest().body(format!("Invalid options: {}", e))),
+        },
+        None => ImportOptions::default(),
+    };
+    options.validate()?;
+    let table = match read_table(&file, options.delimiter as u8) {
+        Ok(table) => table,
+        Err(e) => {
+            let mut errors = ValidationErrors::new();
+            errors.add("file", e.to_string());
+            return Err(errors.into());
+        }
+    };
+    let columns = options.columns(&table.headers)?;
+    let rows = table
+        .rows
+        .iter()
+        .enumerate()
+        .map(|(i, row)| (Table::row_number(i), columns.parse(row)))
+        .collect();
+
+    // Import rows
+    let msg = ImportProducts {
+        author: principal.into_inner(),
+        match_by: options.match_by,
+        create_missing: options.create_missing,
+        dry_run: options.dry_run,
+        rows,
+    };
+    let report = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to import products");
+
+    if report.has_errors() && !report.dry_run {
+        Ok(HttpResponse::UnprocessableEntity().json(report))
+    } else {
+        Ok(HttpResponse::Ok().json(report))
+    }
+}
+
+/// Add or replace exchange rates from a CSV or XLSX file in part `file` of
+/// the multipart body. The file has the columns `currency` and `rate`, and
+/// optionally `decimals`, `rounding` and `rounding_increment`. Nothing is
+/// saved if any row is invalid.
+#[post("/exchange-rates")]
+async fn import_exchange_rates(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    payload: Multipart,
+) -> Result<HttpResponse, Error> {
+    let file = match read_parts(payload).await {
+        Ok((Some(file), _)) => file,
+        Ok((None, _)) => return Ok(HttpResponse::BadRequest().body("Part file is missing")),
+        Err(res) => return Ok(res),
+    };
+    let table = match read_table(&file, b',') {
+        Ok(table) => table,
+        Err(e) => {
+            let mut errors = ValidationErrors::new();
+            errors.add("file", e.to_string());
+            return Err(errors.into());
+        }
+    };
+    let rates = ExchangeRateData::from_table(&table, &ctx.config.currency)?;
+
+    let msg = SetExchangeRates {
+        author: principal.into_inner(),
+        rates,
+    };
+    let rates = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to import exchange rates");
+    Ok(HttpResponse::Ok().json(rates))
+}
+
+/// Reads the parts `file` and `options` of a multipart body. Returns the
+/// response to send if the body can't be read or the file is too large.
+async fn read_parts(
+    mut payload: Multipart,
+) -> Result<(Option<Vec<u8>>, Option<Vec<u8>>), HttpResponse> {
+    let mut file: Option<Vec<u8>> = None;
+    let mut options: Option<Vec<u8>> = None;
+    while let Ok(Some(mut field)) = payload.try_next().await {
+        let name = field
+            .content_disposition()
+            .and_then(|cd| cd.get_name().map(str::to_string));
+        let mut data: Vec<u8> = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk.map_err(|e| HttpResponse::from_error(e.into()))?;
+            data.extend_from_slice(chunk.as_ref());
+            if data.len() > MAX_FILE_SIZE {
+                return Err(HttpResponse::PayloadTooLarge().body("Import file is too large"));
+            }
+        }
+        match name.as_deref() {
+            Some("file") => file = Some(data),
+            Some("options") => options = Some(data),
+            _ => (),
+        }
+    }
+    Ok((file, options))
+}
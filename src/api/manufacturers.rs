@@ -1,23 +1,36 @@
-use actix_web::{delete, get, post, put, web, Error, HttpResponse, Scope};
+use actix_web::{
+    delete, get, http::header, patch, post, put, web, Error, HttpRequest, HttpResponse, Scope,
+};
+use diesel::result::Error::NotFound;
+use validator::Validate;
 
-use crate::actors::DeleteImage;
+use crate::api::exchange_rates::{convert_prices, requested_exchange_rate};
+use crate::api::products::VARY;
+use crate::api::translations::{requested_locales, translate_products};
+use crate::api::typed_error_response;
+use crate::auth::Principal;
 use crate::db::manufacturers::*;
-use crate::models;
+use crate::http_cache::{http_date, if_match_version, versioned_json};
+use crate::models::{self, CurrencyQuery, LocaleQuery, ValidationErrors};
 use crate::Context;
 
 pub fn public_scope(path: &str) -> Scope {
     web::scope(path)
         .service(list_manufacturers)
         .service(get_manufacturer)
+        .service(get_manufacturer_by_slug)
 }
 
 pub fn admin_scope(path: &str) -> Scope {
     web::scope(path)
         .service(list_manufacturers)
         .service(get_manufacturer)
+        .service(get_manufacturer_by_slug)
         .service(add_manufacturer)
         .service(update_manufacturer)
+        .service(patch_manufacturer)
         .service(delete_manufacturer)
+        .service(restore_manufacturer)
 }
 
 /// List all manufacturers
@@ -40,7 +53,7 @@ async fn get_manufacturer(
 ) -> Result<HttpResponse, Error> {
     let manufacturer_id = manufacturer_id.into_inner();
     let msg = GetManufacturer {
-        id: manufacturer_id.clone(),
+        id: manufacturer_id,
     };
     let manufacturer = ctx
         .db
@@ -49,7 +62,13 @@ async fn get_manufacturer(
         .expect("Failed to contact DbActor")
         .expect("Failed to fetch manufacturer");
     if let Some(manufacturer) = manufacturer {
-        Ok(HttpResponse::Ok().json(manufacturer))
+        let mut res = HttpResponse::Ok();
+        res.header(header::LAST_MODIFIED, http_date(manufacturer.updated_at));
+        Ok(versioned_json(
+            &mut res,
+            manufacturer.version,
+            &manufacturer,
+        ))
     } else {
         let res = HttpResponse::NotFound().body(format!(
             "No manufacturer found with id: {}",
@@ -59,76 +78,219 @@ async fn get_manufacturer(
     }
 }
 
-/// Insert new manufacturer from form
-#[post("")]
-async fn add_manufacturer(
+/// Find manufacturer by slug. Its visible products are embedded if the query
+/// contains `products=true`, paginated by `limit` and `offset`, translated to
+/// the locale requested with `lang` or `Accept-Language`, and priced in the
+/// currency requested with `currency` or `Accept-Currency`.
+#[get("/slug/{manufacturer_slug}")]
+async fn get_manufacturer_by_slug(
     ctx: web::Data<Context>,
-    form: web::Json<models::ManufacturerData>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    lang: web::Query<LocaleQuery>,
+    currency: web::Query<CurrencyQuery>,
+    manufacturer_slug: web::Path<String>,
+    query: web::Query<models::EmbedProductsQuery>,
 ) -> Result<HttpResponse, Error> {
-    let msg = InsertManufacturer {
-        data: form.into_inner(),
+    let manufacturer_slug = manufacturer_slug.into_inner();
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let rate = if query.products {
+        requested_exchange_rate(&ctx, &req, &currency).await?
+    } else {
+        None
+    };
+    let msg = GetManufacturerBySlug {
+        slug: manufacturer_slug.clone(),
+        products: query.page(),
     };
     let manufacturer = ctx
         .db
         .send(msg)
         .await
         .expect("Failed to contact DbActor")
-        .expect("Failed to fetch manufacturer");
+        .expect("Failed to fetch manufacturer by slug");
+    if let Some(mut manufacturer) = manufacturer {
+        let mut res = HttpResponse::Ok();
+        if let Some(products) = manufacturer.products.as_mut() {
+            translate_products(&ctx, &mut products.items, &locales).await?;
+            convert_prices(&mut products.items, rate.as_ref());
+            res.header(header::CONTENT_LANGUAGE, locales[0].as_str())
+                .header(header::VARY, VARY);
+        } else {
+            res.header(
+                header::LAST_MODIFIED,
+                http_date(manufacturer.entity.updated_at),
+            );
+        }
+        Ok(versioned_json(
+            &mut res,
+            manufacturer.entity.version,
+            &manufacturer,
+        ))
+    } else {
+        let res = HttpResponse::NotFound().body(format!(
+            "No manufacturer found with slug: {}",
+            manufacturer_slug
+        ));
+        Ok(res)
+    }
+}
 
-    Ok(HttpResponse::Ok().json(manufacturer))
+/// Insert new manufacturer from form
+#[post("")]
+async fn add_manufacturer(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    form: web::Json<models::ManufacturerData>,
+) -> Result<HttpResponse, Error> {
+    let form = form.into_inner();
+    form.validate().map_err(ValidationErrors::from)?;
+    let msg = InsertManufacturer {
+        author: principal.into_inner(),
+        data: form,
+    };
+    let manufacturer = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match manufacturer {
+        Ok(manufacturer) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            manufacturer.version,
+            &manufacturer,
+        )),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
 }
 
 /// Update manufacturer from form
 #[put("/{manufacturer_id}")]
 async fn update_manufacturer(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
     manufacturer_id: web::Path<uuid::Uuid>,
-    form: web::Json<models::ManufacturerData>,
+    form: web::Json<models::Versioned<models::ManufacturerData>>,
 ) -> Result<HttpResponse, Error> {
     let manufacturer_id = manufacturer_id.into_inner();
+    let form = form.into_inner();
+    form.data.validate().map_err(ValidationErrors::from)?;
     let msg = UpdateManufacturer {
-        id: manufacturer_id.clone(),
-        data: form.into_inner(),
+        author: principal.into_inner(),
+        id: manufacturer_id,
+        version: if_match_version(&req).or(form.version),
+        data: form.data,
     };
     let manufacturer = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    if let Ok(manufacturer) = manufacturer {
-        Ok(HttpResponse::Ok().json(manufacturer))
-    } else {
-        let res = HttpResponse::NotFound().body(format!(
-            "No manufacturer found with id: {}",
-            manufacturer_id
-        ));
-        Ok(res)
+    match manufacturer {
+        Ok(manufacturer) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            manufacturer.version,
+            &manufacturer,
+        )),
+        Err(e) => match typed_error_response(&e) {
+            Some(res) => Ok(res),
+            None => {
+                let res = HttpResponse::NotFound().body(format!(
+                    "No manufacturer found with id: {}",
+                    manufacturer_id
+                ));
+                Ok(res)
+            }
+        },
+    }
+}
+
+/// Update provided fields of manufacturer from JSON Merge Patch
+#[patch("/{manufacturer_id}")]
+async fn patch_manufacturer(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
+    manufacturer_id: web::Path<uuid::Uuid>,
+    patch: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    let manufacturer_id = manufacturer_id.into_inner();
+    let patch = models::ManufacturerPatch::from_merge_patch(&patch)?;
+    let msg = PatchManufacturer {
+        author: principal.into_inner(),
+        id: manufacturer_id,
+        version: if_match_version(&req).or(patch.version),
+        patch: patch.data,
+    };
+    let manufacturer = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match manufacturer {
+        Ok(manufacturer) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            manufacturer.version,
+            &manufacturer,
+        )),
+        Err(e) => match typed_error_response(&e) {
+            Some(res) => Ok(res),
+            None => {
+                let res = HttpResponse::NotFound().body(format!(
+                    "No manufacturer found with id: {}",
+                    manufacturer_id
+                ));
+                Ok(res)
+            }
+        },
     }
 }
 
-/// Delete manufacturer with ID
+/// Move manufacturer with ID to the trash
 #[delete("/{manufacturer_id}")]
 async fn delete_manufacturer(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
     manufacturer_id: web::Path<uuid::Uuid>,
+    query: web::Query<models::DeleteQuery>,
 ) -> Result<HttpResponse, Error> {
     let manufacturer_id = manufacturer_id.into_inner();
     let msg = DeleteManufacturer {
-        id: manufacturer_id.clone(),
+        author: principal.into_inner(),
+        id: manufacturer_id,
+        policy: query.policy()?,
     };
     let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    if result.is_ok() {
-        // Request deletion of image and thumbnails
-        let msg = DeleteImage {
-            id: manufacturer_id,
-        };
-        ctx.image.do_send(msg);
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(HttpResponse::NotFound().body(
+            format!("No manufacturer found with id: {}", manufacturer_id),
+        )),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Restore manufacturer with ID from the trash
+#[post("/{manufacturer_id}/restore")]
+async fn restore_manufacturer(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    manufacturer_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let manufacturer_id = manufacturer_id.into_inner();
+    let msg = RestoreManufacturer {
+        author: principal.into_inner(),
+        id: manufacturer_id,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-        // Send success response
-        Ok(HttpResponse::Ok().finish())
-    } else {
-        let res = HttpResponse::NotFound().body(format!(
-            "No manufacturer found with id: {}",
-            manufacturer_id
-        ));
-        Ok(res)
+    match result {
+        Ok(manufacturer) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            manufacturer.version,
+            &manufacturer,
+        )),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!(
+                "No manufacturer found in the trash with id: {}",
+                manufacturer_id
+            )))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
     }
 }
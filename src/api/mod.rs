@@ -1,5 +1,50 @@
-pub mod auth;
+pub mod api_keys;
+pub mod audit;
+pub mod carts;
 pub mod categories;
+pub mod coupons;
+pub mod exchange_rates;
+pub mod export;
+pub mod feeds;
 pub mod images;
+pub mod import;
 pub mod manufacturers;
+pub mod orders;
 pub mod products;
+pub mod promotions;
+pub mod sitemap;
+pub mod translations;
+pub mod trash;
+
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+
+use crate::db::{DeleteConflict, VersionConflict};
+use crate::models::ValidationErrors;
+
+/// Returns the dedicated response of errors like failed validations or
+/// version conflicts. Other errors should be handled by the caller.
+pub fn typed_error_response(err: &failure::Error) -> Option<HttpResponse> {
+    if let Some(errors) = err.downcast_ref::<ValidationErrors>() {
+        return Some(errors.error_response());
+    }
+    if let Some(conflict) = err.downcast_ref::<VersionConflict>() {
+        return Some(conflict.error_response());
+    }
+    if let Some(conflict) = err.downcast_ref::<DeleteConflict>() {
+        return Some(conflict.error_response());
+    }
+    None
+}
+
+/// Returns the location of the entity with the slug, which replaces the last
+/// segment of the requested path. The query string is kept, so a redirect
+/// doesn't lose the requested locale or currency.
+pub fn slug_location(req: &HttpRequest, slug: &str) -> String {
+    let path = req.path();
+    let mut location = format!("{}{}", &path[..=path.rfind('/').unwrap_or_default()], slug);
+    if !req.query_string().is_empty() {
+        location.push('?');
+        location.push_str(req.query_string());
+    }
+    location
+}
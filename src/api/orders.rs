@@ -0,0 +1,39 @@
+use actix_web::{get, web, Error, HttpResponse, Scope};
+
+use crate::db::orders::*;
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path).service(list_orders).service(get_order)
+}
+
+/// List all orders, most recent first
+#[get("")]
+async fn list_orders(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let orders = ctx
+        .db
+        .send(ListOrders {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch orders");
+    Ok(HttpResponse::Ok().json(orders))
+}
+
+/// Find order by ID, with its items
+#[get("/{order_id}")]
+async fn get_order(
+    ctx: web::Data<Context>,
+    order_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let order_id = order_id.into_inner();
+    let order = ctx
+        .db
+        .send(GetOrder { id: order_id })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch order");
+    match order {
+        Some(order) => Ok(HttpResponse::Ok().json(order)),
+        None => Ok(HttpResponse::NotFound().body(format!("No order found with id: {}", order_id))),
+    }
+}
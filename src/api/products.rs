@@ -1,8 +1,18 @@
-use actix_web::{delete, get, post, put, web, Error, HttpResponse, Scope};
+use actix_web::{
+    delete, get, http::header, patch, post, put, web, Error, HttpRequest, HttpResponse, Scope,
+};
+use diesel::result::Error::NotFound;
+use validator::Validate;
 
-use crate::actors::DeleteImage;
+use crate::api::exchange_rates::{convert_prices, requested_exchange_rate};
+use crate::api::translations::{requested_locales, translate_products};
+use crate::api::{slug_location, typed_error_response};
+use crate::auth::Principal;
 use crate::db::products::*;
-use crate::models;
+use crate::db::revisions::*;
+use crate::db::BySlug;
+use crate::http_cache::{if_match_version, versioned_json};
+use crate::models::{self, CurrencyQuery, LocaleQuery, ValidationErrors};
 use crate::Context;
 
 pub fn public_scope(path: &str) -> Scope {
@@ -18,31 +28,66 @@ pub fn admin_scope(path: &str) -> Scope {
         .service(get_product)
         .service(get_product_by_slug)
         .service(add_product)
+        .service(bulk_update_products)
         .service(update_product)
+        .service(patch_product)
         .service(delete_product)
+        .service(restore_product)
+        .service(list_product_revisions)
+        .service(diff_product_revisions)
+        .service(restore_product_revision)
 }
 
-/// List all products
+/// Request headers which change the content of product responses
+pub const VARY: &str = "Accept-Currency, Accept-Language";
+
+/// List all products. Visitors only see published products. Prices are
+/// converted to the currency requested with `currency` or `Accept-Currency`,
+/// and content is translated to the locale requested with `lang` or
+/// `Accept-Language`.
 #[get("")]
-async fn list_products(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
-    let products = ctx
+async fn list_products(
+    ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    query: web::Query<CurrencyQuery>,
+    lang: web::Query<LocaleQuery>,
+) -> Result<HttpResponse, Error> {
+    let rate = requested_exchange_rate(&ctx, &req, &query).await?;
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
+    let msg = ListProducts {
+        published_only: principal.is_none(),
+    };
+    let mut products = ctx
         .db
-        .send(ListProducts {})
+        .send(msg)
         .await
         .expect("Failed to contact DbActor")
         .expect("Failed to fetch product");
-    Ok(HttpResponse::Ok().json(products))
+    convert_prices(&mut products, rate.as_ref());
+    translate_products(&ctx, &mut products, &locales).await?;
+    Ok(HttpResponse::Ok()
+        .header(header::CONTENT_LANGUAGE, locales[0].as_str())
+        .header(header::VARY, VARY)
+        .json(products))
 }
 
 /// Find product by ID
 #[get("/{product_id}")]
 async fn get_product(
     ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    query: web::Query<CurrencyQuery>,
+    lang: web::Query<LocaleQuery>,
     product_id: web::Path<uuid::Uuid>,
 ) -> Result<HttpResponse, Error> {
     let product_id = product_id.into_inner();
+    let rate = requested_exchange_rate(&ctx, &req, &query).await?;
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
     let msg = GetProduct {
-        id: product_id.clone(),
+        id: product_id,
+        published_only: principal.is_none(),
     };
     let product = ctx
         .db
@@ -50,8 +95,15 @@ async fn get_product(
         .await
         .expect("Failed to contact DbActor")
         .expect("Failed to fetch product");
-    if let Some(product) = product {
-        Ok(HttpResponse::Ok().json(product))
+    if let Some(mut product) = product {
+        convert_prices(std::slice::from_mut(&mut product), rate.as_ref());
+        translate_products(&ctx, std::slice::from_mut(&mut product), &locales).await?;
+        // Prices change with sales, promotions and exchange rates without
+        // changing the product, so only the entity tag identifies the response
+        let mut res = HttpResponse::Ok();
+        res.header(header::CONTENT_LANGUAGE, locales[0].as_str())
+            .header(header::VARY, VARY);
+        Ok(versioned_json(&mut res, product.product.version, &product))
     } else {
         let res =
             HttpResponse::NotFound().body(format!("No product found with id: {}", product_id));
@@ -59,28 +111,56 @@ async fn get_product(
     }
 }
 
-/// Find product by slug
+/// Find product by slug. The current slugs of all locales are served, former
+/// slugs of a product are permanently redirected to its current slug in the
+/// requested locale.
 #[get("/slug/{product_slug}")]
 async fn get_product_by_slug(
     ctx: web::Data<Context>,
+    principal: Option<web::ReqData<Principal>>,
+    req: HttpRequest,
+    query: web::Query<CurrencyQuery>,
+    lang: web::Query<LocaleQuery>,
     product_slug: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
     let product_slug = product_slug.into_inner();
+    let rate = requested_exchange_rate(&ctx, &req, &query).await?;
+    let locales = requested_locales(&ctx, &req, &lang, principal.is_some());
     let msg = GetProductBySlug {
         slug: product_slug.clone(),
+        published_only: principal.is_none(),
     };
-    let product = ctx
+    let mut product = ctx
         .db
         .send(msg)
         .await
         .expect("Failed to contact DbActor")
         .expect("Failed to fetch product by slug");
-    if let Some(product) = product {
-        Ok(HttpResponse::Ok().json(product))
-    } else {
-        let res =
-            HttpResponse::NotFound().body(format!("No product found with slug: {}", product_slug));
-        Ok(res)
+    if let Some(found) = product.as_mut() {
+        convert_prices(std::slice::from_mut(&mut found.entity), rate.as_ref());
+        translate_products(&ctx, std::slice::from_mut(&mut found.entity), &locales).await?;
+    }
+    match product {
+        Some(found) if found.former => Ok(HttpResponse::MovedPermanently()
+            .header(
+                header::LOCATION,
+                slug_location(&req, &found.entity.product.slug),
+            )
+            .header(header::VARY, VARY)
+            .json(found.entity)),
+        Some(BySlug {
+            entity: product, ..
+        }) => {
+            let mut res = HttpResponse::Ok();
+            res.header(header::CONTENT_LANGUAGE, locales[0].as_str())
+                .header(header::VARY, VARY);
+            Ok(versioned_json(&mut res, product.product.version, &product))
+        }
+        None => {
+            let res = HttpResponse::NotFound()
+                .body(format!("No product found with slug: {}", product_slug));
+            Ok(res)
+        }
     }
 }
 
@@ -88,66 +168,258 @@ async fn get_product_by_slug(
 #[post("")]
 async fn add_product(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
     form: web::Json<models::ProductDataWithMeta>,
 ) -> Result<HttpResponse, Error> {
+    let form = form.into_inner();
+    form.product.validate().map_err(ValidationErrors::from)?;
     let msg = InsertProduct {
-        data: form.into_inner(),
+        author: principal.into_inner(),
+        data: form,
     };
-    let product = ctx
+    let product = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match product {
+        Ok(product) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            product.product.version,
+            &product,
+        )),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Apply operations to multiple products at once. Either all operations
+/// succeed or no changes are made.
+#[post("/bulk")]
+async fn bulk_update_products(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    form: web::Json<models::BulkRequest>,
+) -> Result<HttpResponse, Error> {
+    let form = form.into_inner();
+    form.validate()?;
+    let msg = BulkUpdateProducts {
+        author: principal.into_inner(),
+        request: form,
+    };
+    let report = ctx
         .db
         .send(msg)
         .await
         .expect("Failed to contact DbActor")
-        .expect("Failed to fetch product");
+        .expect("Failed to apply bulk operations");
 
-    Ok(HttpResponse::Ok().json(product))
+    if report.has_errors() && !report.dry_run {
+        Ok(HttpResponse::UnprocessableEntity().json(report))
+    } else {
+        Ok(HttpResponse::Ok().json(report))
+    }
 }
 
 /// Update product from form
 #[put("/{product_id}")]
 async fn update_product(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
     product_id: web::Path<uuid::Uuid>,
-    form: web::Json<models::ProductDataWithMeta>,
+    form: web::Json<models::Versioned<models::ProductDataWithMeta>>,
 ) -> Result<HttpResponse, Error> {
     let product_id = product_id.into_inner();
+    let form = form.into_inner();
+    form.data
+        .product
+        .validate()
+        .map_err(ValidationErrors::from)?;
     let msg = UpdateProduct {
-        id: product_id.clone(),
-        data: form.into_inner(),
+        author: principal.into_inner(),
+        id: product_id,
+        version: if_match_version(&req).or(form.version),
+        data: form.data,
     };
     let product = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    if let Ok(product) = product {
-        Ok(HttpResponse::Ok().json(product))
-    } else {
-        let res =
-            HttpResponse::NotFound().body(format!("No product found with id: {}", product_id));
-        Ok(res)
+    match product {
+        Ok(product) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            product.product.version,
+            &product,
+        )),
+        Err(e) => match typed_error_response(&e) {
+            Some(res) => Ok(res),
+            None => {
+                let res = HttpResponse::NotFound()
+                    .body(format!("No product found with id: {}", product_id));
+                Ok(res)
+            }
+        },
     }
 }
 
-/// Delete product with ID
+/// Update provided fields of product from JSON Merge Patch
+#[patch("/{product_id}")]
+async fn patch_product(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
+    product_id: web::Path<uuid::Uuid>,
+    patch: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, Error> {
+    let product_id = product_id.into_inner();
+    let patch = models::ProductPatchWithMeta::from_merge_patch(&patch)?;
+    let msg = PatchProduct {
+        author: principal.into_inner(),
+        id: product_id,
+        version: if_match_version(&req).or(patch.version),
+        patch: patch.data,
+    };
+    let product = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match product {
+        Ok(product) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            product.product.version,
+            &product,
+        )),
+        Err(e) => match typed_error_response(&e) {
+            Some(res) => Ok(res),
+            None => {
+                let res = HttpResponse::NotFound()
+                    .body(format!("No product found with id: {}", product_id));
+                Ok(res)
+            }
+        },
+    }
+}
+
+/// Move product with ID to the trash
 #[delete("/{product_id}")]
 async fn delete_product(
     ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
     product_id: web::Path<uuid::Uuid>,
 ) -> Result<HttpResponse, Error> {
     let product_id = product_id.into_inner();
     let msg = DeleteProduct {
-        id: product_id.clone(),
+        author: principal.into_inner(),
+        id: product_id,
     };
     let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
 
-    if result.is_ok() {
-        // Request deletion of image and thumbnails
-        let msg = DeleteImage { id: product_id };
-        ctx.image.do_send(msg);
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(err) => {
+            let res = HttpResponse::BadRequest().body(format!("{}", err));
+            Ok(res)
+        }
+    }
+}
 
-        // Send success response
-        Ok(HttpResponse::Ok().finish())
-    } else {
-        let err = result.unwrap_err();
-        let res = HttpResponse::BadRequest().body(format!("{}", err));
-        Ok(res)
+/// Restore product with ID from the trash
+#[post("/{product_id}/restore")]
+async fn restore_product(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    product_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let product_id = product_id.into_inner();
+    let msg = RestoreProduct {
+        author: principal.into_inner(),
+        id: product_id,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(product) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            product.product.version,
+            &product,
+        )),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(HttpResponse::NotFound().body(
+            format!("No product found in the trash with id: {}", product_id),
+        )),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
+
+/// List the revisions of product with ID, newest first
+#[get("/{product_id}/revisions")]
+async fn list_product_revisions(
+    ctx: web::Data<Context>,
+    product_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let product_id = product_id.into_inner();
+    let msg = ListProductRevisions { product_id };
+    let revisions = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch product revisions");
+    match revisions {
+        Some(revisions) => Ok(HttpResponse::Ok().json(revisions)),
+        None => {
+            Ok(HttpResponse::NotFound().body(format!("No product found with id: {}", product_id)))
+        }
+    }
+}
+
+/// Show the fields which changed between two revisions of product with ID
+#[get("/{product_id}/revisions/diff")]
+async fn diff_product_revisions(
+    ctx: web::Data<Context>,
+    product_id: web::Path<uuid::Uuid>,
+    query: web::Query<models::RevisionDiffQuery>,
+) -> Result<HttpResponse, Error> {
+    let product_id = product_id.into_inner();
+    let msg = DiffProductRevisions {
+        product_id,
+        from: query.from,
+        to: query.to,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(diff) => Ok(HttpResponse::Ok().json(diff)),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(HttpResponse::NotFound().body(
+            format!("No such revisions of product with id: {}", product_id),
+        )),
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
+
+/// Update product with ID to a previous revision
+#[post("/{product_id}/revisions/{revision}/restore")]
+async fn restore_product_revision(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
+    path: web::Path<(uuid::Uuid, i32)>,
+) -> Result<HttpResponse, Error> {
+    let (product_id, revision) = path.into_inner();
+    let msg = RestoreProductRevision {
+        author: principal.into_inner(),
+        product_id,
+        revision,
+        version: if_match_version(&req),
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(product) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            product.product.version,
+            &product,
+        )),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!(
+                "No revision {} of product with id: {}",
+                revision, product_id
+            )))
+        }
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
     }
 }
@@ -0,0 +1,141 @@
+use actix_web::{delete, get, post, put, web, Error, HttpRequest, HttpResponse, Scope};
+use diesel::result::Error::NotFound;
+
+use crate::api::typed_error_response;
+use crate::auth::Principal;
+use crate::db::promotions::*;
+use crate::http_cache::{if_match_version, versioned_json};
+use crate::models;
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(list_promotions)
+        .service(get_promotion)
+        .service(add_promotion)
+        .service(update_promotion)
+        .service(delete_promotion)
+}
+
+/// List all promotions, including the ones which ended or didn't start yet
+#[get("")]
+async fn list_promotions(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let promotions = ctx
+        .db
+        .send(ListPromotions {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch promotions");
+    Ok(HttpResponse::Ok().json(promotions))
+}
+
+/// Find promotion by ID
+#[get("/{promotion_id}")]
+async fn get_promotion(
+    ctx: web::Data<Context>,
+    promotion_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let promotion_id = promotion_id.into_inner();
+    let promotion = ctx
+        .db
+        .send(GetPromotion { id: promotion_id })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch promotion");
+    match promotion {
+        Some(promotion) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            promotion.version,
+            &promotion,
+        )),
+        None => {
+            Ok(HttpResponse::NotFound()
+                .body(format!("No promotion found with id: {}", promotion_id)))
+        }
+    }
+}
+
+/// Insert new promotion from form
+#[post("")]
+async fn add_promotion(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    form: web::Json<models::PromotionData>,
+) -> Result<HttpResponse, Error> {
+    let form = form.into_inner();
+    form.check()?;
+    let msg = InsertPromotion {
+        author: principal.into_inner(),
+        data: form,
+    };
+    let promotion = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match promotion {
+        Ok(promotion) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            promotion.version,
+            &promotion,
+        )),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Update promotion from form
+#[put("/{promotion_id}")]
+async fn update_promotion(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    req: HttpRequest,
+    promotion_id: web::Path<uuid::Uuid>,
+    form: web::Json<models::Versioned<models::PromotionData>>,
+) -> Result<HttpResponse, Error> {
+    let promotion_id = promotion_id.into_inner();
+    let form = form.into_inner();
+    form.data.check()?;
+    let msg = UpdatePromotion {
+        author: principal.into_inner(),
+        id: promotion_id,
+        version: if_match_version(&req).or(form.version),
+        data: form.data,
+    };
+    let promotion = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match promotion {
+        Ok(promotion) => Ok(versioned_json(
+            &mut HttpResponse::Ok(),
+            promotion.version,
+            &promotion,
+        )),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound()
+                .body(format!("No promotion found with id: {}", promotion_id)))
+        }
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Delete promotion with ID
+#[delete("/{promotion_id}")]
+async fn delete_promotion(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    promotion_id: web::Path<uuid::Uuid>,
+) -> Result<HttpResponse, Error> {
+    let promotion_id = promotion_id.into_inner();
+    let msg = DeletePromotion {
+        author: principal.into_inner(),
+        id: promotion_id,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound()
+                .body(format!("No promotion found with id: {}", promotion_id)))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
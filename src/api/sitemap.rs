@@ -0,0 +1,66 @@
+use actix_web::{get, web, Error, HttpResponse, Scope};
+
+use crate::db::sitemap::ListSitemapRecords;
+use crate::feed::{sitemap_index, sitemap_urlset, SitemapUrl, SITEMAP_MAX_URLS};
+use crate::Context;
+
+pub fn public_scope(path: &str) -> Scope {
+    web::scope(path).service(sitemap).service(sitemap_page)
+}
+
+/// Sitemap of the storefront. Becomes a sitemap index referring to
+/// `/public/sitemap/{page}.xml` if it exceeds the max number of URLs.
+#[get("/sitemap.xml")]
+async fn sitemap(ctx: web::Data<Context>) -> Result<HttpResponse, Error> {
+    let urls = sitemap_urls(&ctx).await?;
+    if urls.len() <= SITEMAP_MAX_URLS {
+        return Ok(xml_response(sitemap_urlset(&urls)));
+    }
+
+    let sitemaps: Vec<_> = urls
+        .chunks(SITEMAP_MAX_URLS)
+        .enumerate()
+        .filter_map(|(i, chunk)| {
+            let lastmod = chunk.iter().map(|u| u.lastmod).max()?;
+            let loc = format!("{}/public/sitemap/{}.xml", ctx.config.public_url, i + 1);
+            Some(SitemapUrl { loc, lastmod })
+        })
+        .collect();
+    Ok(xml_response(sitemap_index(&sitemaps)))
+}
+
+/// Single page of a sitemap index, starting at 1
+#[get("/sitemap/{page}.xml")]
+async fn sitemap_page(
+    ctx: web::Data<Context>,
+    page: web::Path<usize>,
+) -> Result<HttpResponse, Error> {
+    let page = page.into_inner();
+    let urls = sitemap_urls(&ctx).await?;
+    let chunk = page
+        .checked_sub(1)
+        .and_then(|i| urls.chunks(SITEMAP_MAX_URLS).nth(i));
+    match chunk {
+        Some(chunk) => Ok(xml_response(sitemap_urlset(chunk))),
+        None => Ok(HttpResponse::NotFound().body(format!("No sitemap found with page: {}", page))),
+    }
+}
+
+async fn sitemap_urls(ctx: &Context) -> Result<Vec<SitemapUrl>, Error> {
+    let records = ctx
+        .db
+        .send(ListSitemapRecords {})
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch sitemap");
+    Ok(records
+        .into_iter()
+        .filter_map(|record| SitemapUrl::from_record(&ctx.config, record))
+        .collect())
+}
+
+fn xml_response(xml: String) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/xml; charset=utf-8")
+        .body(xml)
+}
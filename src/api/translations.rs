@@ -0,0 +1,329 @@
+use actix_web::{delete, get, http::header, put, web, Error, HttpRequest, HttpResponse, Scope};
+use diesel::result::Error::NotFound;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::api::typed_error_response;
+use crate::auth::Principal;
+use crate::db::translations::*;
+use crate::models::{
+    self, locale_chain, parse_accept_language, Category, CategoryNode, LocaleQuery,
+    ProductWithMeta, ValidationErrors,
+};
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path)
+        .service(list_missing_translations)
+        .service(list_product_translations)
+        .service(set_product_translation)
+        .service(delete_product_translation)
+        .service(list_category_translations)
+        .service(set_category_translation)
+        .service(delete_category_translation)
+}
+
+/// Returns the locales in which content is looked up, most preferred first
+/// and ending with the default locale. Visitors request a locale with the
+/// `lang` query parameter or the `Accept-Language` header. Admins edit the
+/// content of the default locale, so they only get translations on request
+/// of `lang`.
+pub fn requested_locales(
+    ctx: &Context,
+    req: &HttpRequest,
+    query: &LocaleQuery,
+    admin: bool,
+) -> Vec<String> {
+    let mut requested: Vec<String> = query
+        .lang
+        .iter()
+        .map(|lang| lang.trim().to_lowercase())
+        .collect();
+    if !admin {
+        if let Some(header) = req
+            .headers()
+            .get(header::ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok())
+        {
+            requested.extend(parse_accept_language(header));
+        }
+    }
+    locale_chain(&requested, &ctx.config.locales)
+}
+
+/// Replaces the content of the products by their translations to the locales
+pub async fn translate_products(
+    ctx: &Context,
+    products: &mut [ProductWithMeta],
+    locales: &[String],
+) -> Result<(), Error> {
+    // The content of the default locale is stored in the products
+    let locales = &locales[..locales.len() - 1];
+    if locales.is_empty() || products.is_empty() {
+        return Ok(());
+    }
+    let msg = GetProductTranslations {
+        product_ids: products.iter().map(|p| p.product.id).collect(),
+        locales: locales.to_vec(),
+    };
+    let translations = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch product translations");
+    for product in products {
+        let own: Vec<_> = locales
+            .iter()
+            .filter_map(|locale| {
+                translations
+                    .iter()
+                    .find(|t| t.product_id == product.product.id && &t.locale == locale)
+            })
+            .collect();
+        product.product.translate(&own);
+    }
+    Ok(())
+}
+
+/// Replaces the content of the categories by their translations to the locales
+pub async fn translate_categories(
+    ctx: &Context,
+    categories: Vec<&mut Category>,
+    locales: &[String],
+) -> Result<(), Error> {
+    let locales = &locales[..locales.len() - 1];
+    if locales.is_empty() || categories.is_empty() {
+        return Ok(());
+    }
+    let msg = GetCategoryTranslations {
+        category_ids: categories.iter().map(|c| c.id).collect(),
+        locales: locales.to_vec(),
+    };
+    let translations = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch category translations");
+    for category in categories {
+        let own: Vec<_> = locales
+            .iter()
+            .filter_map(|locale| {
+                translations
+                    .iter()
+                    .find(|t| t.category_id == category.id && &t.locale == locale)
+            })
+            .collect();
+        category.translate(&own);
+    }
+    Ok(())
+}
+
+/// Collects the categories of all levels of the trees
+pub fn tree_categories(nodes: &mut [CategoryNode]) -> Vec<&mut Category> {
+    let mut categories = Vec::new();
+    for node in nodes {
+        categories.push(&mut node.category);
+        categories.extend(tree_categories(&mut node.children));
+    }
+    categories
+}
+
+/// Checks if content can be translated to the locale, which excludes the
+/// default locale
+fn check_locale(ctx: &Context, locale: &str) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    let translated = &ctx.config.locales[1..];
+    if locale == ctx.config.default_locale() {
+        errors.add(
+            "locale",
+            "Is the default locale, of which the content is edited in the entity itself",
+        );
+    } else if !translated.iter().any(|l| l == locale) {
+        errors.add(
+            "locale",
+            format!("Should be one of {}", translated.join(", ")),
+        );
+    }
+    errors.into_result()
+}
+
+/// List the products and categories with content which isn't translated to
+/// the locale, or to any of the locales if none is provided
+#[get("/missing")]
+async fn list_missing_translations(
+    ctx: web::Data<Context>,
+    query: web::Query<models::MissingTranslationsQuery>,
+) -> Result<HttpResponse, Error> {
+    let locales = match &query.locale {
+        Some(locale) => {
+            check_locale(&ctx, locale)?;
+            vec![locale.clone()]
+        }
+        None => ctx.config.locales[1..].to_vec(),
+    };
+    let missing = ctx
+        .db
+        .send(ListMissingTranslations { locales })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch missing translations");
+    Ok(HttpResponse::Ok().json(missing))
+}
+
+/// List the translations of a product
+#[get("/products/{product_id}")]
+async fn list_product_translations(
+    ctx: web::Data<Context>,
+    product_id: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let product_id = product_id.into_inner();
+    let translations = ctx
+        .db
+        .send(ListProductTranslations { product_id })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch product translations");
+    match translations {
+        Some(translations) => Ok(HttpResponse::Ok().json(translations)),
+        None => {
+            Ok(HttpResponse::NotFound().body(format!("No product found with id: {}", product_id)))
+        }
+    }
+}
+
+/// Add or replace the translation of a product to a locale
+#[put("/products/{product_id}/{locale}")]
+async fn set_product_translation(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    path: web::Path<(Uuid, String)>,
+    form: web::Json<models::ProductTranslationData>,
+) -> Result<HttpResponse, Error> {
+    let (product_id, locale) = path.into_inner();
+    check_locale(&ctx, &locale)?;
+    let mut form = form.into_inner();
+    form.validate().map_err(ValidationErrors::from)?;
+    form.product_id = product_id;
+    form.locale = locale;
+    let msg = SetProductTranslation {
+        author: principal.into_inner(),
+        data: form,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(translation) => Ok(HttpResponse::Ok().json(translation)),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!("No product found with id: {}", product_id)))
+        }
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Delete the translation of a product to a locale
+#[delete("/products/{product_id}/{locale}")]
+async fn delete_product_translation(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    path: web::Path<(Uuid, String)>,
+) -> Result<HttpResponse, Error> {
+    let (product_id, locale) = path.into_inner();
+    let msg = DeleteProductTranslation {
+        author: principal.into_inner(),
+        product_id,
+        locale: locale.clone(),
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!(
+                "No translation to {} found for product with id: {}",
+                locale, product_id
+            )))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
+
+/// List the translations of a category
+#[get("/categories/{category_id}")]
+async fn list_category_translations(
+    ctx: web::Data<Context>,
+    category_id: web::Path<Uuid>,
+) -> Result<HttpResponse, Error> {
+    let category_id = category_id.into_inner();
+    let translations = ctx
+        .db
+        .send(ListCategoryTranslations { category_id })
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch category translations");
+    match translations {
+        Some(translations) => Ok(HttpResponse::Ok().json(translations)),
+        None => Ok(
+            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id))
+        ),
+    }
+}
+
+/// Add or replace the translation of a category to a locale
+#[put("/categories/{category_id}/{locale}")]
+async fn set_category_translation(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    path: web::Path<(Uuid, String)>,
+    form: web::Json<models::CategoryTranslationData>,
+) -> Result<HttpResponse, Error> {
+    let (category_id, locale) = path.into_inner();
+    check_locale(&ctx, &locale)?;
+    let mut form = form.into_inner();
+    form.validate().map_err(ValidationErrors::from)?;
+    form.category_id = category_id;
+    form.locale = locale;
+    let msg = SetCategoryTranslation {
+        author: principal.into_inner(),
+        data: form,
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(translation) => Ok(HttpResponse::Ok().json(translation)),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => Ok(
+            HttpResponse::NotFound().body(format!("No category found with id: {}", category_id))
+        ),
+        Err(e) => Ok(typed_error_response(&e)
+            .unwrap_or_else(|| HttpResponse::InternalServerError().body(e.to_string()))),
+    }
+}
+
+/// Delete the translation of a category to a locale
+#[delete("/categories/{category_id}/{locale}")]
+async fn delete_category_translation(
+    ctx: web::Data<Context>,
+    principal: web::ReqData<Principal>,
+    path: web::Path<(Uuid, String)>,
+) -> Result<HttpResponse, Error> {
+    let (category_id, locale) = path.into_inner();
+    let msg = DeleteCategoryTranslation {
+        author: principal.into_inner(),
+        category_id,
+        locale: locale.clone(),
+    };
+    let result = ctx.db.send(msg).await.expect("Failed to contact DbActor");
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().finish()),
+        Err(e) if matches!(e.downcast_ref(), Some(NotFound)) => {
+            Ok(HttpResponse::NotFound().body(format!(
+                "No translation to {} found for category with id: {}",
+                locale, category_id
+            )))
+        }
+        Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+    }
+}
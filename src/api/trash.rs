@@ -0,0 +1,30 @@
+use actix_web::{get, web, Error, HttpResponse, Scope};
+
+use crate::db::trash::*;
+use crate::models;
+use crate::Context;
+
+pub fn admin_scope(path: &str) -> Scope {
+    web::scope(path).service(list_trash)
+}
+
+/// List deleted products, categories and manufacturers, most recently deleted first
+#[get("")]
+async fn list_trash(
+    ctx: web::Data<Context>,
+    filter: web::Query<models::TrashFilter>,
+) -> Result<HttpResponse, Error> {
+    let retention = chrono::Duration::from_std(ctx.config.trash_retention)
+        .expect("Trash retention is out of range");
+    let msg = ListTrash {
+        entity_type: filter.entity_type,
+        retention,
+    };
+    let items = ctx
+        .db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .expect("Failed to fetch trash");
+    Ok(HttpResponse::Ok().json(items))
+}
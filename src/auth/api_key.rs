@@ -0,0 +1,92 @@
+use actix_web::http::Method;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+/// Prefix of all generated API keys, which eases secret scanning
+const KEY_PREFIX: &str = "bjy_";
+
+/// Number of characters of the key which are stored to identify it
+const DISPLAY_PREFIX_LEN: usize = 12;
+
+/// Generates a new API key. Returns the key, its display prefix and its hash.
+pub fn generate_api_key() -> (String, String, String) {
+    let mut secret = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut secret);
+    let key = format!("{}{}", KEY_PREFIX, to_hex(&secret));
+    let prefix = key[..DISPLAY_PREFIX_LEN].to_string();
+    let hash = hash_api_key(&key);
+    (key, prefix, hash)
+}
+
+/// Keys are long random strings, so a fast hash is sufficient
+pub fn hash_api_key(key: &str) -> String {
+    to_hex(&Sha256::digest(key.as_bytes()))
+}
+
+/// Returns the scope an API key needs for a request on the admin API,
+/// e.g. "products:read" for GET /admin/products/{id}.
+pub fn required_scope(method: &Method, path: &str) -> String {
+    let resource = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .nth(1)
+        .unwrap_or_default();
+    let access = match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => "read",
+        _ => "write",
+    };
+    format!("{}:{}", resource, access)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::StatusCode, ResponseError};
+
+    use super::*;
+    use crate::auth::{AuthError, Principal};
+
+    #[test]
+    fn test_required_scope() {
+        let scope = required_scope(&Method::GET, "/admin/products/b144dca4");
+        assert_eq!(scope, "products:read");
+        let scope = required_scope(&Method::PUT, "/admin/products/b144dca4");
+        assert_eq!(scope, "products:write");
+        let scope = required_scope(&Method::DELETE, "/admin/api-keys/b144dca4");
+        assert_eq!(scope, "api-keys:write");
+    }
+
+    #[test]
+    fn test_check_scopes() {
+        let key = Principal {
+            issuer: "api-key".to_string(),
+            subject: "b144dca4".to_string(),
+            username: None,
+            roles: vec![],
+            scopes: Some(vec![
+                "api-keys:write".to_string(),
+                "products:write".to_string(),
+            ]),
+        };
+        let scopes = |s: &[&str]| s.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(key
+            .check_scopes(&scopes(&["products:read", "api-keys:write"]))
+            .is_ok());
+
+        // A key which manages keys can't mint a more powerful key
+        let err = key
+            .check_scopes(&scopes(&["products:read", "admin"]))
+            .unwrap_err();
+        assert!(matches!(&err, AuthError::MissingScope(scope) if scope == "admin"));
+        assert_eq!(err.status_code(), StatusCode::FORBIDDEN);
+
+        let user = Principal {
+            scopes: None,
+            ..key
+        };
+        assert!(user.check_scopes(&scopes(&["admin"])).is_ok());
+    }
+}
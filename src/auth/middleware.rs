@@ -0,0 +1,194 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix::Addr;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header;
+use actix_web::{Error, HttpMessage};
+use futures::future::{ok, LocalBoxFuture, Ready};
+use serde::Deserialize;
+
+use super::{hash_api_key, required_scope, AuthError, AuthProvider, Principal};
+use crate::db::api_keys::AuthenticateApiKey;
+use crate::db::DbActor;
+
+/// Header containing an API key, as alternative to a bearer token
+const API_KEY_HEADER: &str = "X-Api-Key";
+
+/// Middleware which requires a bearer token of one of the configured providers
+/// with the given role or, if enabled, an API key with the scope required for
+/// the request. On success, the `Principal` is stored in the request extensions
+/// and can be extracted in handlers with `web::ReqData<Principal>`.
+#[derive(Clone)]
+pub struct Authentication {
+    providers: Arc<Vec<Arc<dyn AuthProvider>>>,
+    required_role: String,
+    api_keys: Option<Addr<DbActor>>,
+}
+
+impl Authentication {
+    pub fn new(providers: Vec<Arc<dyn AuthProvider>>, required_role: &str) -> Self {
+        Self {
+            providers: Arc::new(providers),
+            required_role: required_role.to_string(),
+            api_keys: None,
+        }
+    }
+
+    /// Additionally accept API keys stored in the database
+    pub fn with_api_keys(mut self, db: Addr<DbActor>) -> Self {
+        self.api_keys = Some(db);
+        self
+    }
+}
+
+enum Credentials {
+    BearerToken(String),
+    ApiKey(String),
+}
+
+impl<S, B> Transform<S> for Authentication
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthenticationMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(AuthenticationMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            config: self.clone(),
+        })
+    }
+}
+
+pub struct AuthenticationMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    config: Authentication,
+}
+
+impl<S, B> Service for AuthenticationMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let config = self.config.clone();
+        let credentials = credentials(&req);
+        let required_scope = required_scope(req.method(), req.path());
+
+        Box::pin(async move {
+            let principal = match credentials? {
+                Credentials::BearerToken(token) => authenticate(&config, token).await,
+                Credentials::ApiKey(key) => {
+                    authenticate_api_key(&config, key, required_scope).await
+                }
+            }
+            .map_err(|e| {
+                log::debug!("Authentication failed: {}", e);
+                e
+            })?;
+            req.extensions_mut().insert(principal);
+            let res = service.borrow_mut().call(req);
+            res.await
+        })
+    }
+}
+
+/// Claims which are read before the token is validated to select the provider
+#[derive(Deserialize)]
+struct UnverifiedClaims {
+    iss: Option<String>,
+}
+
+async fn authenticate(config: &Authentication, token: String) -> Result<Principal, AuthError> {
+    // Select provider based on issuer
+    let issuer = jsonwebtoken::dangerous_insecure_decode::<UnverifiedClaims>(&token)
+        .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+        .claims
+        .iss
+        .unwrap_or_default();
+    let provider = config
+        .providers
+        .iter()
+        .find(|p| p.issuer() == issuer)
+        .ok_or(AuthError::UnknownIssuer(issuer))?;
+
+    // Validate token and check role
+    let principal = provider.authenticate(&token).await?;
+    if !principal.has_role(&config.required_role) {
+        return Err(AuthError::MissingRole(config.required_role.clone()));
+    }
+    Ok(principal)
+}
+
+async fn authenticate_api_key(
+    config: &Authentication,
+    key: String,
+    required_scope: String,
+) -> Result<Principal, AuthError> {
+    let db = config.api_keys.as_ref().ok_or(AuthError::InvalidApiKey)?;
+    let msg = AuthenticateApiKey {
+        key_hash: hash_api_key(&key),
+    };
+    let api_key = db
+        .send(msg)
+        .await
+        .expect("Failed to contact DbActor")
+        .map_err(|e| {
+            log::error!("Failed to authenticate API key: {}", e);
+            AuthError::InvalidApiKey
+        })?
+        .ok_or(AuthError::InvalidApiKey)?;
+
+    if !api_key.allows(&required_scope) {
+        return Err(AuthError::MissingScope(required_scope));
+    }
+    Ok(Principal {
+        issuer: "api-key".to_string(),
+        subject: api_key.id.to_string(),
+        username: Some(api_key.name),
+        roles: vec![],
+        scopes: Some(api_key.scopes),
+    })
+}
+
+fn credentials(req: &ServiceRequest) -> Result<Credentials, AuthError> {
+    if let Some(key) = req.headers().get(API_KEY_HEADER) {
+        let key = key.to_str().map_err(|_| AuthError::InvalidApiKey)?;
+        return Ok(Credentials::ApiKey(key.trim().to_string()));
+    }
+
+    let value = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .ok_or(AuthError::MissingAuthorizationHeader)?
+        .to_str()
+        .map_err(|_| AuthError::InvalidAuthorizationHeader)?;
+    let mut parts = value.splitn(2, ' ');
+    match (parts.next(), parts.next()) {
+        (Some(scheme), Some(token)) if scheme.eq_ignore_ascii_case("Bearer") => {
+            Ok(Credentials::BearerToken(token.trim().to_string()))
+        }
+        _ => Err(AuthError::InvalidAuthorizationHeader),
+    }
+}
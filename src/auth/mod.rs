@@ -0,0 +1,121 @@
+// Derives of failure predate this lint
+#![allow(non_local_definitions)]
+
+mod api_key;
+mod middleware;
+mod oidc;
+
+pub use self::{api_key::*, middleware::*, oidc::*};
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use failure::Fail;
+use futures::future::LocalBoxFuture;
+use serde::Serialize;
+
+use crate::models::scopes_allow;
+
+/// Authenticated caller of a protected endpoint
+#[derive(Debug, Clone, Serialize)]
+pub struct Principal {
+    /// Issuer which authenticated the principal
+    pub issuer: String,
+
+    /// Subject of the token (e.g. the Keycloak user ID)
+    pub subject: String,
+
+    /// Human readable name of the principal, if provided by the issuer
+    pub username: Option<String>,
+
+    /// Roles granted to the principal
+    pub roles: Vec<String>,
+
+    /// Scopes the principal is restricted to, e.g. for API keys
+    pub scopes: Option<Vec<String>>,
+}
+
+impl Principal {
+    /// Principal of changes made by the backend itself, e.g. by scheduled jobs
+    pub fn system(name: &str) -> Self {
+        Self {
+            issuer: "system".to_string(),
+            subject: name.to_string(),
+            username: Some(name.to_string()),
+            roles: Vec::new(),
+            scopes: None,
+        }
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// Checks if the principal holds all scopes, so it may grant them to an
+    /// API key. Principals which aren't restricted to scopes hold every scope.
+    pub fn check_scopes(&self, scopes: &[String]) -> Result<(), AuthError> {
+        let own = match &self.scopes {
+            Some(own) => own,
+            None => return Ok(()),
+        };
+        match scopes.iter().find(|scope| !scopes_allow(own, scope)) {
+            Some(scope) => Err(AuthError::MissingScope(scope.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Source of trusted identities, e.g. an OpenID Connect issuer
+pub trait AuthProvider: Send + Sync {
+    /// Value of the `iss` claim in tokens handled by this provider
+    fn issuer(&self) -> &str;
+
+    /// Validates the provided bearer token and returns the authenticated principal
+    fn authenticate(&self, token: &str) -> LocalBoxFuture<'static, Result<Principal, AuthError>>;
+}
+
+#[derive(Debug, Fail)]
+pub enum AuthError {
+    #[fail(display = "Authorization header is missing")]
+    MissingAuthorizationHeader,
+
+    #[fail(display = "Authorization header is not a valid bearer token")]
+    InvalidAuthorizationHeader,
+
+    #[fail(display = "Token is invalid: {}", _0)]
+    InvalidToken(String),
+
+    #[fail(display = "Token issuer is not trusted: {}", _0)]
+    UnknownIssuer(String),
+
+    #[fail(display = "Token is signed with an unknown key: {}", _0)]
+    UnknownKey(String),
+
+    #[fail(display = "Unable to fetch signing keys of issuer: {}", _0)]
+    KeySetUnavailable(String),
+
+    #[fail(display = "Required role is missing: {}", _0)]
+    MissingRole(String),
+
+    #[fail(display = "API key is invalid, expired or revoked")]
+    InvalidApiKey,
+
+    #[fail(display = "Required scope is missing: {}", _0)]
+    MissingScope(String),
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::MissingRole(_) | AuthError::MissingScope(_) => StatusCode::FORBIDDEN,
+            AuthError::KeySetUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = HttpResponse::build(self.status_code());
+        if self.status_code() == StatusCode::UNAUTHORIZED {
+            res.header("WWW-Authenticate", "Bearer");
+        }
+        res.body(self.to_string())
+    }
+}
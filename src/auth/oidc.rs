@@ -0,0 +1,444 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use actix_web::client::Client;
+use futures::future::LocalBoxFuture;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{AuthError, AuthProvider, Principal};
+
+/// Minimal time between two key set refreshes triggered by an unknown key ID
+const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximal time between two attempts to fetch a key set which keeps failing
+const MAX_REFRESH_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Algorithms which can be verified with the RSA keys of a key set
+const RSA_ALGORITHMS: [Algorithm; 6] = [
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+];
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+    /// Issuer URL, e.g. http://localhost:8091/auth/realms/bjoetiek
+    pub issuer_url: String,
+
+    /// Expected value in the `aud` claim
+    pub audience: String,
+
+    /// Dotted path to the claim containing the roles of the principal
+    pub roles_claim: String,
+
+    /// Claim containing a human readable name of the principal
+    pub username_claim: String,
+
+    /// Max age of the cached key set before it's fetched again
+    pub jwks_ttl: Duration,
+}
+
+impl OidcConfig {
+    /// Config for a Keycloak realm which exposes its realm roles in `realm_access.roles`
+    pub fn keycloak(realm_url: &str, audience: &str, jwks_ttl: Duration) -> Self {
+        Self {
+            issuer_url: realm_url.trim_end_matches('/').to_string(),
+            audience: audience.to_string(),
+            roles_claim: "realm_access.roles".to_string(),
+            username_claim: "preferred_username".to_string(),
+            jwks_ttl,
+        }
+    }
+}
+
+/// Validates tokens of an OpenID Connect issuer with the keys published in
+/// the JWKS referenced by its discovery document.
+#[derive(Clone)]
+pub struct OidcProvider {
+    config: Arc<OidcConfig>,
+    key_set: Arc<RwLock<KeySet>>,
+}
+
+#[derive(Default)]
+struct KeySet {
+    keys: HashMap<Option<String>, DecodingKey<'static>>,
+    fetched_at: Option<Instant>,
+    last_attempt: Option<Instant>,
+
+    /// Number of consecutive failed fetches
+    failures: u32,
+}
+
+impl KeySet {
+    /// Time to wait after the last attempt before fetching again. Doubles
+    /// with every consecutive failure.
+    fn backoff(&self) -> Duration {
+        let factor = 2u32.saturating_pow(self.failures);
+        MIN_REFRESH_INTERVAL
+            .saturating_mul(factor)
+            .min(MAX_REFRESH_BACKOFF)
+    }
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    issuer: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct JsonWebKeySet {
+    keys: Vec<JsonWebKey>,
+}
+
+#[derive(Deserialize)]
+struct JsonWebKey {
+    kty: String,
+    kid: Option<String>,
+    #[serde(rename = "use")]
+    key_use: Option<String>,
+    n: Option<String>,
+    e: Option<String>,
+}
+
+impl OidcProvider {
+    pub fn new(config: OidcConfig) -> Self {
+        Self {
+            config: Arc::new(config),
+            key_set: Arc::new(RwLock::new(KeySet::default())),
+        }
+    }
+
+    /// Returns the cached key for the provided key ID, if the cache is still fresh
+    fn cached_key(&self, kid: &Option<String>) -> Option<DecodingKey<'static>> {
+        let key_set = self.key_set.read().unwrap();
+        let is_fresh = key_set
+            .fetched_at
+            .map(|t| t.elapsed() < self.config.jwks_ttl)
+            .unwrap_or(false);
+        if !is_fresh {
+            return None;
+        }
+
+        // Tokens without a key ID are accepted if the key set has a single key
+        match kid {
+            Some(_) => key_set.keys.get(kid).cloned(),
+            None if key_set.keys.len() == 1 => key_set.keys.values().next().cloned(),
+            None => None,
+        }
+    }
+
+    /// Fetches the key set of the issuer, unless it was attempted very
+    /// recently. The cached keys are kept while throttled, if there are any.
+    async fn refresh_key_set(&self) -> Result<(), AuthError> {
+        {
+            let mut key_set = self.key_set.write().unwrap();
+            let backoff = key_set.backoff();
+            let throttled = key_set
+                .last_attempt
+                .map(|t| t.elapsed() < backoff)
+                .unwrap_or(false);
+            if throttled && key_set.fetched_at.is_some() {
+                return Ok(());
+            }
+            if throttled {
+                let msg = format!(
+                    "retrying {} within {} seconds",
+                    self.config.issuer_url,
+                    backoff.as_secs()
+                );
+                return Err(AuthError::KeySetUnavailable(msg));
+            }
+            key_set.last_attempt = Some(Instant::now());
+        }
+
+        let result = fetch_keys(&self.config.issuer_url).await;
+        let mut key_set = self.key_set.write().unwrap();
+        match result {
+            Ok(keys) => {
+                log::info!(
+                    "Fetched {} signing key(s) of {}",
+                    keys.len(),
+                    self.config.issuer_url
+                );
+                key_set.keys = keys;
+                key_set.fetched_at = Some(Instant::now());
+                key_set.failures = 0;
+                Ok(())
+            }
+            Err(e) => {
+                log::warn!(
+                    "Failed to fetch key set of {}: {}",
+                    self.config.issuer_url,
+                    e
+                );
+                key_set.failures = key_set.failures.saturating_add(1);
+                Err(e)
+            }
+        }
+    }
+
+    async fn find_key(&self, kid: &Option<String>) -> Result<DecodingKey<'static>, AuthError> {
+        if let Some(key) = self.cached_key(kid) {
+            return Ok(key);
+        }
+
+        // Key set is stale or key is unknown (e.g. rotated by issuer)
+        self.refresh_key_set().await?;
+        let key_set = self.key_set.read().unwrap();
+        match kid {
+            Some(_) => key_set.keys.get(kid).cloned(),
+            None if key_set.keys.len() == 1 => key_set.keys.values().next().cloned(),
+            None => None,
+        }
+        .ok_or_else(|| AuthError::UnknownKey(kid.clone().unwrap_or_else(|| "<none>".into())))
+    }
+
+    async fn validate(&self, token: String) -> Result<Principal, AuthError> {
+        // Select key
+        let header = decode_header(&token).map_err(|e| AuthError::InvalidToken(e.to_string()))?;
+        if !RSA_ALGORITHMS.contains(&header.alg) {
+            let msg = format!("Algorithm {:?} is not supported", header.alg);
+            return Err(AuthError::InvalidToken(msg));
+        }
+        let key = self.find_key(&header.kid).await?;
+
+        // Validate token
+        let mut validation = Validation::new(header.alg);
+        validation.iss = Some(self.config.issuer_url.clone());
+        validation.set_audience(&[&self.config.audience]);
+        let claims = decode::<HashMap<String, Value>>(&token, &key, &validation)
+            .map_err(|e| AuthError::InvalidToken(e.to_string()))?
+            .claims;
+
+        // Build principal
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .ok_or_else(|| AuthError::InvalidToken("Claim sub is missing".into()))?;
+        let username = claims
+            .get(&self.config.username_claim)
+            .and_then(Value::as_str);
+        let roles = lookup_claim(&claims, &self.config.roles_claim)
+            .and_then(Value::as_array)
+            .map(|roles| {
+                roles
+                    .iter()
+                    .filter_map(Value::as_str)
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Principal {
+            issuer: self.config.issuer_url.clone(),
+            subject: subject.to_string(),
+            username: username.map(String::from),
+            roles,
+            scopes: None,
+        })
+    }
+}
+
+impl AuthProvider for OidcProvider {
+    fn issuer(&self) -> &str {
+        &self.config.issuer_url
+    }
+
+    fn authenticate(&self, token: &str) -> LocalBoxFuture<'static, Result<Principal, AuthError>> {
+        let provider = self.clone();
+        let token = token.to_string();
+        Box::pin(async move { provider.validate(token).await })
+    }
+}
+
+/// Resolves the JWKS of the issuer through its discovery document and
+/// returns the contained RSA signing keys by key ID.
+async fn fetch_keys(
+    issuer_url: &str,
+) -> Result<HashMap<Option<String>, DecodingKey<'static>>, AuthError> {
+    // Fetch discovery document
+    let discovery_url = format!("{}/.well-known/openid-configuration", issuer_url);
+    let discovery: DiscoveryDocument = fetch_json(&discovery_url).await?;
+    if discovery.issuer != issuer_url {
+        let msg = format!("Discovery document is for issuer {}", discovery.issuer);
+        return Err(AuthError::KeySetUnavailable(msg));
+    }
+
+    // Fetch key set
+    let jwks: JsonWebKeySet = fetch_json(&discovery.jwks_uri).await?;
+    let keys = jwks
+        .keys
+        .into_iter()
+        .filter(|k| k.kty == "RSA" && k.key_use.as_deref().unwrap_or("sig") == "sig")
+        .filter_map(|k| match (k.n, k.e) {
+            (Some(n), Some(e)) => {
+                let key = DecodingKey::from_rsa_components(&n, &e).into_static();
+                Some((k.kid, key))
+            }
+            _ => None,
+        })
+        .collect();
+    Ok(keys)
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(url: &str) -> Result<T, AuthError> {
+    let mut res = Client::default()
+        .get(url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| AuthError::KeySetUnavailable(format!("{}: {}", url, e)))?;
+    if !res.status().is_success() {
+        let msg = format!("{} returned status {}", url, res.status());
+        return Err(AuthError::KeySetUnavailable(msg));
+    }
+
+    // Content type is not checked as key sets are served with various types
+    let body = res
+        .body()
+        .limit(1 << 20)
+        .await
+        .map_err(|e| AuthError::KeySetUnavailable(format!("{}: {}", url, e)))?;
+    serde_json::from_slice(&body)
+        .map_err(|e| AuthError::KeySetUnavailable(format!("{}: {}", url, e)))
+}
+
+/// Looks up a claim by a dotted path, e.g. "realm_access.roles"
+fn lookup_claim<'a>(claims: &'a HashMap<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut parts = path.split('.');
+    let mut value = claims.get(parts.next()?)?;
+    for part in parts {
+        value = value.get(part)?;
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use actix_web::{web, App, HttpResponse, HttpServer};
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    use super::*;
+
+    const KEY_1_PEM: &str = include_str!("testdata/rsa_key_1.pem");
+    const KEY_1_N: &str = include_str!("testdata/rsa_key_1.n");
+    const KEY_2_PEM: &str = include_str!("testdata/rsa_key_2.pem");
+    const KEY_2_N: &str = include_str!("testdata/rsa_key_2.n");
+
+    /// Starts a stand-in issuer which serves a discovery document and the
+    /// key set currently stored in the returned mutex.
+    fn start_issuer() -> (String, Arc<Mutex<Value>>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer_url = format!("http://{}", listener.local_addr().unwrap());
+        let jwks = Arc::new(Mutex::new(json!({ "keys": [] })));
+
+        let (url, keys) = (issuer_url.clone(), jwks.clone());
+        let server = HttpServer::new(move || {
+            let (url, keys) = (url.clone(), keys.clone());
+            App::new()
+                .route(
+                    "/.well-known/openid-configuration",
+                    web::get().to(move || {
+                        let doc = json!({ "issuer": url, "jwks_uri": format!("{}/certs", url) });
+                        HttpResponse::Ok().json(doc)
+                    }),
+                )
+                .route(
+                    "/certs",
+                    web::get().to(move || HttpResponse::Ok().json(&*keys.lock().unwrap())),
+                )
+        })
+        .listen(listener)
+        .unwrap()
+        .run();
+        actix_rt::spawn(async move {
+            let _ = server.await;
+        });
+
+        (issuer_url, jwks)
+    }
+
+    fn jwk(kid: &str, n: &str) -> Value {
+        json!({ "kty": "RSA", "kid": kid, "use": "sig", "alg": "RS256", "n": n, "e": "AQAB" })
+    }
+
+    fn sign(kid: &str, pem: &str, claims: Value) -> String {
+        let mut header = Header::new(Algorithm::RS256);
+        header.kid = Some(kid.to_string());
+        let key = EncodingKey::from_rsa_pem(pem.as_bytes()).unwrap();
+        encode(&header, &claims, &key).unwrap()
+    }
+
+    fn claims(issuer: &str, audience: &str, expires_in: i64) -> Value {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        json!({
+            "iss": issuer,
+            "aud": audience,
+            "sub": "user-1",
+            "exp": now.as_secs() as i64 + expires_in,
+            "preferred_username": "jens",
+            "realm_access": { "roles": ["admin"] },
+        })
+    }
+
+    #[actix_rt::test]
+    async fn test_oidc_provider() {
+        let (issuer, jwks) = start_issuer();
+        *jwks.lock().unwrap() = json!({ "keys": [jwk("key-1", KEY_1_N)] });
+        let config = OidcConfig::keycloak(&issuer, "bjoetiek", Duration::from_secs(3600));
+        let provider = OidcProvider::new(config);
+
+        // Valid token
+        let token = sign("key-1", KEY_1_PEM, claims(&issuer, "bjoetiek", 60));
+        let principal = provider.authenticate(&token).await.unwrap();
+        assert_eq!(principal.subject, "user-1");
+        assert_eq!(principal.username.as_deref(), Some("jens"));
+        assert!(principal.has_role("admin"));
+
+        // Invalid issuer, audience and expiry
+        let token = sign("key-1", KEY_1_PEM, claims("http://evil", "bjoetiek", 60));
+        assert!(provider.authenticate(&token).await.is_err());
+        let token = sign("key-1", KEY_1_PEM, claims(&issuer, "other", 60));
+        assert!(provider.authenticate(&token).await.is_err());
+        let token = sign("key-1", KEY_1_PEM, claims(&issuer, "bjoetiek", -60));
+        assert!(provider.authenticate(&token).await.is_err());
+
+        // Key signed with unpublished key
+        let token = sign("key-1", KEY_2_PEM, claims(&issuer, "bjoetiek", 60));
+        assert!(provider.authenticate(&token).await.is_err());
+
+        // Rotated key is picked up
+        *jwks.lock().unwrap() = json!({ "keys": [jwk("key-1", KEY_1_N), jwk("key-2", KEY_2_N)] });
+        provider.key_set.write().unwrap().last_attempt = None;
+        let token = sign("key-2", KEY_2_PEM, claims(&issuer, "bjoetiek", 60));
+        let principal = provider.authenticate(&token).await.unwrap();
+        assert_eq!(principal.subject, "user-1");
+    }
+
+    #[actix_rt::test]
+    async fn test_backoff_after_failed_fetch() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let issuer = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let config = OidcConfig::keycloak(&issuer, "bjoetiek", Duration::from_secs(3600));
+        let provider = OidcProvider::new(config);
+
+        // Only the first request tries to fetch the unreachable key set
+        let token = sign("key-1", KEY_1_PEM, claims(&issuer, "bjoetiek", 60));
+        assert!(provider.authenticate(&token).await.is_err());
+        assert!(provider.authenticate(&token).await.is_err());
+        let key_set = provider.key_set.read().unwrap();
+        assert_eq!(key_set.failures, 1);
+        assert_eq!(key_set.backoff(), MIN_REFRESH_INTERVAL * 2);
+    }
+}
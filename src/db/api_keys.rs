@@ -0,0 +1,124 @@
+use actix::{Handler, Message};
+use diesel::{dsl::now, prelude::*};
+use failure::Error;
+
+use super::audit::{self, Action, EntityType};
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{ApiKey, ApiKeyData};
+use crate::schema::api_keys::dsl;
+
+#[derive(Debug)]
+pub struct ListApiKeys {}
+
+impl Message for ListApiKeys {
+    type Result = Result<Vec<ApiKey>, Error>;
+}
+
+impl Handler<ListApiKeys> for DbActor {
+    type Result = Result<Vec<ApiKey>, Error>;
+
+    fn handle(&mut self, _msg: ListApiKeys, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let api_keys = dsl::api_keys.order(dsl::created_at).load::<ApiKey>(&conn)?;
+
+        Ok(api_keys)
+    }
+}
+
+#[derive(Debug)]
+pub struct InsertApiKey {
+    pub author: Principal,
+    pub data: ApiKeyData,
+}
+
+impl Message for InsertApiKey {
+    type Result = Result<ApiKey, Error>;
+}
+
+impl Handler<InsertApiKey> for DbActor {
+    type Result = Result<ApiKey, Error>;
+
+    fn handle(&mut self, msg: InsertApiKey, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let api_key = diesel::insert_into(dsl::api_keys)
+                .values(&msg.data)
+                .get_result::<ApiKey>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::ApiKey,
+                api_key.id,
+                Action::Create,
+                None,
+                Some(&api_key),
+            )?;
+            Ok(api_key)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RevokeApiKey {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+}
+
+impl Message for RevokeApiKey {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<RevokeApiKey> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: RevokeApiKey, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::api_keys
+                .find(msg.id)
+                .filter(dsl::revoked_at.is_null())
+                .first::<ApiKey>(&conn)?;
+            let api_key = diesel::update(dsl::api_keys.find(msg.id))
+                .set(dsl::revoked_at.eq(now))
+                .get_result::<ApiKey>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::ApiKey,
+                msg.id,
+                Action::Revoke,
+                Some(&before),
+                Some(&api_key),
+            )
+        })
+    }
+}
+
+/// Finds the active (not revoked or expired) key with the provided hash and
+/// marks it as used.
+#[derive(Debug)]
+pub struct AuthenticateApiKey {
+    pub key_hash: String,
+}
+
+impl Message for AuthenticateApiKey {
+    type Result = Result<Option<ApiKey>, Error>;
+}
+
+impl Handler<AuthenticateApiKey> for DbActor {
+    type Result = Result<Option<ApiKey>, Error>;
+
+    fn handle(&mut self, msg: AuthenticateApiKey, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let active_key = dsl::api_keys
+            .filter(dsl::key_hash.eq(msg.key_hash))
+            .filter(dsl::revoked_at.is_null())
+            .filter(dsl::expires_at.is_null().or(dsl::expires_at.gt(now)));
+        diesel::update(active_key)
+            .set(dsl::last_used_at.eq(now))
+            .get_result(&conn)
+            .optional()
+            .map_err(Error::from)
+    }
+}
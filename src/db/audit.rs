@@ -0,0 +1,177 @@
+use actix::{Handler, Message};
+use diesel::prelude::*;
+use failure::Error;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::helpers;
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{AuditEntry, AuditEntryData, AuditFilter};
+use crate::schema::audit_log::dsl;
+
+/// Max number of entries returned by ListAuditEntries
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub enum EntityType {
+    ApiKey,
+    Category,
+    Coupon,
+    ExchangeRate,
+    Image,
+    Manufacturer,
+    Order,
+    Product,
+    Promotion,
+}
+
+impl EntityType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntityType::ApiKey => "api_key",
+            EntityType::Category => "category",
+            EntityType::Coupon => "coupon",
+            EntityType::ExchangeRate => "exchange_rate",
+            EntityType::Image => "image",
+            EntityType::Manufacturer => "manufacturer",
+            EntityType::Order => "order",
+            EntityType::Product => "product",
+            EntityType::Promotion => "promotion",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+    Create,
+    Update,
+    Delete,
+    Upload,
+    Revoke,
+    Restore,
+}
+
+impl Action {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Delete => "delete",
+            Action::Upload => "upload",
+            Action::Revoke => "revoke",
+            Action::Restore => "restore",
+        }
+    }
+}
+
+/// Appends an audit entry with the diff between the serialized before and
+/// after state of the entity. Should be called inside the transaction of the
+/// mutation.
+pub fn record<T: Serialize>(
+    conn: &PgConnection,
+    author: &Principal,
+    entity_type: EntityType,
+    entity_id: Uuid,
+    action: Action,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<(), Error> {
+    let before = before.map(serde_json::to_value).transpose()?;
+    let after = after.map(serde_json::to_value).transpose()?;
+    let diff = helpers::json_diff(
+        &before.unwrap_or(Value::Null),
+        &after.unwrap_or(Value::Null),
+    );
+
+    let entry = AuditEntryData {
+        user_issuer: author.issuer.clone(),
+        user_subject: author.subject.clone(),
+        user_name: author.username.clone(),
+        entity_type: entity_type.as_str().to_string(),
+        entity_id,
+        action: action.as_str().to_string(),
+        diff,
+    };
+    diesel::insert_into(dsl::audit_log)
+        .values(entry)
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Records a mutation which doesn't pass through the DbActor (e.g. images)
+#[derive(Debug)]
+pub struct InsertAuditEntry {
+    pub author: Principal,
+    pub entity_type: EntityType,
+    pub entity_id: Uuid,
+    pub action: Action,
+    pub after: Value,
+}
+
+impl Message for InsertAuditEntry {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<InsertAuditEntry> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: InsertAuditEntry, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        record(
+            &conn,
+            &msg.author,
+            msg.entity_type,
+            msg.entity_id,
+            msg.action,
+            None,
+            Some(&msg.after),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct ListAuditEntries {
+    pub filter: AuditFilter,
+}
+
+impl Message for ListAuditEntries {
+    type Result = Result<Vec<AuditEntry>, Error>;
+}
+
+impl Handler<ListAuditEntries> for DbActor {
+    type Result = Result<Vec<AuditEntry>, Error>;
+
+    fn handle(&mut self, msg: ListAuditEntries, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let filter = msg.filter;
+        let mut query = dsl::audit_log.into_boxed();
+        if let Some(entity_type) = filter.entity_type {
+            query = query.filter(dsl::entity_type.eq(entity_type));
+        }
+        if let Some(entity_id) = filter.entity_id {
+            query = query.filter(dsl::entity_id.eq(entity_id));
+        }
+        if let Some(user) = filter.user {
+            query = query.filter(
+                dsl::user_subject
+                    .eq(user.clone())
+                    .or(dsl::user_name.eq(user)),
+            );
+        }
+        if let Some(from) = filter.from {
+            query = query.filter(dsl::created_at.ge(from));
+        }
+        if let Some(to) = filter.to {
+            query = query.filter(dsl::created_at.lt(to));
+        }
+
+        let entries = query
+            .order(dsl::created_at.desc())
+            .limit(filter.limit.unwrap_or(100).min(MAX_LIMIT))
+            .offset(filter.offset.unwrap_or(0))
+            .load::<AuditEntry>(&conn)?;
+        Ok(entries)
+    }
+}
@@ -0,0 +1,543 @@
+use actix::{Handler, Message};
+use chrono::{NaiveDateTime, Utc};
+use diesel::result::Error::NotFound;
+use diesel::{dsl::exists, prelude::*};
+use failure::Error;
+use uuid::Uuid;
+
+use super::audit::{self, Action, EntityType};
+use super::products::is_published;
+use super::promotions;
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{
+    normalize_coupon_code, Cart, CartCoupon, CartItem, CartLine, CartView, CategoryProduct, Coupon,
+    CouponUsage, Order, OrderData, OrderItem, OrderTax, OrderTotals, OrderWithItems, Product,
+    TaxAmounts, ValidationErrors,
+};
+use crate::schema::cart_items::dsl as ci_dsl;
+use crate::schema::carts::dsl;
+use crate::schema::category_products::dsl as cp_dsl;
+use crate::schema::coupons::dsl as co_dsl;
+use crate::schema::order_items::dsl as oi_dsl;
+use crate::schema::order_taxes::dsl as ot_dsl;
+use crate::schema::orders::dsl as o_dsl;
+use crate::schema::products::dsl as p_dsl;
+
+#[derive(Debug)]
+pub struct CreateCart {
+    /// Shipping cost in cents of orders without free shipping
+    pub shipping_cost: i32,
+}
+
+impl Message for CreateCart {
+    type Result = Result<CartView, Error>;
+}
+
+impl Handler<CreateCart> for DbActor {
+    type Result = Result<CartView, Error>;
+
+    fn handle(&mut self, msg: CreateCart, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let cart = diesel::insert_into(dsl::carts)
+            .default_values()
+            .get_result::<Cart>(&conn)?;
+        cart_view(&conn, cart, msg.shipping_cost, self.prices_include_tax)
+    }
+}
+
+#[derive(Debug)]
+pub struct GetCart {
+    pub id: Uuid,
+    pub shipping_cost: i32,
+}
+
+impl Message for GetCart {
+    type Result = Result<CartView, Error>;
+}
+
+impl Handler<GetCart> for DbActor {
+    type Result = Result<CartView, Error>;
+
+    fn handle(&mut self, msg: GetCart, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let cart = dsl::carts.find(msg.id).first::<Cart>(&conn)?;
+        cart_view(&conn, cart, msg.shipping_cost, self.prices_include_tax)
+    }
+}
+
+/// Adds a product to the cart or changes its quantity
+#[derive(Debug)]
+pub struct SetCartItem {
+    pub cart_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: i32,
+    pub shipping_cost: i32,
+}
+
+impl Message for SetCartItem {
+    type Result = Result<CartView, Error>;
+}
+
+impl Handler<SetCartItem> for DbActor {
+    type Result = Result<CartView, Error>;
+
+    fn handle(&mut self, msg: SetCartItem, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            dsl::carts.find(msg.cart_id).first::<Cart>(&conn)?;
+            let product = p_dsl::products
+                .find(msg.product_id)
+                .filter(p_dsl::status.ne("ARCHIVED"))
+                .filter(p_dsl::deleted_at.is_null())
+                .filter(is_published(Utc::now().naive_utc()));
+            let available: bool = diesel::select(exists(product)).get_result(&conn)?;
+            if !available {
+                let mut errors = ValidationErrors::new();
+                let message = format!("Product {} isn't available", msg.product_id);
+                errors.add("product_id", message);
+                return Err(errors.into());
+            }
+
+            let item = CartItem {
+                cart_id: msg.cart_id,
+                product_id: msg.product_id,
+                quantity: msg.quantity,
+            };
+            diesel::insert_into(ci_dsl::cart_items)
+                .values(&item)
+                .on_conflict((ci_dsl::cart_id, ci_dsl::product_id))
+                .do_update()
+                .set(ci_dsl::quantity.eq(msg.quantity))
+                .execute(&conn)?;
+            let cart = touch_cart(&conn, msg.cart_id)?;
+            cart_view(&conn, cart, msg.shipping_cost, self.prices_include_tax)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoveCartItem {
+    pub cart_id: Uuid,
+    pub product_id: Uuid,
+    pub shipping_cost: i32,
+}
+
+impl Message for RemoveCartItem {
+    type Result = Result<CartView, Error>;
+}
+
+impl Handler<RemoveCartItem> for DbActor {
+    type Result = Result<CartView, Error>;
+
+    fn handle(&mut self, msg: RemoveCartItem, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let item = ci_dsl::cart_items.find((msg.cart_id, msg.product_id));
+            diesel::delete(item).execute(&conn)?;
+            let cart = touch_cart(&conn, msg.cart_id)?;
+            cart_view(&conn, cart, msg.shipping_cost, self.prices_include_tax)
+        })
+    }
+}
+
+/// Applies a coupon to the cart. Fails with a `ValidationErrors` which
+/// explains why the coupon was rejected.
+#[derive(Debug)]
+pub struct ApplyCoupon {
+    pub cart_id: Uuid,
+    pub code: String,
+    pub shipping_cost: i32,
+}
+
+impl Message for ApplyCoupon {
+    type Result = Result<CartView, Error>;
+}
+
+impl Handler<ApplyCoupon> for DbActor {
+    type Result = Result<CartView, Error>;
+
+    fn handle(&mut self, msg: ApplyCoupon, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            dsl::carts.find(msg.cart_id).first::<Cart>(&conn)?;
+            let mut errors = ValidationErrors::new();
+            let coupon = co_dsl::coupons
+                .filter(co_dsl::code.eq(normalize_coupon_code(&msg.code)))
+                .first::<Coupon>(&conn)
+                .optional()?;
+            let coupon = match coupon {
+                Some(coupon) => coupon,
+                None => {
+                    errors.add("code", "Coupon doesn't exist");
+                    return Err(errors.into());
+                }
+            };
+
+            let now = Utc::now().naive_utc();
+            let lines = cart_lines(&conn, msg.cart_id, now, self.prices_include_tax)?;
+            let usage = coupon_usage(&conn, coupon.id, None)?;
+            if let Err(rejection) = coupon.evaluate(&lines, now, usage) {
+                errors.add("code", rejection.to_string());
+                return Err(errors.into());
+            }
+
+            let cart = diesel::update(dsl::carts.find(msg.cart_id))
+                .set(dsl::coupon_id.eq(coupon.id))
+                .get_result::<Cart>(&conn)?;
+            cart_view(&conn, cart, msg.shipping_cost, self.prices_include_tax)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RemoveCoupon {
+    pub cart_id: Uuid,
+    pub shipping_cost: i32,
+}
+
+impl Message for RemoveCoupon {
+    type Result = Result<CartView, Error>;
+}
+
+impl Handler<RemoveCoupon> for DbActor {
+    type Result = Result<CartView, Error>;
+
+    fn handle(&mut self, msg: RemoveCoupon, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let cart = diesel::update(dsl::carts.find(msg.cart_id))
+            .set(dsl::coupon_id.eq(None::<Uuid>))
+            .get_result::<Cart>(&conn)?;
+        cart_view(&conn, cart, msg.shipping_cost, self.prices_include_tax)
+    }
+}
+
+/// Turns the cart into an order, takes the ordered quantities from the stock
+/// and deletes the cart. Fails with a `ValidationErrors` if the cart is empty,
+/// a product is no longer available or doesn't have enough stock, or the
+/// coupon is rejected.
+#[derive(Debug)]
+pub struct Checkout {
+    pub cart_id: Uuid,
+    pub customer_email: String,
+    pub shipping_cost: i32,
+
+    /// Base currency, in which orders are settled
+    pub currency: String,
+}
+
+impl Message for Checkout {
+    type Result = Result<OrderWithItems, Error>;
+}
+
+impl Handler<Checkout> for DbActor {
+    type Result = Result<OrderWithItems, Error>;
+
+    fn handle(&mut self, msg: Checkout, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let cart = dsl::carts
+                .find(msg.cart_id)
+                .for_update()
+                .first::<Cart>(&conn)?;
+
+            // Lock the products, so concurrent orders can't exceed their stock
+            let product_ids = ci_dsl::cart_items
+                .filter(ci_dsl::cart_id.eq(cart.id))
+                .select(ci_dsl::product_id);
+            let stock = p_dsl::products
+                .filter(p_dsl::id.eq_any(product_ids))
+                .order(p_dsl::id)
+                .select((p_dsl::id, p_dsl::name, p_dsl::stock_count))
+                .for_update()
+                .load::<(Uuid, String, i32)>(&conn)?;
+
+            let now = Utc::now().naive_utc();
+            let lines = cart_lines(&conn, cart.id, now, self.prices_include_tax)?;
+            let mut errors = ValidationErrors::new();
+            if stock.is_empty() {
+                errors.add("items", "Cart is empty");
+                return Err(errors.into());
+            }
+            for (id, name, _) in &stock {
+                if !lines.iter().any(|line| line.product_id == *id) {
+                    errors.add("items", format!("{} is no longer available", name));
+                }
+            }
+            for line in &lines {
+                let available = stock
+                    .iter()
+                    .find(|(id, _, _)| *id == line.product_id)
+                    .map_or(0, |(_, _, count)| *count);
+                if line.quantity > available {
+                    let message = format!("Only {} of {} in stock", available.max(0), line.name);
+                    errors.add("items", message);
+                }
+            }
+            if !errors.is_empty() {
+                return Err(errors.into());
+            }
+
+            // Lock the coupon, so concurrent orders can't exceed its usage limits
+            let customer_email = msg.customer_email.trim().to_lowercase();
+            let coupon = match cart.coupon_id {
+                Some(id) => Some(
+                    co_dsl::coupons
+                        .find(id)
+                        .for_update()
+                        .first::<Coupon>(&conn)?,
+                ),
+                None => None,
+            };
+            let discount = match &coupon {
+                Some(coupon) => {
+                    let usage = coupon_usage(&conn, coupon.id, Some(&customer_email))?;
+                    match coupon.evaluate(&lines, now, usage) {
+                        Ok(discount) => Some(discount),
+                        Err(rejection) => {
+                            errors.add("code", rejection.to_string());
+                            return Err(errors.into());
+                        }
+                    }
+                }
+                None => None,
+            };
+
+            let totals = OrderTotals::new(
+                &lines,
+                discount.as_ref(),
+                msg.shipping_cost,
+                self.prices_include_tax,
+            )?;
+            let data = OrderData {
+                customer_email,
+                coupon_id: coupon.as_ref().map(|c| c.id),
+                coupon_code: coupon.map(|c| c.code),
+                subtotal: totals.subtotal,
+                discount: totals.discount,
+                shipping: totals.shipping,
+                total: totals.total,
+                prices_include_tax: self.prices_include_tax,
+                net: totals.net,
+                tax: totals.tax,
+                currency: msg.currency.clone(),
+            };
+            let order = diesel::insert_into(o_dsl::orders)
+                .values(&data)
+                .get_result::<Order>(&conn)?;
+            let items: Vec<OrderItem> = lines
+                .into_iter()
+                .enumerate()
+                .map(|(i, line)| OrderItem {
+                    order_id: order.id,
+                    line: i as i32 + 1,
+                    product_id: Some(line.product_id),
+                    name: line.name,
+                    quantity: line.quantity,
+                    unit_price: line.unit_price,
+                    total: line.total,
+                    tax_rate: line.tax_rate,
+                    net: line.tax_breakdown.net,
+                    tax: line.tax_breakdown.tax,
+                })
+                .collect();
+            diesel::insert_into(oi_dsl::order_items)
+                .values(&items)
+                .execute(&conn)?;
+            let taxes: Vec<OrderTax> = totals
+                .taxes
+                .into_iter()
+                .map(|line| OrderTax {
+                    order_id: order.id,
+                    rate: line.rate,
+                    net: line.amounts.net,
+                    tax: line.amounts.tax,
+                    gross: line.amounts.gross,
+                })
+                .collect();
+            diesel::insert_into(ot_dsl::order_taxes)
+                .values(&taxes)
+                .execute(&conn)?;
+
+            // Stock isn't versioned, so taking it doesn't conflict with edits
+            // of the product
+            for item in &items {
+                let product_id = item.product_id.ok_or(NotFound)?;
+                let taken = diesel::update(
+                    p_dsl::products
+                        .find(product_id)
+                        .filter(p_dsl::stock_count.ge(item.quantity)),
+                )
+                .set(p_dsl::stock_count.eq(p_dsl::stock_count - item.quantity))
+                .execute(&conn)?;
+                if taken == 0 {
+                    errors.add("items", format!("{} is out of stock", item.name));
+                    return Err(errors.into());
+                }
+            }
+            diesel::delete(dsl::carts.find(cart.id)).execute(&conn)?;
+            let order = OrderWithItems {
+                order,
+                items,
+                taxes,
+            };
+            audit::record(
+                &conn,
+                &Principal::system("checkout"),
+                EntityType::Order,
+                order.order.id,
+                Action::Create,
+                None,
+                Some(&order),
+            )?;
+            Ok(order)
+        })
+    }
+}
+
+/// Deletes the carts which weren't changed since the moment, together with
+/// their items. Returns the number of deleted carts.
+#[derive(Debug)]
+pub struct PurgeCarts {
+    pub before: NaiveDateTime,
+}
+
+impl Message for PurgeCarts {
+    type Result = Result<usize, Error>;
+}
+
+impl Handler<PurgeCarts> for DbActor {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: PurgeCarts, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let purged =
+            diesel::delete(dsl::carts.filter(dsl::updated_at.lt(msg.before))).execute(&conn)?;
+        Ok(purged)
+    }
+}
+
+/// Builds the view of the cart with the current prices. Products which are no
+/// longer available are left out.
+fn cart_view(
+    conn: &PgConnection,
+    cart: Cart,
+    shipping_cost: i32,
+    prices_include_tax: bool,
+) -> Result<CartView, Error> {
+    let now = Utc::now().naive_utc();
+    let lines = cart_lines(conn, cart.id, now, prices_include_tax)?;
+    let (coupon, discount) = match cart.coupon_id {
+        Some(id) => {
+            let coupon = co_dsl::coupons.find(id).first::<Coupon>(conn)?;
+            let usage = coupon_usage(conn, id, None)?;
+            let result = coupon.evaluate(&lines, now, usage);
+            let coupon = CartCoupon {
+                code: coupon.code,
+                discount_type: coupon.discount_type,
+                discount_value: coupon.discount_value,
+                rejection: result.as_ref().err().map(|r| r.to_string()),
+            };
+            (Some(coupon), result.ok())
+        }
+        None => (None, None),
+    };
+    Ok(CartView {
+        id: cart.id,
+        created_at: cart.created_at,
+        updated_at: cart.updated_at,
+        totals: OrderTotals::new(&lines, discount.as_ref(), shipping_cost, prices_include_tax)?,
+        items: lines,
+        coupon,
+    })
+}
+
+/// Loads the available products in the cart, priced at the provided moment.
+/// Fails with a `ValidationErrors` if the total of a line doesn't fit.
+fn cart_lines(
+    conn: &PgConnection,
+    cart_id: Uuid,
+    now: NaiveDateTime,
+    prices_include_tax: bool,
+) -> Result<Vec<CartLine>, Error> {
+    let products = ci_dsl::cart_items
+        .inner_join(p_dsl::products)
+        .filter(ci_dsl::cart_id.eq(cart_id))
+        .filter(p_dsl::status.ne("ARCHIVED"))
+        .filter(p_dsl::deleted_at.is_null())
+        .filter(is_published(now))
+        .select((ci_dsl::quantity, p_dsl::products::all_columns()))
+        .order((p_dsl::name, p_dsl::id))
+        .load::<(i32, Product)>(conn)?;
+    let ids: Vec<Uuid> = products.iter().map(|(_, p)| p.id).collect();
+    let categories = cp_dsl::category_products
+        .filter(cp_dsl::product_id.eq_any(&ids))
+        .load::<CategoryProduct>(conn)?;
+
+    // Coupons apply to subcategories too
+    let rules = promotions::price_rules(conn, now, prices_include_tax, true)?;
+    let mut lines = Vec::with_capacity(products.len());
+    for (quantity, product) in products {
+        let category_ids: Vec<Uuid> = categories
+            .iter()
+            .filter(|c| c.product_id == product.id)
+            .map(|c| c.category_id)
+            .collect();
+        let price = rules.price(&product, &category_ids);
+        let total = match price.effective_price.checked_mul(quantity) {
+            Some(total) => total,
+            None => {
+                let mut errors = ValidationErrors::new();
+                errors.add(
+                    "quantity",
+                    format!("Total of {} is too large", product.name),
+                );
+                return Err(errors.into());
+            }
+        };
+        lines.push(CartLine {
+            product_id: product.id,
+            name: product.name,
+            slug: product.slug,
+            quantity,
+            regular_price: price.regular_price,
+            unit_price: price.effective_price,
+            total,
+            tax_rate: product.tax_rate,
+            tax_breakdown: TaxAmounts::new(total, product.tax_rate, prices_include_tax),
+            manufacturer_id: product.manufacturer_id,
+            category_ids: rules.with_ancestors(&category_ids),
+        });
+    }
+    Ok(lines)
+}
+
+/// Counts the orders with the coupon, in total and of the customer
+fn coupon_usage(
+    conn: &PgConnection,
+    coupon_id: Uuid,
+    customer_email: Option<&str>,
+) -> QueryResult<CouponUsage> {
+    let total = o_dsl::orders
+        .filter(o_dsl::coupon_id.eq(coupon_id))
+        .count()
+        .get_result::<i64>(conn)?;
+    let by_customer = match customer_email {
+        Some(email) => Some(
+            o_dsl::orders
+                .filter(o_dsl::coupon_id.eq(coupon_id))
+                .filter(o_dsl::customer_email.eq(email))
+                .count()
+                .get_result::<i64>(conn)?,
+        ),
+        None => None,
+    };
+    Ok(CouponUsage { total, by_customer })
+}
+
+/// Marks the cart as modified
+fn touch_cart(conn: &PgConnection, id: Uuid) -> QueryResult<Cart> {
+    diesel::update(dsl::carts.find(id))
+        .set(dsl::updated_at.eq(diesel::dsl::now))
+        .get_result(conn)
+}
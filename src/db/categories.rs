@@ -1,14 +1,31 @@
 use actix::{Handler, Message};
-use diesel::{prelude::*, result::Error::NotFound};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use diesel::result::Error::NotFound;
 use failure::Error;
+use uuid::Uuid;
 
+use super::audit::{self, Action, EntityType};
 use super::helpers;
-use super::DbActor;
-use crate::models::{Category, CategoryData};
+use super::products::{get_product, patch_audited_product, visible_products, ProductOwner};
+use super::translations;
+use super::{DbActor, DeleteConflict, Reference, VersionConflict};
+use crate::auth::Principal;
+use crate::models::{
+    breadcrumbs, category_tree, descendant_ids, is_descendant_or_self, published_categories,
+    Category, CategoryData, CategoryNode, CategoryOrder, CategoryPatch, DeletePolicy, Page,
+    PageRequest, ProductPatchWithMeta, ProductWithMeta, ValidationErrors, WithProducts,
+};
 use crate::schema::categories::dsl;
+use crate::schema::category_products::dsl as cp_dsl;
+use crate::schema::products::dsl as p_dsl;
 
 #[derive(Debug)]
-pub struct ListCategories {}
+pub struct ListCategories {
+    /// Excludes categories outside their publishing period, together with
+    /// their subcategories
+    pub published_only: bool,
+}
 
 impl Message for ListCategories {
     type Result = Result<Vec<Category>, Error>;
@@ -17,12 +34,10 @@ impl Message for ListCategories {
 impl Handler<ListCategories> for DbActor {
     type Result = Result<Vec<Category>, Error>;
 
-    fn handle(&mut self, _msg: ListCategories, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: ListCategories, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        let categories = dsl::categories
-            .load::<Category>(&conn)
-            .expect("Error loading categories");
-
+        let now = Utc::now().naive_utc();
+        let categories = visible_categories(&conn, msg.published_only, now)?;
         Ok(categories)
     }
 }
@@ -30,6 +45,10 @@ impl Handler<ListCategories> for DbActor {
 #[derive(Debug)]
 pub struct GetCategory {
     pub id: uuid::Uuid,
+
+    /// Excludes categories outside their publishing period, together with
+    /// their subcategories
+    pub published_only: bool,
 }
 
 impl Message for GetCategory {
@@ -41,17 +60,236 @@ impl Handler<GetCategory> for DbActor {
 
     fn handle(&mut self, msg: GetCategory, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        let category = dsl::categories
-            .find(msg.id)
-            .first::<Category>(&conn)
-            .optional()?;
-
+        let now = Utc::now().naive_utc();
+        let category = visible_categories(&conn, msg.published_only, now)?
+            .into_iter()
+            .find(|c| c.id == msg.id);
         Ok(category)
     }
 }
 
+/// Finds a category by slug, with a page of its visible products if requested
+#[derive(Debug)]
+pub struct GetCategoryBySlug {
+    pub slug: String,
+    pub products: Option<PageRequest>,
+
+    /// Includes the products of subcategories
+    pub descendants: bool,
+
+    /// Excludes categories outside their publishing period, together with
+    /// their subcategories
+    pub published_only: bool,
+}
+
+impl Message for GetCategoryBySlug {
+    type Result = Result<Option<WithProducts<Category>>, Error>;
+}
+
+impl Handler<GetCategoryBySlug> for DbActor {
+    type Result = Result<Option<WithProducts<Category>>, Error>;
+
+    fn handle(&mut self, msg: GetCategoryBySlug, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let now = Utc::now().naive_utc();
+        let categories = visible_categories(&conn, msg.published_only, now)?;
+        let id = match categories.iter().find(|c| c.slug == msg.slug) {
+            Some(category) => Some(category.id),
+            None => translations::translated_category_id(&conn, &msg.slug)?,
+        };
+        let category = match id.filter(|id| categories.iter().any(|c| c.id == *id)) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+
+        let products = match msg.products {
+            Some(page) => {
+                let owner = products_owner(&categories, category, msg.descendants);
+                Some(visible_products(
+                    &conn,
+                    owner,
+                    page,
+                    now,
+                    self.prices_include_tax,
+                )?)
+            }
+            None => None,
+        };
+        let category = categories
+            .into_iter()
+            .find(|c| c.id == category)
+            .ok_or(NotFound)?;
+        Ok(Some(WithProducts {
+            entity: category,
+            products,
+        }))
+    }
+}
+
+/// Fetches a page of the visible products of a category
+#[derive(Debug)]
+pub struct ListCategoryProducts {
+    pub id: Uuid,
+    pub page: PageRequest,
+
+    /// Includes the products of subcategories
+    pub descendants: bool,
+
+    /// Excludes categories outside their publishing period, together with
+    /// their subcategories
+    pub published_only: bool,
+}
+
+impl Message for ListCategoryProducts {
+    type Result = Result<Option<Page<ProductWithMeta>>, Error>;
+}
+
+impl Handler<ListCategoryProducts> for DbActor {
+    type Result = Result<Option<Page<ProductWithMeta>>, Error>;
+
+    fn handle(&mut self, msg: ListCategoryProducts, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let now = Utc::now().naive_utc();
+        let categories = visible_categories(&conn, msg.published_only, now)?;
+        if !categories.iter().any(|c| c.id == msg.id) {
+            return Ok(None);
+        }
+        let owner = products_owner(&categories, msg.id, msg.descendants);
+        Ok(Some(visible_products(
+            &conn,
+            owner,
+            msg.page,
+            now,
+            self.prices_include_tax,
+        )?))
+    }
+}
+
+/// Builds the category tree. Returns the subtree of the root if provided.
+#[derive(Debug)]
+pub struct GetCategoryTree {
+    pub root: Option<Uuid>,
+
+    /// Excludes categories outside their publishing period, together with
+    /// their subcategories
+    pub published_only: bool,
+}
+
+impl Message for GetCategoryTree {
+    type Result = Result<Option<Vec<CategoryNode>>, Error>;
+}
+
+impl Handler<GetCategoryTree> for DbActor {
+    type Result = Result<Option<Vec<CategoryNode>>, Error>;
+
+    fn handle(&mut self, msg: GetCategoryTree, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let now = Utc::now().naive_utc();
+        let categories = visible_categories(&conn, msg.published_only, now)?;
+        Ok(category_tree(categories, msg.root))
+    }
+}
+
+/// Lists the categories from the top level down to the category
+#[derive(Debug)]
+pub struct GetBreadcrumbs {
+    pub id: Uuid,
+
+    /// Excludes categories outside their publishing period, together with
+    /// their subcategories
+    pub published_only: bool,
+}
+
+impl Message for GetBreadcrumbs {
+    type Result = Result<Option<Vec<Category>>, Error>;
+}
+
+impl Handler<GetBreadcrumbs> for DbActor {
+    type Result = Result<Option<Vec<Category>>, Error>;
+
+    fn handle(&mut self, msg: GetBreadcrumbs, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let now = Utc::now().naive_utc();
+        let categories = visible_categories(&conn, msg.published_only, now)?;
+        Ok(breadcrumbs(categories, msg.id))
+    }
+}
+
+/// Changes the sort order of all subcategories of a parent
+#[derive(Debug)]
+pub struct ReorderCategories {
+    pub author: Principal,
+    pub order: CategoryOrder,
+}
+
+impl Message for ReorderCategories {
+    type Result = Result<Vec<Category>, Error>;
+}
+
+impl Handler<ReorderCategories> for DbActor {
+    type Result = Result<Vec<Category>, Error>;
+
+    fn handle(&mut self, msg: ReorderCategories, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let order = &msg.order;
+            let siblings = match order.parent_id {
+                Some(parent_id) => dsl::categories
+                    .filter(dsl::parent_id.eq(parent_id))
+                    .filter(dsl::deleted_at.is_null())
+                    .load::<Category>(&conn)?,
+                None => dsl::categories
+                    .filter(dsl::parent_id.is_null())
+                    .filter(dsl::deleted_at.is_null())
+                    .load::<Category>(&conn)?,
+            };
+            let mut ids: Vec<_> = order.category_ids.clone();
+            ids.sort();
+            ids.dedup();
+            let complete = ids.len() == order.category_ids.len()
+                && ids.len() == siblings.len()
+                && siblings.iter().all(|c| ids.contains(&c.id));
+            if !complete || order.category_ids.len() > i16::MAX as usize {
+                let mut errors = ValidationErrors::new();
+                errors.add(
+                    "category_ids",
+                    "Should contain all subcategories of the parent exactly once",
+                );
+                return Err(errors.into());
+            }
+
+            let mut result = Vec::with_capacity(siblings.len());
+            for (sort_order, id) in order.category_ids.iter().enumerate() {
+                let before = siblings.iter().find(|c| c.id == *id).ok_or(NotFound)?;
+                if before.sort_order == sort_order as i16 {
+                    result.push(dsl::categories.find(id).first::<Category>(&conn)?);
+                    continue;
+                }
+                let after = diesel::update(dsl::categories.find(id))
+                    .set((
+                        dsl::sort_order.eq(sort_order as i16),
+                        dsl::version.eq(dsl::version + 1),
+                    ))
+                    .get_result::<Category>(&conn)?;
+                audit::record(
+                    &conn,
+                    &msg.author,
+                    EntityType::Category,
+                    after.id,
+                    Action::Update,
+                    Some(before),
+                    Some(&after),
+                )?;
+                result.push(after);
+            }
+            Ok(result)
+        })
+    }
+}
+
 #[derive(Debug)]
 pub struct InsertCategory {
+    pub author: Principal,
     pub data: CategoryData,
 }
 
@@ -62,25 +300,17 @@ impl Message for InsertCategory {
 impl Handler<InsertCategory> for DbActor {
     type Result = Result<Category, Error>;
 
-    fn handle(&mut self, msg: InsertCategory, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: InsertCategory, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        let cat: Category = diesel::insert_into(dsl::categories)
-            .values(&msg.data)
-            .get_result(&conn)
-            .map_err(Error::from)?;
-
-        let msg_update = UpdateCategory {
-            id: cat.id,
-            data: msg.data,
-        };
-
-        self.handle(msg_update, ctx)
+        conn.transaction(|| insert_category(&conn, &msg.author, msg.data))
     }
 }
 
 #[derive(Debug)]
 pub struct UpdateCategory {
+    pub author: Principal,
     pub id: uuid::Uuid,
+    pub version: Option<i32>,
     pub data: CategoryData,
 }
 
@@ -91,19 +321,70 @@ impl Message for UpdateCategory {
 impl Handler<UpdateCategory> for DbActor {
     type Result = Result<Category, Error>;
 
-    fn handle(&mut self, mut msg: UpdateCategory, _: &mut Self::Context) -> Self::Result {
-        msg.data.slug = helpers::generate_slug(&msg.data.name, &msg.id);
+    fn handle(&mut self, msg: UpdateCategory, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = get_live_category(&conn, msg.id)?;
+            check_parent(&conn, Some(msg.id), msg.data.parent_id)?;
+            bump_version(&conn, msg.id, msg.version.unwrap_or(before.version))?;
+            let cat = update_category(&conn, msg.id, msg.data)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Category,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&cat),
+            )?;
+            Ok(cat)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PatchCategory {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+    pub version: Option<i32>,
+    pub patch: CategoryPatch,
+}
+
+impl Message for PatchCategory {
+    type Result = Result<Category, Error>;
+}
+
+impl Handler<PatchCategory> for DbActor {
+    type Result = Result<Category, Error>;
+
+    fn handle(&mut self, msg: PatchCategory, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        diesel::update(dsl::categories.find(msg.id))
-            .set(msg.data)
-            .get_result(&conn)
-            .map_err(Error::from)
+        conn.transaction(|| {
+            let before = get_live_category(&conn, msg.id)?;
+            check_parent(&conn, Some(msg.id), msg.patch.parent_id.flatten())?;
+            bump_version(&conn, msg.id, msg.version.unwrap_or(before.version))?;
+            let cat = patch_category(&conn, msg.id, msg.patch)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Category,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&cat),
+            )?;
+            Ok(cat)
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct DeleteCategory {
+    pub author: Principal,
     pub id: uuid::Uuid,
+
+    /// Handling of the products and subcategories of the category
+    pub policy: DeletePolicy,
 }
 
 impl Message for DeleteCategory {
@@ -115,9 +396,305 @@ impl Handler<DeleteCategory> for DbActor {
 
     fn handle(&mut self, msg: DeleteCategory, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        diesel::delete(dsl::categories.find(msg.id))
-            .execute(&conn)
-            .and_then(|c| if c > 0 { Ok(()) } else { Err(NotFound) })
-            .map_err(Error::from)
+        conn.transaction(|| {
+            let before = get_live_category(&conn, msg.id)?;
+            let product_ids = cp_dsl::category_products
+                .inner_join(p_dsl::products)
+                .filter(cp_dsl::category_id.eq(msg.id))
+                .filter(p_dsl::deleted_at.is_null())
+                .select(cp_dsl::product_id)
+                .load::<Uuid>(&conn)?;
+            let children = dsl::categories
+                .filter(dsl::parent_id.eq(msg.id))
+                .filter(dsl::deleted_at.is_null())
+                .order((dsl::sort_order, dsl::name))
+                .load::<Category>(&conn)?;
+
+            // Products and subcategories are moved to the new category, or
+            // subcategories are moved up if they're detached
+            let target = match msg.policy {
+                DeletePolicy::Restrict => {
+                    let conflict = DeleteConflict {
+                        products: p_dsl::products
+                            .filter(p_dsl::id.eq_any(&product_ids))
+                            .select((p_dsl::id, p_dsl::name))
+                            .order(p_dsl::name)
+                            .load::<(Uuid, String)>(&conn)?
+                            .into_iter()
+                            .map(|(id, name)| Reference { id, name })
+                            .collect(),
+                        categories: children
+                            .iter()
+                            .map(|c| Reference {
+                                id: c.id,
+                                name: c.name.clone(),
+                            })
+                            .collect(),
+                    };
+                    if !conflict.is_empty() {
+                        return Err(conflict.into());
+                    }
+                    None
+                }
+                DeletePolicy::Detach => None,
+                DeletePolicy::Reassign(target) => {
+                    check_reassign_target(&conn, msg.id, target)?;
+                    Some(target)
+                }
+            };
+
+            for product_id in product_ids {
+                let product = get_product(&conn, product_id)?.ok_or(NotFound)?;
+                let mut category_ids: Vec<_> = product
+                    .category_ids
+                    .iter()
+                    .copied()
+                    .filter(|id| *id != msg.id)
+                    .collect();
+                if let Some(target) = target.filter(|t| !category_ids.contains(t)) {
+                    category_ids.push(target);
+                }
+                let patch = ProductPatchWithMeta {
+                    category_ids: Some(category_ids),
+                    ..Default::default()
+                };
+                patch_audited_product(&conn, &msg.author, &product, patch)?;
+            }
+            for child in children {
+                let parent_id = target.or(before.parent_id);
+                let after = diesel::update(dsl::categories.find(child.id))
+                    .set((
+                        dsl::parent_id.eq(parent_id),
+                        dsl::version.eq(dsl::version + 1),
+                    ))
+                    .get_result::<Category>(&conn)?;
+                audit::record(
+                    &conn,
+                    &msg.author,
+                    EntityType::Category,
+                    child.id,
+                    Action::Update,
+                    Some(&child),
+                    Some(&after),
+                )?;
+            }
+
+            diesel::update(dsl::categories.find(msg.id))
+                .set((
+                    dsl::deleted_at.eq(diesel::dsl::now),
+                    dsl::version.eq(dsl::version + 1),
+                ))
+                .execute(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Category,
+                msg.id,
+                Action::Delete,
+                Some(&before),
+                None,
+            )
+        })
+    }
+}
+
+/// Takes a category out of the trash. Its parent is cleared if the parent is
+/// still in the trash.
+#[derive(Debug)]
+pub struct RestoreCategory {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+}
+
+impl Message for RestoreCategory {
+    type Result = Result<Category, Error>;
+}
+
+impl Handler<RestoreCategory> for DbActor {
+    type Result = Result<Category, Error>;
+
+    fn handle(&mut self, msg: RestoreCategory, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::categories
+                .find(msg.id)
+                .filter(dsl::deleted_at.is_not_null())
+                .first::<Category>(&conn)?;
+            let parent_id = match before.parent_id {
+                Some(parent_id) => dsl::categories
+                    .find(parent_id)
+                    .filter(dsl::deleted_at.is_null())
+                    .select(dsl::id)
+                    .first::<Uuid>(&conn)
+                    .optional()?,
+                None => None,
+            };
+            let after = diesel::update(dsl::categories.find(msg.id))
+                .set((
+                    dsl::deleted_at.eq(None::<NaiveDateTime>),
+                    dsl::parent_id.eq(parent_id),
+                    dsl::version.eq(dsl::version + 1),
+                ))
+                .get_result::<Category>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Category,
+                msg.id,
+                Action::Restore,
+                Some(&before),
+                Some(&after),
+            )?;
+            Ok(after)
+        })
+    }
+}
+
+/// Inserts a category. Should be called inside a transaction.
+pub(super) fn insert_category(
+    conn: &PgConnection,
+    author: &Principal,
+    data: CategoryData,
+) -> Result<Category, Error> {
+    check_parent(conn, None, data.parent_id)?;
+    let cat: Category = diesel::insert_into(dsl::categories)
+        .values(&data)
+        .get_result(conn)?;
+
+    // Update category to set slug
+    let cat = update_category(conn, cat.id, data)?;
+    audit::record(
+        conn,
+        author,
+        EntityType::Category,
+        cat.id,
+        Action::Create,
+        None,
+        Some(&cat),
+    )?;
+    Ok(cat)
+}
+
+/// Updates the category and regenerates its slug
+fn update_category(
+    conn: &PgConnection,
+    id: Uuid,
+    mut data: CategoryData,
+) -> Result<Category, Error> {
+    data.slug = helpers::generate_slug(&data.name, &id);
+    translations::check_category_slug(conn, &id, &data.slug)?;
+    let category: Category = diesel::update(dsl::categories.find(id))
+        .set(&data)
+        .get_result(conn)?;
+    helpers::check_schedule(category.publish_at, category.unpublish_at)?;
+    Ok(category)
+}
+
+/// Updates only the provided fields of the category and regenerates its slug if renamed
+fn patch_category(
+    conn: &PgConnection,
+    id: Uuid,
+    mut patch: CategoryPatch,
+) -> Result<Category, Error> {
+    if let Some(name) = &patch.name {
+        let slug = helpers::generate_slug(name, &id);
+        translations::check_category_slug(conn, &id, &slug)?;
+        patch.slug = Some(slug);
+    }
+    if !patch.is_empty() {
+        diesel::update(dsl::categories.find(id))
+            .set(&patch)
+            .execute(conn)?;
+    }
+    let category: Category = dsl::categories.find(id).first(conn)?;
+    helpers::check_schedule(category.publish_at, category.unpublish_at)?;
+    Ok(category)
+}
+
+/// Fetches a category which isn't in the trash
+fn get_live_category(conn: &PgConnection, id: Uuid) -> QueryResult<Category> {
+    dsl::categories
+        .find(id)
+        .filter(dsl::deleted_at.is_null())
+        .first(conn)
+}
+
+/// Loads all categories which aren't in the trash
+fn live_categories(conn: &PgConnection) -> QueryResult<Vec<Category>> {
+    dsl::categories.filter(dsl::deleted_at.is_null()).load(conn)
+}
+
+/// Loads the categories which aren't in the trash, keeping only the published
+/// ones if requested
+fn visible_categories(
+    conn: &PgConnection,
+    published_only: bool,
+    now: NaiveDateTime,
+) -> QueryResult<Vec<Category>> {
+    let categories = live_categories(conn)?;
+    if !published_only {
+        return Ok(categories);
+    }
+    Ok(published_categories(categories, now))
+}
+
+/// Verifies the parent exists and isn't the category itself or one of its descendants
+fn check_parent(
+    conn: &PgConnection,
+    id: Option<Uuid>,
+    parent_id: Option<Uuid>,
+) -> Result<(), Error> {
+    let parent_id = match parent_id {
+        Some(parent_id) => parent_id,
+        None => return Ok(()),
+    };
+    let categories = live_categories(conn)?;
+    let mut errors = ValidationErrors::new();
+    if !categories.iter().any(|c| c.id == parent_id) {
+        errors.add("parent_id", format!("Category {} doesn't exist", parent_id));
+    } else if id.is_some_and(|id| is_descendant_or_self(&categories, id, parent_id)) {
+        errors.add(
+            "parent_id",
+            "Should not be the category itself or one of its subcategories",
+        );
+    }
+    Ok(errors.into_result()?)
+}
+
+/// Verifies the products and subcategories of a deleted category can be moved
+/// to the target category
+fn check_reassign_target(conn: &PgConnection, id: Uuid, target: Uuid) -> Result<(), Error> {
+    let categories = live_categories(conn)?;
+    let mut errors = ValidationErrors::new();
+    if !categories.iter().any(|c| c.id == target) {
+        errors.add("reassign_to", format!("Category {} doesn't exist", target));
+    } else if is_descendant_or_self(&categories, id, target) {
+        errors.add(
+            "reassign_to",
+            "Should not be the category itself or one of its subcategories",
+        );
+    }
+    Ok(errors.into_result()?)
+}
+
+/// Selects the products of the category, and of its subcategories if requested
+fn products_owner(categories: &[Category], id: Uuid, descendants: bool) -> ProductOwner {
+    if !descendants {
+        return ProductOwner::Categories(vec![id]);
+    }
+    ProductOwner::Categories(descendant_ids(categories, id))
+}
+
+/// Increments the version of the category. Fails with a `VersionConflict` if the
+/// category doesn't have the expected version. Should be called inside a transaction.
+fn bump_version(conn: &PgConnection, id: Uuid, expected: i32) -> Result<(), Error> {
+    let bumped = diesel::update(dsl::categories.find(id))
+        .filter(dsl::version.eq(expected))
+        .set(dsl::version.eq(dsl::version + 1))
+        .execute(conn)?;
+    if bumped == 0 {
+        let current = dsl::categories.find(id).first::<Category>(conn)?;
+        return Err(VersionConflict::new(current.version, &current).into());
     }
+    Ok(())
 }
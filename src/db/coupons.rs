@@ -0,0 +1,198 @@
+use actix::{Handler, Message};
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+use super::audit::{self, Action, EntityType};
+use super::{DbActor, VersionConflict};
+use crate::auth::Principal;
+use crate::models::{Coupon, CouponData, ValidationErrors};
+use crate::schema::categories::dsl as c_dsl;
+use crate::schema::coupons::dsl;
+use crate::schema::manufacturers::dsl as m_dsl;
+
+#[derive(Debug)]
+pub struct ListCoupons {}
+
+impl Message for ListCoupons {
+    type Result = Result<Vec<Coupon>, Error>;
+}
+
+impl Handler<ListCoupons> for DbActor {
+    type Result = Result<Vec<Coupon>, Error>;
+
+    fn handle(&mut self, _msg: ListCoupons, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let coupons = dsl::coupons.order(dsl::code).load::<Coupon>(&conn)?;
+        Ok(coupons)
+    }
+}
+
+#[derive(Debug)]
+pub struct GetCoupon {
+    pub id: Uuid,
+}
+
+impl Message for GetCoupon {
+    type Result = Result<Option<Coupon>, Error>;
+}
+
+impl Handler<GetCoupon> for DbActor {
+    type Result = Result<Option<Coupon>, Error>;
+
+    fn handle(&mut self, msg: GetCoupon, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let coupon = dsl::coupons
+            .find(msg.id)
+            .first::<Coupon>(&conn)
+            .optional()?;
+        Ok(coupon)
+    }
+}
+
+#[derive(Debug)]
+pub struct InsertCoupon {
+    pub author: Principal,
+    pub data: CouponData,
+}
+
+impl Message for InsertCoupon {
+    type Result = Result<Coupon, Error>;
+}
+
+impl Handler<InsertCoupon> for DbActor {
+    type Result = Result<Coupon, Error>;
+
+    fn handle(&mut self, msg: InsertCoupon, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            check_coupon(&conn, &msg.data, None)?;
+            let coupon = diesel::insert_into(dsl::coupons)
+                .values(&msg.data)
+                .get_result::<Coupon>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Coupon,
+                coupon.id,
+                Action::Create,
+                None,
+                Some(&coupon),
+            )?;
+            Ok(coupon)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct UpdateCoupon {
+    pub author: Principal,
+    pub id: Uuid,
+    pub version: Option<i32>,
+    pub data: CouponData,
+}
+
+impl Message for UpdateCoupon {
+    type Result = Result<Coupon, Error>;
+}
+
+impl Handler<UpdateCoupon> for DbActor {
+    type Result = Result<Coupon, Error>;
+
+    fn handle(&mut self, msg: UpdateCoupon, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::coupons.find(msg.id).first::<Coupon>(&conn)?;
+            let expected = msg.version.unwrap_or(before.version);
+            check_coupon(&conn, &msg.data, Some(msg.id))?;
+            let coupon = diesel::update(dsl::coupons.find(msg.id))
+                .filter(dsl::version.eq(expected))
+                .set((&msg.data, dsl::version.eq(dsl::version + 1)))
+                .get_result::<Coupon>(&conn)
+                .optional()?;
+            let coupon = match coupon {
+                Some(coupon) => coupon,
+                None => return Err(VersionConflict::new(before.version, &before).into()),
+            };
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Coupon,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&coupon),
+            )?;
+            Ok(coupon)
+        })
+    }
+}
+
+/// Deletes a coupon. Orders keep the code of the coupon.
+#[derive(Debug)]
+pub struct DeleteCoupon {
+    pub author: Principal,
+    pub id: Uuid,
+}
+
+impl Message for DeleteCoupon {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeleteCoupon> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeleteCoupon, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = diesel::delete(dsl::coupons.find(msg.id)).get_result::<Coupon>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Coupon,
+                msg.id,
+                Action::Delete,
+                Some(&before),
+                None,
+            )
+        })
+    }
+}
+
+/// Verifies the code is unique and the restrictions refer to existing entities
+fn check_coupon(conn: &PgConnection, data: &CouponData, id: Option<Uuid>) -> Result<(), Error> {
+    let mut errors = ValidationErrors::new();
+    let owner = dsl::coupons
+        .filter(dsl::code.eq(&data.code))
+        .select(dsl::id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    if owner.is_some() && owner != id {
+        errors.add("code", format!("Code {} is already in use", data.code));
+    }
+
+    let found = c_dsl::categories
+        .filter(c_dsl::id.eq_any(&data.category_ids))
+        .filter(c_dsl::deleted_at.is_null())
+        .select(c_dsl::id)
+        .load::<Uuid>(conn)?;
+    for id in data.category_ids.iter().filter(|id| !found.contains(id)) {
+        errors.add("category_ids", format!("Category {} doesn't exist", id));
+    }
+    let found = m_dsl::manufacturers
+        .filter(m_dsl::id.eq_any(&data.manufacturer_ids))
+        .filter(m_dsl::deleted_at.is_null())
+        .select(m_dsl::id)
+        .load::<Uuid>(conn)?;
+    for id in data
+        .manufacturer_ids
+        .iter()
+        .filter(|id| !found.contains(id))
+    {
+        errors.add(
+            "manufacturer_ids",
+            format!("Manufacturer {} doesn't exist", id),
+        );
+    }
+    Ok(errors.into_result()?)
+}
@@ -0,0 +1,131 @@
+use actix::{Handler, Message};
+use diesel::prelude::*;
+use failure::Error;
+
+use super::audit::{self, Action, EntityType};
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{ExchangeRate, ExchangeRateData};
+use crate::schema::exchange_rates::dsl;
+
+#[derive(Debug)]
+pub struct ListExchangeRates {}
+
+impl Message for ListExchangeRates {
+    type Result = Result<Vec<ExchangeRate>, Error>;
+}
+
+impl Handler<ListExchangeRates> for DbActor {
+    type Result = Result<Vec<ExchangeRate>, Error>;
+
+    fn handle(&mut self, _msg: ListExchangeRates, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let rates = dsl::exchange_rates
+            .order(dsl::currency)
+            .load::<ExchangeRate>(&conn)?;
+        Ok(rates)
+    }
+}
+
+#[derive(Debug)]
+pub struct GetExchangeRate {
+    pub currency: String,
+}
+
+impl Message for GetExchangeRate {
+    type Result = Result<Option<ExchangeRate>, Error>;
+}
+
+impl Handler<GetExchangeRate> for DbActor {
+    type Result = Result<Option<ExchangeRate>, Error>;
+
+    fn handle(&mut self, msg: GetExchangeRate, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let rate = dsl::exchange_rates
+            .filter(dsl::currency.eq(&msg.currency))
+            .first::<ExchangeRate>(&conn)
+            .optional()?;
+        Ok(rate)
+    }
+}
+
+/// Adds or replaces the rates of the currencies
+#[derive(Debug)]
+pub struct SetExchangeRates {
+    pub author: Principal,
+    pub rates: Vec<ExchangeRateData>,
+}
+
+impl Message for SetExchangeRates {
+    type Result = Result<Vec<ExchangeRate>, Error>;
+}
+
+impl Handler<SetExchangeRates> for DbActor {
+    type Result = Result<Vec<ExchangeRate>, Error>;
+
+    fn handle(&mut self, msg: SetExchangeRates, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let mut rates = Vec::new();
+            for data in &msg.rates {
+                let before = dsl::exchange_rates
+                    .filter(dsl::currency.eq(&data.currency))
+                    .first::<ExchangeRate>(&conn)
+                    .optional()?;
+                let rate = diesel::insert_into(dsl::exchange_rates)
+                    .values(data)
+                    .on_conflict(dsl::currency)
+                    .do_update()
+                    .set(data)
+                    .get_result::<ExchangeRate>(&conn)?;
+                let action = match before {
+                    Some(_) => Action::Update,
+                    None => Action::Create,
+                };
+                audit::record(
+                    &conn,
+                    &msg.author,
+                    EntityType::ExchangeRate,
+                    rate.id,
+                    action,
+                    before.as_ref(),
+                    Some(&rate),
+                )?;
+                rates.push(rate);
+            }
+            Ok(rates)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteExchangeRate {
+    pub author: Principal,
+    pub currency: String,
+}
+
+impl Message for DeleteExchangeRate {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeleteExchangeRate> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeleteExchangeRate, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before =
+                diesel::delete(dsl::exchange_rates.filter(dsl::currency.eq(&msg.currency)))
+                    .get_result::<ExchangeRate>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::ExchangeRate,
+                before.id,
+                Action::Delete,
+                Some(&before),
+                None,
+            )
+        })
+    }
+}
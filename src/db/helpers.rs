@@ -1,7 +1,11 @@
+use chrono::NaiveDateTime;
 use convert_case::{Case, Casing};
+use serde_json::{json, Map, Value};
 use unicode_normalization::UnicodeNormalization;
 use uuid::Uuid;
 
+use crate::models::{Product, ValidationErrors};
+
 /// Generate a kebab-cased slug based on the first 30 characters of the name
 /// and the first 8 bytes of the UUID.
 pub fn generate_slug(name: &str, id: &Uuid) -> String {
@@ -17,6 +21,73 @@ pub fn generate_slug(name: &str, id: &Uuid) -> String {
     format!("{}-{:x}", normalized_name, id_fields.0)
 }
 
+/// Determines the slug after an update. A slug which differs from the one
+/// generated from the current name is custom and is kept unless another slug
+/// is requested. An empty requested slug is generated from the new name.
+pub fn next_slug(
+    id: &Uuid,
+    current_name: &str,
+    current_slug: &str,
+    requested: Option<&str>,
+    new_name: &str,
+) -> String {
+    let generated = generate_slug(current_name, id);
+    match requested {
+        None if current_slug != generated => current_slug.to_string(),
+        Some(slug) if !slug.is_empty() && slug != generated => slug.to_string(),
+        _ => generate_slug(new_name, id),
+    }
+}
+
+/// Returns the fields which differ between two JSON objects as
+/// `{"field": {"old": ..., "new": ...}}`. Null is treated as an empty object.
+pub fn json_diff(before: &Value, after: &Value) -> Value {
+    let empty = Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+
+    let mut diff = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+        if old != new && !diff.contains_key(key) {
+            diff.insert(key.clone(), json!({ "old": old, "new": new }));
+        }
+    }
+    Value::Object(diff)
+}
+
+/// Verifies the end of a publishing period is after its start
+pub fn check_schedule(
+    publish_at: Option<NaiveDateTime>,
+    unpublish_at: Option<NaiveDateTime>,
+) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if let (Some(publish_at), Some(unpublish_at)) = (publish_at, unpublish_at) {
+        if unpublish_at <= publish_at {
+            errors.add("unpublish_at", "Should be after publish_at");
+        }
+    }
+    errors.into_result()
+}
+
+/// Verifies the sale price is below the price and the sale ends after it starts
+pub fn check_sale(product: &Product) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::new();
+    if product
+        .sale_price
+        .is_some_and(|sale_price| sale_price >= product.price)
+    {
+        errors.add("sale_price", "Should be below price");
+    }
+    if let (Some(starts_at), Some(ends_at)) = (product.sale_starts_at, product.sale_ends_at) {
+        if ends_at <= starts_at {
+            errors.add("sale_ends_at", "Should be after sale_starts_at");
+        }
+    }
+    errors.into_result()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,4 +99,32 @@ mod tests {
         let expected = "dit-is-een-test-b144dca4";
         assert_eq!(generate_slug(name, &id), expected);
     }
+
+    #[test]
+    fn test_next_slug() {
+        let id = Uuid::parse_str("b144dca4-b7e2-4651-97bc-7fa5125ab04e").unwrap();
+        let generated = "zeep-b144dca4";
+        let next = |slug, requested| next_slug(&id, "Zeep", slug, requested, "Lavendel zeep");
+        assert_eq!(next(generated, None), "lavendel-zeep-b144dca4");
+        assert_eq!(next(generated, Some(generated)), "lavendel-zeep-b144dca4");
+        assert_eq!(next(generated, Some("zeep")), "zeep");
+        assert_eq!(next("zeep", None), "zeep");
+        assert_eq!(next("zeep", Some("")), "lavendel-zeep-b144dca4");
+    }
+
+    #[test]
+    fn test_json_diff() {
+        let before = json!({ "name": "Old", "price": 100, "stock_count": 5 });
+        let after = json!({ "name": "New", "price": 100, "status": "AVAILABLE" });
+        let expected = json!({
+            "name": { "old": "Old", "new": "New" },
+            "stock_count": { "old": 5, "new": null },
+            "status": { "old": null, "new": "AVAILABLE" },
+        });
+        assert_eq!(json_diff(&before, &after), expected);
+        assert_eq!(
+            json_diff(&Value::Null, &json!({ "a": 1 })),
+            json!({ "a": { "old": null, "new": 1 } })
+        );
+    }
 }
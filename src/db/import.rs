@@ -0,0 +1,315 @@
+use actix::{Handler, Message};
+use diesel::result::Error::RollbackTransaction;
+use diesel::{prelude::*, sql_types::Text};
+use failure::Error;
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::audit::{self, Action, EntityType};
+use super::categories::insert_category;
+use super::helpers;
+use super::manufacturers::insert_manufacturer;
+use super::products::{bump_version, check_unique_sku, get_product, insert_product, patch_product};
+use super::revisions;
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{
+    CategoryData, ImportMatch, ImportReport, ImportRow, ImportRowResult, ManufacturerData, Product,
+    ProductData, ProductDataWithMeta, ProductPatchWithMeta, ValidationErrors,
+};
+use crate::schema::categories::dsl as c_dsl;
+use crate::schema::manufacturers::dsl as m_dsl;
+use crate::schema::products::dsl;
+
+sql_function!(fn lower(x: Text) -> Text);
+
+/// Creates or updates products from the rows of an import file. Rows which
+/// couldn't be parsed are passed as error and reported as is.
+#[derive(Debug)]
+pub struct ImportProducts {
+    pub author: Principal,
+    pub match_by: ImportMatch,
+    pub create_missing: bool,
+    pub dry_run: bool,
+    pub rows: Vec<(usize, Result<ImportRow, ValidationErrors>)>,
+}
+
+impl Message for ImportProducts {
+    type Result = Result<ImportReport, Error>;
+}
+
+impl Handler<ImportProducts> for DbActor {
+    type Result = Result<ImportReport, Error>;
+
+    fn handle(&mut self, mut msg: ImportProducts, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let mut report = ImportReport {
+            dry_run: msg.dry_run,
+            ..ImportReport::default()
+        };
+        let rows = std::mem::take(&mut msg.rows);
+        let outcome = conn.transaction::<_, Error, _>(|| {
+            for (row, parsed) in rows {
+                let mut result = ImportRowResult {
+                    row,
+                    action: None,
+                    product_id: None,
+                    changes: None,
+                    errors: None,
+                };
+                let row = match parsed {
+                    Ok(row) => row,
+                    Err(errors) => {
+                        result.errors = Some(errors);
+                        report.rows.push(result);
+                        continue;
+                    }
+                };
+
+                // Each row runs in a savepoint, so a failing row doesn't
+                // prevent reporting on the remaining rows.
+                let mut created = Created::default();
+                let imported = conn.transaction(|| import_row(&conn, &msg, row, &mut created));
+                match imported {
+                    Ok((action, product_id, changes)) => {
+                        match action {
+                            Action::Create => report.created += 1,
+                            _ => report.updated += 1,
+                        }
+                        report.created_manufacturers.extend(created.manufacturers);
+                        report.created_categories.extend(created.categories);
+                        result.action = Some(action.as_str());
+                        result.product_id = Some(product_id);
+                        result.changes = Some(changes);
+                    }
+                    Err(e) => result.errors = Some(row_errors(e)),
+                }
+                report.rows.push(result);
+            }
+
+            if msg.dry_run || report.has_errors() {
+                return Err(RollbackTransaction.into());
+            }
+            Ok(())
+        });
+
+        report.applied = match outcome {
+            Ok(()) => true,
+            Err(e) if matches!(e.downcast_ref(), Some(RollbackTransaction)) => false,
+            Err(e) => return Err(e),
+        };
+        Ok(report)
+    }
+}
+
+/// Names of the manufacturers and categories created while importing a row
+#[derive(Debug, Default)]
+struct Created {
+    manufacturers: Vec<String>,
+    categories: Vec<String>,
+}
+
+/// Creates or updates the product of a single row. Returns the performed
+/// action, the ID of the product and the changed fields.
+fn import_row(
+    conn: &PgConnection,
+    msg: &ImportProducts,
+    row: ImportRow,
+    created: &mut Created,
+) -> Result<(Action, Uuid, Value), Error> {
+    let existing = match msg.match_by {
+        ImportMatch::Sku => match row.product.sku.clone().flatten() {
+            Some(sku) => dsl::products
+                .filter(dsl::sku.eq(sku))
+                .filter(dsl::deleted_at.is_null())
+                .first::<Product>(conn)
+                .optional()?,
+            None => return Err(missing("sku").into()),
+        },
+        ImportMatch::Slug => match &row.slug {
+            Some(slug) => dsl::products
+                .filter(dsl::slug.eq(slug))
+                .filter(dsl::deleted_at.is_null())
+                .first::<Product>(conn)
+                .optional()?,
+            None => return Err(missing("slug").into()),
+        },
+    };
+
+    // Resolve references by name
+    let mut errors = ValidationErrors::new();
+    let manufacturer_id = match &row.manufacturer {
+        Some(name) => resolve_manufacturer(conn, msg, name, created)?,
+        None => None,
+    };
+    if let (Some(name), None) = (&row.manufacturer, manufacturer_id) {
+        errors.add(
+            "manufacturer",
+            format!("Manufacturer {} doesn't exist", name),
+        );
+    }
+    let mut category_ids = Vec::new();
+    for name in row.categories.iter().flatten() {
+        match resolve_category(conn, msg, name, created)? {
+            Some(id) => category_ids.push(id),
+            None => errors.add("categories", format!("Category {} doesn't exist", name)),
+        }
+    }
+    errors.into_result()?;
+    let category_ids = row.categories.as_ref().map(|_| category_ids);
+
+    match existing {
+        Some(product) => {
+            let id = product.id;
+            let before = get_product(conn, id)?.ok_or(diesel::NotFound)?;
+            let mut product = row.product;
+            if manufacturer_id.is_some() {
+                product.manufacturer_id = Some(manufacturer_id);
+            }
+            let sku = product.sku.clone().flatten();
+            check_unique_sku(conn, sku.as_deref(), Some(id))?;
+            bump_version(conn, id, before.product.version)?;
+            let patch = ProductPatchWithMeta {
+                product,
+                category_ids,
+            };
+            let after = patch_product(conn, id, patch)?;
+            audit::record(
+                conn,
+                &msg.author,
+                EntityType::Product,
+                id,
+                Action::Update,
+                Some(&before),
+                Some(&after),
+            )?;
+            revisions::record(conn, &msg.author, &after)?;
+            let changes = helpers::json_diff(
+                &serde_json::to_value(&before)?,
+                &serde_json::to_value(&after)?,
+            );
+            Ok((Action::Update, id, changes))
+        }
+        None => {
+            let mut errors = ValidationErrors::new();
+            let product = row.product;
+            if product.name.is_none() {
+                errors.add("name", "Required for new products");
+            }
+            if product.price.is_none() {
+                errors.add("price", "Required for new products");
+            }
+            if product.tax_rate.is_none() {
+                errors.add("tax_rate", "Required for new products");
+            }
+            errors.into_result()?;
+
+            let data = ProductDataWithMeta {
+                product: ProductData {
+                    name: product.name.unwrap_or_default(),
+                    slug: String::new(),
+                    description_short: product.description_short.unwrap_or_default(),
+                    description_long: product.description_long.unwrap_or_default(),
+                    price: product.price.unwrap_or_default(),
+                    manufacturer_id,
+                    status: product.status.unwrap_or_else(|| "AVAILABLE".to_string()),
+                    stock_count: product.stock_count.unwrap_or_default(),
+                    sku: product.sku.flatten(),
+                    meta_title: None,
+                    meta_description: None,
+                    canonical_url: None,
+                    noindex: false,
+                    publish_at: None,
+                    unpublish_at: None,
+                    sale_price: None,
+                    sale_starts_at: None,
+                    sale_ends_at: None,
+                    tax_rate: product.tax_rate.unwrap_or_default(),
+                },
+                category_ids: category_ids.unwrap_or_default(),
+            };
+            let after = insert_product(conn, &msg.author, data)?;
+            let changes = helpers::json_diff(&Value::Null, &serde_json::to_value(&after)?);
+            Ok((Action::Create, after.product.id, changes))
+        }
+    }
+}
+
+/// Finds a manufacturer by name, ignoring case. Creates it if allowed.
+fn resolve_manufacturer(
+    conn: &PgConnection,
+    msg: &ImportProducts,
+    name: &str,
+    created: &mut Created,
+) -> Result<Option<Uuid>, Error> {
+    let id = m_dsl::manufacturers
+        .filter(lower(m_dsl::name).eq(lower(name)))
+        .filter(m_dsl::deleted_at.is_null())
+        .select(m_dsl::id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    if id.is_some() || !msg.create_missing {
+        return Ok(id);
+    }
+
+    let data = ManufacturerData {
+        name: name.to_string(),
+        website_url: String::new(),
+        slug: String::new(),
+    };
+    let manufacturer = insert_manufacturer(conn, &msg.author, data)?;
+    created.manufacturers.push(manufacturer.name);
+    Ok(Some(manufacturer.id))
+}
+
+/// Finds a category by name, ignoring case. Creates it if allowed.
+fn resolve_category(
+    conn: &PgConnection,
+    msg: &ImportProducts,
+    name: &str,
+    created: &mut Created,
+) -> Result<Option<Uuid>, Error> {
+    let id = c_dsl::categories
+        .filter(lower(c_dsl::name).eq(lower(name)))
+        .filter(c_dsl::deleted_at.is_null())
+        .select(c_dsl::id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    if id.is_some() || !msg.create_missing {
+        return Ok(id);
+    }
+
+    let data = CategoryData {
+        name: name.to_string(),
+        description: String::new(),
+        sort_order: 0,
+        parent_id: None,
+        meta_title: None,
+        meta_description: None,
+        canonical_url: None,
+        noindex: false,
+        publish_at: None,
+        unpublish_at: None,
+        slug: String::new(),
+    };
+    let category = insert_category(conn, &msg.author, data)?;
+    created.categories.push(category.name);
+    Ok(Some(category.id))
+}
+
+fn missing(field: &str) -> ValidationErrors {
+    let mut errors = ValidationErrors::new();
+    errors.add(field, "Required to match existing products");
+    errors
+}
+
+fn row_errors(err: Error) -> ValidationErrors {
+    match err.downcast::<ValidationErrors>() {
+        Ok(errors) => errors,
+        Err(err) => {
+            let mut errors = ValidationErrors::new();
+            errors.add("row", err.to_string());
+            errors
+        }
+    }
+}
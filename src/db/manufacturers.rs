@@ -1,11 +1,21 @@
 use actix::{Handler, Message};
-use diesel::{prelude::*, result::Error::NotFound};
+use chrono::{NaiveDateTime, Utc};
+use diesel::result::Error::NotFound;
+use diesel::{dsl::exists, prelude::*};
 use failure::Error;
+use uuid::Uuid;
 
+use super::audit::{self, Action, EntityType};
 use super::helpers;
-use super::DbActor;
-use crate::models::{Manufacturer, ManufacturerData};
+use super::products::{get_product, patch_audited_product, visible_products, ProductOwner};
+use super::{DbActor, DeleteConflict, Reference, VersionConflict};
+use crate::auth::Principal;
+use crate::models::{
+    DeletePolicy, Manufacturer, ManufacturerData, ManufacturerPatch, PageRequest, ProductPatch,
+    ProductPatchWithMeta, ValidationErrors, WithProducts,
+};
 use crate::schema::manufacturers::dsl;
+use crate::schema::products::dsl as p_dsl;
 
 #[derive(Debug)]
 pub struct ListManufacturers {}
@@ -20,6 +30,7 @@ impl Handler<ListManufacturers> for DbActor {
     fn handle(&mut self, _msg: ListManufacturers, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
         let manufacturers = dsl::manufacturers
+            .filter(dsl::deleted_at.is_null())
             .load::<Manufacturer>(&conn)
             .expect("Error loading manufacturers");
 
@@ -43,6 +54,7 @@ impl Handler<GetManufacturer> for DbActor {
         let conn = self.pool.get()?;
         let manufacturer = dsl::manufacturers
             .find(msg.id)
+            .filter(dsl::deleted_at.is_null())
             .first::<Manufacturer>(&conn)
             .optional()?;
 
@@ -50,8 +62,55 @@ impl Handler<GetManufacturer> for DbActor {
     }
 }
 
+/// Finds a manufacturer by slug, with a page of its visible products if requested
+#[derive(Debug)]
+pub struct GetManufacturerBySlug {
+    pub slug: String,
+    pub products: Option<PageRequest>,
+}
+
+impl Message for GetManufacturerBySlug {
+    type Result = Result<Option<WithProducts<Manufacturer>>, Error>;
+}
+
+impl Handler<GetManufacturerBySlug> for DbActor {
+    type Result = Result<Option<WithProducts<Manufacturer>>, Error>;
+
+    fn handle(&mut self, msg: GetManufacturerBySlug, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let manufacturer = dsl::manufacturers
+            .filter(dsl::slug.eq(&msg.slug))
+            .filter(dsl::deleted_at.is_null())
+            .first::<Manufacturer>(&conn)
+            .optional()?;
+        let manufacturer = match manufacturer {
+            Some(manufacturer) => manufacturer,
+            None => return Ok(None),
+        };
+
+        let products = match msg.products {
+            Some(page) => {
+                let owner = ProductOwner::Manufacturer(manufacturer.id);
+                Some(visible_products(
+                    &conn,
+                    owner,
+                    page,
+                    Utc::now().naive_utc(),
+                    self.prices_include_tax,
+                )?)
+            }
+            None => None,
+        };
+        Ok(Some(WithProducts {
+            entity: manufacturer,
+            products,
+        }))
+    }
+}
+
 #[derive(Debug)]
 pub struct InsertManufacturer {
+    pub author: Principal,
     pub data: ManufacturerData,
 }
 
@@ -62,25 +121,17 @@ impl Message for InsertManufacturer {
 impl Handler<InsertManufacturer> for DbActor {
     type Result = Result<Manufacturer, Error>;
 
-    fn handle(&mut self, msg: InsertManufacturer, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: InsertManufacturer, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        let man: Manufacturer = diesel::insert_into(dsl::manufacturers)
-            .values(&msg.data)
-            .get_result(&conn)
-            .map_err(Error::from)?;
-
-        let msg_update = UpdateManufacturer {
-            id: man.id,
-            data: msg.data,
-        };
-
-        self.handle(msg_update, ctx)
+        conn.transaction(|| insert_manufacturer(&conn, &msg.author, msg.data))
     }
 }
 
 #[derive(Debug)]
 pub struct UpdateManufacturer {
+    pub author: Principal,
     pub id: uuid::Uuid,
+    pub version: Option<i32>,
     pub data: ManufacturerData,
 }
 
@@ -91,19 +142,74 @@ impl Message for UpdateManufacturer {
 impl Handler<UpdateManufacturer> for DbActor {
     type Result = Result<Manufacturer, Error>;
 
-    fn handle(&mut self, mut msg: UpdateManufacturer, _: &mut Self::Context) -> Self::Result {
-        msg.data.slug = helpers::generate_slug(&msg.data.name, &msg.id);
+    fn handle(&mut self, msg: UpdateManufacturer, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::manufacturers
+                .find(msg.id)
+                .filter(dsl::deleted_at.is_null())
+                .first::<Manufacturer>(&conn)?;
+            bump_version(&conn, msg.id, msg.version.unwrap_or(before.version))?;
+            let man = update_manufacturer(&conn, msg.id, msg.data)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Manufacturer,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&man),
+            )?;
+            Ok(man)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PatchManufacturer {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+    pub version: Option<i32>,
+    pub patch: ManufacturerPatch,
+}
+
+impl Message for PatchManufacturer {
+    type Result = Result<Manufacturer, Error>;
+}
+
+impl Handler<PatchManufacturer> for DbActor {
+    type Result = Result<Manufacturer, Error>;
+
+    fn handle(&mut self, msg: PatchManufacturer, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        diesel::update(dsl::manufacturers.find(msg.id))
-            .set(&msg.data)
-            .get_result(&conn)
-            .map_err(Error::from)
+        conn.transaction(|| {
+            let before = dsl::manufacturers
+                .find(msg.id)
+                .filter(dsl::deleted_at.is_null())
+                .first::<Manufacturer>(&conn)?;
+            bump_version(&conn, msg.id, msg.version.unwrap_or(before.version))?;
+            let man = patch_manufacturer(&conn, msg.id, msg.patch)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Manufacturer,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&man),
+            )?;
+            Ok(man)
+        })
     }
 }
 
 #[derive(Debug)]
 pub struct DeleteManufacturer {
+    pub author: Principal,
     pub id: uuid::Uuid,
+
+    /// Handling of the products of the manufacturer
+    pub policy: DeletePolicy,
 }
 
 impl Message for DeleteManufacturer {
@@ -115,9 +221,183 @@ impl Handler<DeleteManufacturer> for DbActor {
 
     fn handle(&mut self, msg: DeleteManufacturer, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        diesel::delete(dsl::manufacturers.find(msg.id))
-            .execute(&conn)
-            .and_then(|c| if c > 0 { Ok(()) } else { Err(NotFound) })
-            .map_err(Error::from)
+        conn.transaction(|| {
+            let before = dsl::manufacturers
+                .find(msg.id)
+                .filter(dsl::deleted_at.is_null())
+                .first::<Manufacturer>(&conn)?;
+            let products = p_dsl::products
+                .filter(p_dsl::manufacturer_id.eq(msg.id))
+                .filter(p_dsl::deleted_at.is_null())
+                .select((p_dsl::id, p_dsl::name))
+                .order(p_dsl::name)
+                .load::<(Uuid, String)>(&conn)?;
+
+            let target = match msg.policy {
+                DeletePolicy::Restrict if !products.is_empty() => {
+                    let products = products
+                        .into_iter()
+                        .map(|(id, name)| Reference { id, name })
+                        .collect();
+                    return Err(DeleteConflict {
+                        products,
+                        ..Default::default()
+                    }
+                    .into());
+                }
+                DeletePolicy::Restrict | DeletePolicy::Detach => None,
+                DeletePolicy::Reassign(target) => {
+                    let target_query = dsl::manufacturers
+                        .find(target)
+                        .filter(dsl::deleted_at.is_null());
+                    let exists: bool = diesel::select(exists(target_query)).get_result(&conn)?;
+                    let mut errors = ValidationErrors::new();
+                    if !exists {
+                        let message = format!("Manufacturer {} doesn't exist", target);
+                        errors.add("reassign_to", message);
+                    } else if target == msg.id {
+                        errors.add("reassign_to", "Should not be the manufacturer itself");
+                    }
+                    errors.into_result()?;
+                    Some(target)
+                }
+            };
+
+            for (product_id, _) in products {
+                let product = get_product(&conn, product_id)?.ok_or(NotFound)?;
+                let patch = ProductPatchWithMeta {
+                    product: ProductPatch {
+                        manufacturer_id: Some(target),
+                        ..Default::default()
+                    },
+                    category_ids: None,
+                };
+                patch_audited_product(&conn, &msg.author, &product, patch)?;
+            }
+
+            diesel::update(dsl::manufacturers.find(msg.id))
+                .set((
+                    dsl::deleted_at.eq(diesel::dsl::now),
+                    dsl::version.eq(dsl::version + 1),
+                ))
+                .execute(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Manufacturer,
+                msg.id,
+                Action::Delete,
+                Some(&before),
+                None,
+            )
+        })
+    }
+}
+
+/// Takes a manufacturer out of the trash
+#[derive(Debug)]
+pub struct RestoreManufacturer {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+}
+
+impl Message for RestoreManufacturer {
+    type Result = Result<Manufacturer, Error>;
+}
+
+impl Handler<RestoreManufacturer> for DbActor {
+    type Result = Result<Manufacturer, Error>;
+
+    fn handle(&mut self, msg: RestoreManufacturer, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::manufacturers
+                .find(msg.id)
+                .filter(dsl::deleted_at.is_not_null())
+                .first::<Manufacturer>(&conn)?;
+            let after = diesel::update(dsl::manufacturers.find(msg.id))
+                .set((
+                    dsl::deleted_at.eq(None::<NaiveDateTime>),
+                    dsl::version.eq(dsl::version + 1),
+                ))
+                .get_result::<Manufacturer>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Manufacturer,
+                msg.id,
+                Action::Restore,
+                Some(&before),
+                Some(&after),
+            )?;
+            Ok(after)
+        })
+    }
+}
+
+/// Inserts a manufacturer. Should be called inside a transaction.
+pub(super) fn insert_manufacturer(
+    conn: &PgConnection,
+    author: &Principal,
+    data: ManufacturerData,
+) -> Result<Manufacturer, Error> {
+    let man: Manufacturer = diesel::insert_into(dsl::manufacturers)
+        .values(&data)
+        .get_result(conn)?;
+
+    // Update manufacturer to set slug
+    let man = update_manufacturer(conn, man.id, data)?;
+    audit::record(
+        conn,
+        author,
+        EntityType::Manufacturer,
+        man.id,
+        Action::Create,
+        None,
+        Some(&man),
+    )?;
+    Ok(man)
+}
+
+/// Updates the manufacturer and regenerates its slug
+fn update_manufacturer(
+    conn: &PgConnection,
+    id: Uuid,
+    mut data: ManufacturerData,
+) -> QueryResult<Manufacturer> {
+    data.slug = helpers::generate_slug(&data.name, &id);
+    diesel::update(dsl::manufacturers.find(id))
+        .set(&data)
+        .get_result(conn)
+}
+
+/// Updates only the provided fields of the manufacturer and regenerates its slug if renamed
+fn patch_manufacturer(
+    conn: &PgConnection,
+    id: Uuid,
+    mut patch: ManufacturerPatch,
+) -> QueryResult<Manufacturer> {
+    if let Some(name) = &patch.name {
+        patch.slug = Some(helpers::generate_slug(name, &id));
+    }
+    if !patch.is_empty() {
+        diesel::update(dsl::manufacturers.find(id))
+            .set(&patch)
+            .execute(conn)?;
+    }
+    dsl::manufacturers.find(id).first(conn)
+}
+
+/// Increments the version of the manufacturer. Fails with a `VersionConflict` if the
+/// manufacturer doesn't have the expected version. Should be called inside a transaction.
+fn bump_version(conn: &PgConnection, id: Uuid, expected: i32) -> Result<(), Error> {
+    let bumped = diesel::update(dsl::manufacturers.find(id))
+        .filter(dsl::version.eq(expected))
+        .set(dsl::version.eq(dsl::version + 1))
+        .execute(conn)?;
+    if bumped == 0 {
+        let current = dsl::manufacturers.find(id).first::<Manufacturer>(conn)?;
+        return Err(VersionConflict::new(current.version, &current).into());
     }
+    Ok(())
 }
@@ -1,16 +1,42 @@
+// Derives of failure and diesel 1.x predate this lint
+#![allow(non_local_definitions)]
+
 mod helpers;
+pub mod api_keys;
+pub mod audit;
+pub mod carts;
 pub mod categories;
+pub mod coupons;
+pub mod exchange_rates;
+pub mod import;
 pub mod manufacturers;
+pub mod orders;
 pub mod products;
+pub mod promotions;
+pub mod revisions;
+pub mod schedule;
+pub mod sitemap;
+pub mod translations;
+pub mod trash;
 
 use actix::{Actor, SyncContext};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel::PgConnection;
+use failure::Fail;
+use serde::Serialize;
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::http_cache::versioned_json;
 
 type DbPool = Pool<ConnectionManager<PgConnection>>;
 
 pub struct DbActor {
     pool: DbPool,
+
+    /// Whether the prices of products include tax
+    prices_include_tax: bool,
 }
 
 impl Actor for DbActor {
@@ -18,7 +44,86 @@ impl Actor for DbActor {
 }
 
 impl DbActor {
-    pub fn new(pool: DbPool) -> Self {
-        Self { pool }
+    pub fn new(pool: DbPool, prices_include_tax: bool) -> Self {
+        Self {
+            pool,
+            prices_include_tax,
+        }
+    }
+}
+
+/// Returned when an update is based on an outdated version of the entity
+#[derive(Debug, Fail)]
+#[fail(display = "Entity has been modified, current version is {}", version)]
+pub struct VersionConflict {
+    pub version: i32,
+
+    /// Current representation of the entity
+    pub current: Value,
+}
+
+impl VersionConflict {
+    pub fn new<T: Serialize>(version: i32, current: &T) -> Self {
+        Self {
+            version,
+            current: serde_json::to_value(current).unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// Entity which refers to another entity
+#[derive(Debug, Serialize)]
+pub struct Reference {
+    pub id: Uuid,
+    pub name: String,
+}
+
+/// Entity found by a slug
+#[derive(Debug)]
+pub struct BySlug<T> {
+    pub entity: T,
+
+    /// Whether the slug is a former slug of the entity, rather than one of its
+    /// current slugs in the locales
+    pub former: bool,
+}
+
+/// Returned when an entity can't be deleted because other entities refer to it
+#[derive(Debug, Default, Fail, Serialize)]
+#[fail(display = "Entity is still referenced by other entities")]
+pub struct DeleteConflict {
+    pub products: Vec<Reference>,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<Reference>,
+}
+
+impl DeleteConflict {
+    pub fn is_empty(&self) -> bool {
+        self.products.is_empty() && self.categories.is_empty()
+    }
+}
+
+impl ResponseError for DeleteConflict {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::CONFLICT
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
+impl ResponseError for VersionConflict {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::PRECONDITION_FAILED
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        versioned_json(
+            &mut HttpResponse::build(self.status_code()),
+            self.version,
+            &self.current,
+        )
     }
 }
@@ -0,0 +1,63 @@
+use actix::{Handler, Message};
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+use super::DbActor;
+use crate::models::{Order, OrderItem, OrderTax, OrderWithItems};
+use crate::schema::order_items::dsl as oi_dsl;
+use crate::schema::order_taxes::dsl as ot_dsl;
+use crate::schema::orders::dsl;
+
+/// Lists all orders, most recent first
+#[derive(Debug)]
+pub struct ListOrders {}
+
+impl Message for ListOrders {
+    type Result = Result<Vec<Order>, Error>;
+}
+
+impl Handler<ListOrders> for DbActor {
+    type Result = Result<Vec<Order>, Error>;
+
+    fn handle(&mut self, _msg: ListOrders, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let orders = dsl::orders
+            .order((dsl::created_at.desc(), dsl::id))
+            .load::<Order>(&conn)?;
+        Ok(orders)
+    }
+}
+
+#[derive(Debug)]
+pub struct GetOrder {
+    pub id: Uuid,
+}
+
+impl Message for GetOrder {
+    type Result = Result<Option<OrderWithItems>, Error>;
+}
+
+impl Handler<GetOrder> for DbActor {
+    type Result = Result<Option<OrderWithItems>, Error>;
+
+    fn handle(&mut self, msg: GetOrder, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let order = dsl::orders.find(msg.id).first::<Order>(&conn).optional()?;
+        let order = match order {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+        let items = OrderItem::belonging_to(&order)
+            .order(oi_dsl::line)
+            .load::<OrderItem>(&conn)?;
+        let taxes = OrderTax::belonging_to(&order)
+            .order(ot_dsl::rate.desc())
+            .load::<OrderTax>(&conn)?;
+        Ok(Some(OrderWithItems {
+            order,
+            items,
+            taxes,
+        }))
+    }
+}
@@ -1,16 +1,38 @@
 use actix::{Handler, Message};
-use diesel::{prelude::*, result::Error::NotFound};
-use failure::Error;
+use chrono::{NaiveDateTime, Utc};
+use diesel::dsl::exists;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::result::Error::{NotFound, RollbackTransaction};
+use diesel::sql_types::Bool;
+use failure::{format_err, Error};
+use serde_json::Value;
 use uuid::Uuid;
 
+use super::audit::{self, Action, EntityType};
 use super::helpers;
-use super::DbActor;
-use crate::models::{CategoryProduct, Product, ProductDataWithMeta, ProductWithMeta};
+use super::promotions;
+use super::revisions;
+use super::translations;
+use super::{BySlug, DbActor, VersionConflict};
+use crate::auth::Principal;
+use crate::models::{
+    BulkAction, BulkItemResult, BulkReport, BulkRequest, CategoryProduct, ExportedProduct, Page,
+    PageRequest, Product, ProductDataWithMeta, ProductPatchWithMeta, ProductPrice, ProductWithMeta,
+    ValidationErrors,
+};
+use crate::schema::categories::dsl as c_dsl;
 use crate::schema::category_products::dsl as cp_dsl;
+use crate::schema::manufacturers::dsl as m_dsl;
+use crate::schema::product_translations::dsl as pt_dsl;
 use crate::schema::products::dsl;
+use crate::schema::slug_history::dsl as sh_dsl;
 
 #[derive(Debug)]
-pub struct ListProducts {}
+pub struct ListProducts {
+    /// Excludes archived products and products outside their publishing period
+    pub published_only: bool,
+}
 
 impl Message for ListProducts {
     type Result = Result<Vec<ProductWithMeta>, Error>;
@@ -19,34 +41,143 @@ impl Message for ListProducts {
 impl Handler<ListProducts> for DbActor {
     type Result = Result<Vec<ProductWithMeta>, Error>;
 
-    fn handle(&mut self, _msg: ListProducts, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: ListProducts, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        let products = dsl::products
+        let now = Utc::now().naive_utc();
+        let mut query = dsl::products.filter(dsl::deleted_at.is_null()).into_boxed();
+        if msg.published_only {
+            query = query
+                .filter(dsl::status.ne("ARCHIVED"))
+                .filter(is_published(now));
+        }
+        let products = query
             .load::<Product>(&conn)
             .expect("Error loading products");
-        let category_ids = CategoryProduct::belonging_to(&products)
-            .load::<CategoryProduct>(&conn)
-            .expect("Error loading category products")
-            .grouped_by(&products);
-        let products_with_meta = products
+        let products_with_meta = with_meta_all(&conn, products)?;
+        Ok(with_pricing(
+            &conn,
+            products_with_meta,
+            now,
+            self.prices_include_tax,
+        )?)
+    }
+}
+
+/// Fetches a page of products for an export, ordered by ID. Pass the ID of
+/// the last product of the previous page as `after`.
+#[derive(Debug)]
+pub struct ExportProducts {
+    pub after: Option<Uuid>,
+    pub limit: i64,
+}
+
+impl Message for ExportProducts {
+    type Result = Result<Vec<ExportedProduct>, Error>;
+}
+
+impl Handler<ExportProducts> for DbActor {
+    type Result = Result<Vec<ExportedProduct>, Error>;
+
+    fn handle(&mut self, msg: ExportProducts, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let mut query = dsl::products
+            .left_join(m_dsl::manufacturers)
+            .filter(dsl::deleted_at.is_null())
+            .select((dsl::products::all_columns(), m_dsl::name.nullable()))
+            .order(dsl::id)
+            .limit(msg.limit)
+            .into_boxed();
+        if let Some(after) = msg.after {
+            query = query.filter(dsl::id.gt(after));
+        }
+        let products = query.load::<(Product, Option<String>)>(&conn)?;
+
+        // Fetch category names of the page
+        let ids: Vec<Uuid> = products.iter().map(|(p, _)| p.id).collect();
+        let categories = cp_dsl::category_products
+            .inner_join(c_dsl::categories)
+            .filter(cp_dsl::product_id.eq_any(&ids))
+            .filter(c_dsl::deleted_at.is_null())
+            .select((cp_dsl::product_id, c_dsl::name))
+            .order((cp_dsl::product_id, c_dsl::sort_order, c_dsl::name))
+            .load::<(Uuid, String)>(&conn)?;
+
+        let products = products
             .into_iter()
-            .zip(category_ids)
-            .map(|(product, category_ids)| {
-                let category_ids = category_ids.into_iter().map(|c| c.category_id).collect();
-                ProductWithMeta {
-                    product,
-                    category_ids,
-                }
+            .map(|(product, manufacturer)| ExportedProduct {
+                categories: categories
+                    .iter()
+                    .filter(|(id, _)| *id == product.id)
+                    .map(|(_, name)| name.clone())
+                    .collect(),
+                id: product.id,
+                sku: product.sku,
+                name: product.name,
+                slug: product.slug,
+                status: product.status,
+                price: product.price,
+                tax_rate: product.tax_rate,
+                stock_count: product.stock_count,
+                manufacturer,
+                created_at: product.created_at,
+                updated_at: product.updated_at,
             })
             .collect();
+        Ok(products)
+    }
+}
+
+/// Lists the products which can be advertised, with the name of their
+/// manufacturer and their prices
+#[derive(Debug)]
+pub struct ListFeedProducts {}
+
+impl Message for ListFeedProducts {
+    type Result = Result<Vec<(Product, Option<String>, ProductPrice)>, Error>;
+}
+
+impl Handler<ListFeedProducts> for DbActor {
+    type Result = Result<Vec<(Product, Option<String>, ProductPrice)>, Error>;
 
-        Ok(products_with_meta)
+    fn handle(&mut self, _msg: ListFeedProducts, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let now = Utc::now().naive_utc();
+        let products = dsl::products
+            .left_join(m_dsl::manufacturers)
+            .filter(dsl::status.ne("ARCHIVED"))
+            .filter(dsl::deleted_at.is_null())
+            .filter(is_published(now))
+            .select((dsl::products::all_columns(), m_dsl::name.nullable()))
+            .order(dsl::name)
+            .load::<(Product, Option<String>)>(&conn)?;
+
+        let rules = promotions::price_rules(&conn, now, self.prices_include_tax, false)?;
+        let ids: Vec<Uuid> = products.iter().map(|(p, _)| p.id).collect();
+        let categories = cp_dsl::category_products
+            .filter(cp_dsl::product_id.eq_any(&ids))
+            .load::<CategoryProduct>(&conn)?;
+        let products = products
+            .into_iter()
+            .map(|(product, manufacturer)| {
+                let category_ids: Vec<Uuid> = categories
+                    .iter()
+                    .filter(|c| c.product_id == product.id)
+                    .map(|c| c.category_id)
+                    .collect();
+                let price = rules.price(&product, &category_ids);
+                (product, manufacturer, price)
+            })
+            .collect();
+        Ok(products)
     }
 }
 
 #[derive(Debug)]
 pub struct GetProduct {
     pub id: uuid::Uuid,
+
+    /// Excludes archived products and products outside their publishing period
+    pub published_only: bool,
 }
 
 impl Message for GetProduct {
@@ -57,73 +188,92 @@ impl Handler<GetProduct> for DbActor {
     type Result = Result<Option<ProductWithMeta>, Error>;
 
     fn handle(&mut self, msg: GetProduct, _: &mut Self::Context) -> Self::Result {
-        // Fetch product
         let conn = self.pool.get()?;
-        let product = dsl::products
+        let now = Utc::now().naive_utc();
+        let mut query = dsl::products
             .find(msg.id)
-            .first::<Product>(&conn)
-            .optional()?;
-        if product.is_none() {
-            return Ok(None);
+            .filter(dsl::deleted_at.is_null())
+            .into_boxed();
+        if msg.published_only {
+            query = query
+                .filter(dsl::status.ne("ARCHIVED"))
+                .filter(is_published(now));
+        }
+        match query.first::<Product>(&conn).optional()? {
+            Some(product) => {
+                let product = with_meta(&conn, product)?;
+                Ok(with_pricing(&conn, vec![product], now, self.prices_include_tax)?.pop())
+            }
+            None => Ok(None),
         }
-
-        // Fetch related data
-        let product = product.unwrap();
-        let category_ids = CategoryProduct::belonging_to(&product)
-            .select(cp_dsl::category_id)
-            .load::<Uuid>(&conn)
-            .expect("Error loading category products");
-
-        // Build result
-        let product_with_meta = ProductWithMeta {
-            product,
-            category_ids,
-        };
-        Ok(Some(product_with_meta))
     }
 }
 
+/// Finds a product by its current slug or by one of its former slugs
 #[derive(Debug)]
 pub struct GetProductBySlug {
     pub slug: String,
+
+    /// Excludes archived products and products outside their publishing period
+    pub published_only: bool,
 }
 
 impl Message for GetProductBySlug {
-    type Result = Result<Option<ProductWithMeta>, Error>;
+    type Result = Result<Option<BySlug<ProductWithMeta>>, Error>;
 }
 
 impl Handler<GetProductBySlug> for DbActor {
-    type Result = Result<Option<ProductWithMeta>, Error>;
+    type Result = Result<Option<BySlug<ProductWithMeta>>, Error>;
 
     fn handle(&mut self, msg: GetProductBySlug, _: &mut Self::Context) -> Self::Result {
         // Fetch product
         let conn = self.pool.get()?;
-        let product = dsl::products
-            .filter(dsl::slug.eq_all(msg.slug))
+        let now = Utc::now().naive_utc();
+        let mut product = dsl::products
+            .filter(dsl::slug.eq_all(&msg.slug))
+            .filter(dsl::deleted_at.is_null())
             .first::<Product>(&conn)
             .optional()?;
         if product.is_none() {
-            return Ok(None);
+            if let Some(id) = translations::translated_product_id(&conn, &msg.slug)? {
+                product = dsl::products
+                    .find(id)
+                    .filter(dsl::deleted_at.is_null())
+                    .first::<Product>(&conn)
+                    .optional()?;
+            }
+        }
+        let former = product.is_none();
+        if former {
+            product = sh_dsl::slug_history
+                .inner_join(dsl::products)
+                .filter(sh_dsl::slug.eq_all(&msg.slug))
+                .filter(dsl::deleted_at.is_null())
+                .select(dsl::products::all_columns())
+                .first::<Product>(&conn)
+                .optional()?;
+        }
+        if msg.published_only {
+            product = product.filter(|p| p.status != "ARCHIVED" && p.is_published(now));
         }
 
         // Fetch related data
-        let product = product.unwrap();
-        let category_ids = CategoryProduct::belonging_to(&product)
-            .select(cp_dsl::category_id)
-            .load::<Uuid>(&conn)
-            .expect("Error loading category products");
-
-        // Build result
-        let product_with_meta = ProductWithMeta {
-            product,
-            category_ids,
-        };
-        Ok(Some(product_with_meta))
+        match product {
+            Some(product) => {
+                let product = with_meta(&conn, product)?;
+                let entity = with_pricing(&conn, vec![product], now, self.prices_include_tax)?
+                    .pop()
+                    .ok_or(NotFound)?;
+                Ok(Some(BySlug { entity, former }))
+            }
+            None => Ok(None),
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct InsertProduct {
+    pub author: Principal,
     pub data: ProductDataWithMeta,
 }
 
@@ -134,25 +284,17 @@ impl Message for InsertProduct {
 impl Handler<InsertProduct> for DbActor {
     type Result = Result<ProductWithMeta, Error>;
 
-    fn handle(&mut self, msg: InsertProduct, ctx: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: InsertProduct, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        // Insert product
-        let product = diesel::insert_into(dsl::products)
-            .values(&msg.data.product)
-            .get_result::<Product>(&conn)?;
-
-        // Update product to set slug and CategoryProducts
-        let msg_update = UpdateProduct {
-            id: product.id,
-            data: msg.data,
-        };
-        self.handle(msg_update, ctx)
+        conn.transaction(|| insert_product(&conn, &msg.author, msg.data))
     }
 }
 
 #[derive(Debug)]
 pub struct UpdateProduct {
+    pub author: Principal,
     pub id: uuid::Uuid,
+    pub version: Option<i32>,
     pub data: ProductDataWithMeta,
 }
 
@@ -163,41 +305,58 @@ impl Message for UpdateProduct {
 impl Handler<UpdateProduct> for DbActor {
     type Result = Result<ProductWithMeta, Error>;
 
-    fn handle(&mut self, mut msg: UpdateProduct, _: &mut Self::Context) -> Self::Result {
+    fn handle(&mut self, msg: UpdateProduct, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
         conn.transaction(|| {
-            // Update product
-            msg.data.product.slug = helpers::generate_slug(&msg.data.product.name, &msg.id);
-            let product = diesel::update(dsl::products.find(msg.id))
-                .set(&msg.data.product)
-                .get_result::<Product>(&conn)?;
-
-            // Remove old CategoryProducts
-            diesel::delete(cp_dsl::category_products.filter(cp_dsl::product_id.eq_all(product.id)))
-                .execute(&conn)?;
+            replace_audited_product(&conn, &msg.author, msg.id, msg.version, msg.data)
+        })
+    }
+}
 
-            // Recreate CategoryProducts
-            for category_id in msg.data.category_ids.iter() {
-                let category_product = CategoryProduct {
-                    product_id: product.id,
-                    category_id: category_id.clone(),
-                };
-                diesel::insert_into(cp_dsl::category_products)
-                    .values(category_product)
-                    .execute(&conn)?;
-            }
+#[derive(Debug)]
+pub struct PatchProduct {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+    pub version: Option<i32>,
+    pub patch: ProductPatchWithMeta,
+}
 
-            // Update successful
-            Ok(ProductWithMeta {
-                product,
-                category_ids: msg.data.category_ids,
-            })
+impl Message for PatchProduct {
+    type Result = Result<ProductWithMeta, Error>;
+}
+
+impl Handler<PatchProduct> for DbActor {
+    type Result = Result<ProductWithMeta, Error>;
+
+    fn handle(&mut self, msg: PatchProduct, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = get_product(&conn, msg.id)?.ok_or(NotFound)?;
+            let patch = &msg.patch;
+            let category_ids = patch.category_ids.as_deref().unwrap_or_default();
+            check_references(&conn, patch.product.manufacturer_id.flatten(), category_ids)?;
+            let sku = patch.product.sku.as_ref().and_then(Option::as_deref);
+            check_unique_sku(&conn, sku, Some(msg.id))?;
+            bump_version(&conn, msg.id, msg.version.unwrap_or(before.product.version))?;
+            let product = patch_product(&conn, msg.id, msg.patch)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Product,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&product),
+            )?;
+            revisions::record(&conn, &msg.author, &product)?;
+            Ok(product)
         })
     }
 }
 
 #[derive(Debug)]
 pub struct DeleteProduct {
+    pub author: Principal,
     pub id: uuid::Uuid,
 }
 
@@ -210,9 +369,680 @@ impl Handler<DeleteProduct> for DbActor {
 
     fn handle(&mut self, msg: DeleteProduct, _: &mut Self::Context) -> Self::Result {
         let conn = self.pool.get()?;
-        diesel::delete(dsl::products.find(msg.id))
-            .execute(&conn)
-            .and_then(|c| if c > 0 { Ok(()) } else { Err(NotFound) })
-            .map_err(Error::from)
+        conn.transaction(|| {
+            let before = get_product(&conn, msg.id)?.ok_or(NotFound)?;
+            trash_product(&conn, &msg.author, &before)
+        })
+    }
+}
+
+/// Takes a product out of the trash. Categories and a manufacturer which are
+/// still in the trash are removed from the product.
+#[derive(Debug)]
+pub struct RestoreProduct {
+    pub author: Principal,
+    pub id: uuid::Uuid,
+}
+
+impl Message for RestoreProduct {
+    type Result = Result<ProductWithMeta, Error>;
+}
+
+impl Handler<RestoreProduct> for DbActor {
+    type Result = Result<ProductWithMeta, Error>;
+
+    fn handle(&mut self, msg: RestoreProduct, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::products
+                .find(msg.id)
+                .filter(dsl::deleted_at.is_not_null())
+                .first::<Product>(&conn)
+                .optional()?
+                .ok_or(NotFound)?;
+            let before = with_meta(&conn, before)?;
+
+            // Drop references to entities which are still in the trash
+            let trashed_categories = c_dsl::categories
+                .filter(c_dsl::deleted_at.is_not_null())
+                .select(c_dsl::id);
+            diesel::delete(
+                cp_dsl::category_products
+                    .filter(cp_dsl::product_id.eq(msg.id))
+                    .filter(cp_dsl::category_id.eq_any(trashed_categories)),
+            )
+            .execute(&conn)?;
+            let trashed_manufacturers = m_dsl::manufacturers
+                .filter(m_dsl::deleted_at.is_not_null())
+                .select(m_dsl::id.nullable());
+            diesel::update(
+                dsl::products
+                    .find(msg.id)
+                    .filter(dsl::manufacturer_id.eq_any(trashed_manufacturers)),
+            )
+            .set(dsl::manufacturer_id.eq(None::<Uuid>))
+            .execute(&conn)?;
+
+            diesel::update(dsl::products.find(msg.id))
+                .set((
+                    dsl::deleted_at.eq(None::<NaiveDateTime>),
+                    dsl::version.eq(dsl::version + 1),
+                ))
+                .execute(&conn)?;
+            let after = get_product(&conn, msg.id)?.ok_or(NotFound)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Product,
+                msg.id,
+                Action::Restore,
+                Some(&before),
+                Some(&after),
+            )?;
+            revisions::record(&conn, &msg.author, &after)?;
+            Ok(after)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct BulkUpdateProducts {
+    pub author: Principal,
+    pub request: BulkRequest,
+}
+
+impl Message for BulkUpdateProducts {
+    type Result = Result<BulkReport, Error>;
+}
+
+impl Handler<BulkUpdateProducts> for DbActor {
+    type Result = Result<BulkReport, Error>;
+
+    fn handle(&mut self, msg: BulkUpdateProducts, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let dry_run = msg.request.dry_run;
+        let mut results = Vec::new();
+        let outcome = conn.transaction::<_, Error, _>(|| {
+            for (index, operation) in msg.request.operations.iter().enumerate() {
+                for &id in &operation.product_ids {
+                    // Each item runs in a savepoint, so a failing item doesn't
+                    // prevent reporting on the remaining items.
+                    let changes = conn.transaction(|| {
+                        apply_bulk_action(&conn, &msg.author, id, &operation.action)
+                    });
+                    let (changes, error) = match changes {
+                        Ok(changes) => (changes, None),
+                        Err(e) => (None, Some(bulk_error_message(&e))),
+                    };
+                    results.push(BulkItemResult {
+                        operation: index,
+                        action: operation.action.as_str(),
+                        product_id: id,
+                        changes,
+                        error,
+                    });
+                }
+            }
+
+            if dry_run || results.iter().any(|r| r.error.is_some()) {
+                return Err(RollbackTransaction.into());
+            }
+            Ok(())
+        });
+
+        let applied = match outcome {
+            Ok(()) => true,
+            Err(e) if matches!(e.downcast_ref(), Some(RollbackTransaction)) => false,
+            Err(e) => return Err(e),
+        };
+        Ok(BulkReport {
+            dry_run,
+            applied,
+            results,
+        })
+    }
+}
+
+/// Applies a bulk action to a single product and returns the changed fields.
+/// Should be called inside a transaction.
+fn apply_bulk_action(
+    conn: &PgConnection,
+    author: &Principal,
+    id: Uuid,
+    action: &BulkAction,
+) -> Result<Option<Value>, Error> {
+    let before =
+        get_product(conn, id)?.ok_or_else(|| format_err!("Product {} doesn't exist", id))?;
+    let patch = match action.to_patch(&before)? {
+        Some(patch) => patch,
+        None => {
+            trash_product(conn, author, &before)?;
+            return Ok(None);
+        }
+    };
+
+    let after = patch_audited_product(conn, author, &before, patch)?;
+    let changes = helpers::json_diff(
+        &serde_json::to_value(&before)?,
+        &serde_json::to_value(&after)?,
+    );
+    Ok(Some(changes))
+}
+
+/// Patches the product on behalf of the author, bumping its version and
+/// recording the change. Should be called inside a transaction.
+pub(super) fn patch_audited_product(
+    conn: &PgConnection,
+    author: &Principal,
+    before: &ProductWithMeta,
+    patch: ProductPatchWithMeta,
+) -> Result<ProductWithMeta, Error> {
+    let id = before.product.id;
+    let category_ids = patch.category_ids.as_deref().unwrap_or_default();
+    check_references(conn, patch.product.manufacturer_id.flatten(), category_ids)?;
+    bump_version(conn, id, before.product.version)?;
+    let after = patch_product(conn, id, patch)?;
+    audit::record(
+        conn,
+        author,
+        EntityType::Product,
+        id,
+        Action::Update,
+        Some(before),
+        Some(&after),
+    )?;
+    revisions::record(conn, author, &after)?;
+    Ok(after)
+}
+
+/// Replaces the product on behalf of the author, bumping its version and
+/// recording the change. The current version is expected if no version is
+/// provided. Should be called inside a transaction.
+pub(super) fn replace_audited_product(
+    conn: &PgConnection,
+    author: &Principal,
+    id: Uuid,
+    version: Option<i32>,
+    data: ProductDataWithMeta,
+) -> Result<ProductWithMeta, Error> {
+    let before = get_product(conn, id)?.ok_or(NotFound)?;
+    check_references(conn, data.product.manufacturer_id, &data.category_ids)?;
+    check_unique_sku(conn, data.product.sku.as_deref(), Some(id))?;
+    bump_version(conn, id, version.unwrap_or(before.product.version))?;
+    let after = update_product(conn, id, data)?;
+    audit::record(
+        conn,
+        author,
+        EntityType::Product,
+        id,
+        Action::Update,
+        Some(&before),
+        Some(&after),
+    )?;
+    revisions::record(conn, author, &after)?;
+    Ok(after)
+}
+
+/// Moves the product to the trash, bumping its version and recording the
+/// change. Should be called inside a transaction.
+fn trash_product(
+    conn: &PgConnection,
+    author: &Principal,
+    before: &ProductWithMeta,
+) -> Result<(), Error> {
+    let id = before.product.id;
+    diesel::update(dsl::products.find(id))
+        .set((
+            dsl::deleted_at.eq(diesel::dsl::now),
+            dsl::version.eq(dsl::version + 1),
+        ))
+        .execute(conn)?;
+    audit::record(
+        conn,
+        author,
+        EntityType::Product,
+        id,
+        Action::Delete,
+        Some(before),
+        None,
+    )
+}
+
+fn bulk_error_message(err: &Error) -> String {
+    match err.downcast_ref::<ValidationErrors>() {
+        Some(errors) => errors.messages().join("; "),
+        None => err.to_string(),
+    }
+}
+
+/// Inserts a product with its CategoryProducts. Should be called inside a transaction.
+pub(super) fn insert_product(
+    conn: &PgConnection,
+    author: &Principal,
+    data: ProductDataWithMeta,
+) -> Result<ProductWithMeta, Error> {
+    check_references(conn, data.product.manufacturer_id, &data.category_ids)?;
+    check_unique_sku(conn, data.product.sku.as_deref(), None)?;
+    check_unique_slug(conn, &data.product.slug, None)?;
+
+    // Insert product
+    let product = diesel::insert_into(dsl::products)
+        .values(&data.product)
+        .get_result::<Product>(conn)?;
+
+    // Update product to set slug and CategoryProducts
+    let product = update_product(conn, product.id, data)?;
+    audit::record(
+        conn,
+        author,
+        EntityType::Product,
+        product.product.id,
+        Action::Create,
+        None,
+        Some(&product),
+    )?;
+    revisions::record(conn, author, &product)?;
+    Ok(product)
+}
+
+/// Fetches a product with its related data, unless it's in the trash
+pub(super) fn get_product(conn: &PgConnection, id: Uuid) -> QueryResult<Option<ProductWithMeta>> {
+    let product = dsl::products
+        .find(id)
+        .filter(dsl::deleted_at.is_null())
+        .first::<Product>(conn)
+        .optional()?;
+    match product {
+        Some(product) => Ok(Some(with_meta(conn, product)?)),
+        None => Ok(None),
+    }
+}
+
+fn with_meta_all(conn: &PgConnection, products: Vec<Product>) -> QueryResult<Vec<ProductWithMeta>> {
+    let category_ids = CategoryProduct::belonging_to(&products)
+        .load::<CategoryProduct>(conn)?
+        .grouped_by(&products);
+    let products_with_meta = products
+        .into_iter()
+        .zip(category_ids)
+        .map(|(product, category_ids)| {
+            let category_ids = category_ids.into_iter().map(|c| c.category_id).collect();
+            ProductWithMeta {
+                product,
+                category_ids,
+                pricing: None,
+            }
+        })
+        .collect();
+    Ok(products_with_meta)
+}
+
+/// Adds the prices after sales and promotions to the products
+fn with_pricing(
+    conn: &PgConnection,
+    mut products: Vec<ProductWithMeta>,
+    now: NaiveDateTime,
+    prices_include_tax: bool,
+) -> QueryResult<Vec<ProductWithMeta>> {
+    let rules = promotions::price_rules(conn, now, prices_include_tax, false)?;
+    for product in &mut products {
+        product.pricing = Some(rules.price(&product.product, &product.category_ids));
+    }
+    Ok(products)
+}
+
+/// Condition which matches the products inside their publishing period at the
+/// provided moment. The moment is taken from the clock of the backend, like
+/// the checks of loaded products and categories.
+pub(super) fn is_published<QS>(
+    now: NaiveDateTime,
+) -> Box<dyn BoxableExpression<QS, Pg, SqlType = Bool>>
+where
+    dsl::publish_at: SelectableExpression<QS>,
+    dsl::unpublish_at: SelectableExpression<QS>,
+{
+    Box::new(
+        dsl::publish_at
+            .is_null()
+            .or(dsl::publish_at.le(now))
+            .and(dsl::unpublish_at.is_null().or(dsl::unpublish_at.gt(now))),
+    )
+}
+
+/// Owner of the products listed by `visible_products`
+pub(super) enum ProductOwner {
+    /// Products in any of the categories
+    Categories(Vec<Uuid>),
+    Manufacturer(Uuid),
+}
+
+/// Fetches a page of the products of a category or manufacturer which are
+/// published at the provided moment and aren't archived, ordered by name
+pub(super) fn visible_products(
+    conn: &PgConnection,
+    owner: ProductOwner,
+    page: PageRequest,
+    now: NaiveDateTime,
+    prices_include_tax: bool,
+) -> QueryResult<Page<ProductWithMeta>> {
+    let query = || {
+        let query = dsl::products
+            .filter(dsl::status.ne("ARCHIVED"))
+            .filter(dsl::deleted_at.is_null())
+            .filter(is_published(now))
+            .into_boxed();
+        match owner {
+            ProductOwner::Categories(ref ids) => query.filter(
+                dsl::id.eq_any(
+                    cp_dsl::category_products
+                        .filter(cp_dsl::category_id.eq_any(ids))
+                        .select(cp_dsl::product_id),
+                ),
+            ),
+            ProductOwner::Manufacturer(id) => query.filter(dsl::manufacturer_id.eq(id)),
+        }
+    };
+    let total = query().count().get_result::<i64>(conn)?;
+    let products = query()
+        .order((dsl::name, dsl::id))
+        .limit(page.limit)
+        .offset(page.offset)
+        .load::<Product>(conn)?;
+    Ok(Page {
+        items: with_pricing(
+            conn,
+            with_meta_all(conn, products)?,
+            now,
+            prices_include_tax,
+        )?,
+        total,
+        limit: page.limit,
+        offset: page.offset,
+    })
+}
+
+fn with_meta(conn: &PgConnection, product: Product) -> QueryResult<ProductWithMeta> {
+    let category_ids = CategoryProduct::belonging_to(&product)
+        .select(cp_dsl::category_id)
+        .load::<Uuid>(conn)?;
+    Ok(ProductWithMeta {
+        product,
+        category_ids,
+        pricing: None,
+    })
+}
+
+/// Updates the product and its CategoryProducts. Should be called inside a transaction.
+fn update_product(
+    conn: &PgConnection,
+    id: Uuid,
+    mut data: ProductDataWithMeta,
+) -> Result<ProductWithMeta, Error> {
+    // Update product
+    let (name, slug) = current_slug(conn, id)?;
+    let requested = Some(data.product.slug.as_str());
+    data.product.slug = helpers::next_slug(&id, &name, &slug, requested, &data.product.name);
+    retire_slug(conn, id, &slug, &data.product.slug)?;
+    let product = diesel::update(dsl::products.find(id))
+        .set(&data.product)
+        .get_result::<Product>(conn)?;
+    helpers::check_schedule(product.publish_at, product.unpublish_at)?;
+    helpers::check_sale(&product)?;
+
+    // Recreate CategoryProducts
+    set_categories(conn, product.id, &data.category_ids)?;
+
+    // Update successful
+    Ok(ProductWithMeta {
+        product,
+        category_ids: data.category_ids,
+        pricing: None,
+    })
+}
+
+/// Updates only the provided fields of the product. Should be called inside a transaction.
+pub(super) fn patch_product(
+    conn: &PgConnection,
+    id: Uuid,
+    mut patch: ProductPatchWithMeta,
+) -> Result<ProductWithMeta, Error> {
+    let (name, slug) = current_slug(conn, id)?;
+    let new_name = patch.product.name.as_deref().unwrap_or(&name);
+    let requested = patch.product.slug.as_deref();
+    let next_slug = helpers::next_slug(&id, &name, &slug, requested, new_name);
+    retire_slug(conn, id, &slug, &next_slug)?;
+    patch.product.slug = Some(next_slug).filter(|s| *s != slug);
+
+    if !patch.product.is_empty() {
+        diesel::update(dsl::products.find(id))
+            .set(&patch.product)
+            .execute(conn)?;
+    }
+    if let Some(category_ids) = &patch.category_ids {
+        set_categories(conn, id, category_ids)?;
+    }
+    let product = get_product(conn, id)?.ok_or(NotFound)?;
+    helpers::check_schedule(product.product.publish_at, product.product.unpublish_at)?;
+    helpers::check_sale(&product.product)?;
+    Ok(product)
+}
+
+/// Detaches the products from the manufacturers which are purged on behalf
+/// of the author, bumping their versions and recording the changes. Only
+/// products in the trash can still refer to them. Should be called inside a
+/// transaction.
+pub(super) fn detach_manufacturers(
+    conn: &PgConnection,
+    author: &Principal,
+    manufacturer_ids: &[Uuid],
+) -> Result<(), Error> {
+    let products = dsl::products
+        .filter(dsl::manufacturer_id.eq_any(manufacturer_ids))
+        .load::<Product>(conn)?;
+    for product in products {
+        let before = with_meta(conn, product)?;
+        let id = before.product.id;
+        let after = diesel::update(dsl::products.find(id))
+            .set((
+                dsl::manufacturer_id.eq(None::<Uuid>),
+                dsl::version.eq(dsl::version + 1),
+            ))
+            .get_result::<Product>(conn)?;
+        let after = with_meta(conn, after)?;
+        audit::record(
+            conn,
+            author,
+            EntityType::Product,
+            id,
+            Action::Update,
+            Some(&before),
+            Some(&after),
+        )?;
+        revisions::record(conn, author, &after)?;
+    }
+    Ok(())
+}
+
+/// Returns the current name and slug of the product
+fn current_slug(conn: &PgConnection, id: Uuid) -> QueryResult<(String, String)> {
+    dsl::products
+        .find(id)
+        .select((dsl::name, dsl::slug))
+        .first(conn)
+}
+
+/// Keeps the current slug of the product in the slug history when it's
+/// replaced, either its own slug or the slug of one of its translations.
+/// Fails if the new slug is or was used by another product.
+pub(super) fn retire_slug(
+    conn: &PgConnection,
+    id: Uuid,
+    current: &str,
+    new: &str,
+) -> Result<(), Error> {
+    if current == new {
+        return Ok(());
+    }
+    check_unique_slug(conn, new, Some(id))?;
+
+    // The product may take back one of its former slugs
+    diesel::delete(sh_dsl::slug_history.find(new)).execute(conn)?;
+    if !current.is_empty() {
+        diesel::insert_into(sh_dsl::slug_history)
+            .values((sh_dsl::slug.eq(current), sh_dsl::product_id.eq(id)))
+            .on_conflict(sh_dsl::slug)
+            .do_update()
+            .set((
+                sh_dsl::product_id.eq(id),
+                sh_dsl::retired_at.eq(diesel::dsl::now),
+            ))
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Verifies the slug isn't used by another product, now or in the past, in
+/// any locale. Products in the trash keep their slugs.
+pub(super) fn check_unique_slug(
+    conn: &PgConnection,
+    slug: &str,
+    id: Option<Uuid>,
+) -> Result<(), Error> {
+    if slug.is_empty() {
+        return Ok(());
+    }
+    let owner = dsl::products
+        .filter(dsl::slug.eq(slug))
+        .select(dsl::id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    let former_owner = sh_dsl::slug_history
+        .find(slug)
+        .select(sh_dsl::product_id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    let translation_owner = pt_dsl::product_translations
+        .filter(pt_dsl::slug.eq(slug))
+        .select(pt_dsl::product_id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    let other = [owner, former_owner, translation_owner]
+        .iter()
+        .flatten()
+        .find(|&&owner| Some(owner) != id)
+        .copied();
+    if let Some(other) = other {
+        let mut errors = ValidationErrors::new();
+        errors.add(
+            "slug",
+            in_use_message(conn, &format!("Slug {}", slug), other)?,
+        );
+        return Err(errors.into());
+    }
+    Ok(())
+}
+
+/// Replaces the CategoryProducts of the product
+fn set_categories(conn: &PgConnection, product_id: Uuid, category_ids: &[Uuid]) -> QueryResult<()> {
+    // Remove old CategoryProducts
+    diesel::delete(cp_dsl::category_products.filter(cp_dsl::product_id.eq_all(product_id)))
+        .execute(conn)?;
+
+    // Recreate CategoryProducts
+    for category_id in category_ids.iter() {
+        let category_product = CategoryProduct {
+            product_id,
+            category_id: *category_id,
+        };
+        diesel::insert_into(cp_dsl::category_products)
+            .values(category_product)
+            .execute(conn)?;
+    }
+    Ok(())
+}
+
+/// Verifies the referenced manufacturer and categories exist and aren't in the trash
+pub(super) fn check_references(
+    conn: &PgConnection,
+    manufacturer_id: Option<Uuid>,
+    category_ids: &[Uuid],
+) -> Result<(), Error> {
+    let mut errors = ValidationErrors::new();
+    if let Some(id) = manufacturer_id {
+        let manufacturer = m_dsl::manufacturers
+            .find(id)
+            .filter(m_dsl::deleted_at.is_null());
+        let exists: bool = diesel::select(exists(manufacturer)).get_result(conn)?;
+        if !exists {
+            errors.add(
+                "manufacturer_id",
+                format!("Manufacturer {} doesn't exist", id),
+            );
+        }
+    }
+
+    let found = c_dsl::categories
+        .filter(c_dsl::id.eq_any(category_ids))
+        .filter(c_dsl::deleted_at.is_null())
+        .select(c_dsl::id)
+        .load::<Uuid>(conn)?;
+    for id in category_ids.iter().filter(|id| !found.contains(id)) {
+        errors.add("category_ids", format!("Category {} doesn't exist", id));
+    }
+    Ok(errors.into_result()?)
+}
+
+/// Verifies no other product uses the SKU, including products in the trash
+pub(super) fn check_unique_sku(
+    conn: &PgConnection,
+    sku: Option<&str>,
+    id: Option<Uuid>,
+) -> Result<(), Error> {
+    let sku = match sku {
+        Some(sku) => sku,
+        None => return Ok(()),
+    };
+    let owner = dsl::products
+        .filter(dsl::sku.eq(sku))
+        .select(dsl::id)
+        .first::<Uuid>(conn)
+        .optional()?;
+    if let Some(owner) = owner.filter(|&owner| Some(owner) != id) {
+        let mut errors = ValidationErrors::new();
+        errors.add("sku", in_use_message(conn, &format!("SKU {}", sku), owner)?);
+        return Err(errors.into());
+    }
+    Ok(())
+}
+
+/// Describes the conflict with the product which holds a unique value. As
+/// products in the trash keep their values until they're purged, these are
+/// pointed out, so the product can be restored or deleted permanently.
+fn in_use_message(conn: &PgConnection, value: &str, owner: Uuid) -> QueryResult<String> {
+    let trashed = dsl::products
+        .find(owner)
+        .select(dsl::deleted_at.is_not_null())
+        .first::<bool>(conn)?;
+    if trashed {
+        Ok(format!(
+            "{} is already in use by product {} in the trash",
+            value, owner
+        ))
+    } else {
+        Ok(format!("{} is already in use", value))
+    }
+}
+
+/// Increments the version of the product. Fails with a `VersionConflict` if the
+/// product doesn't have the expected version. Should be called inside a transaction.
+pub(super) fn bump_version(conn: &PgConnection, id: Uuid, expected: i32) -> Result<(), Error> {
+    let bumped = diesel::update(dsl::products.find(id))
+        .filter(dsl::version.eq(expected))
+        .set(dsl::version.eq(dsl::version + 1))
+        .execute(conn)?;
+    if bumped == 0 {
+        let current = get_product(conn, id)?.ok_or(NotFound)?;
+        return Err(VersionConflict::new(current.product.version, &current).into());
     }
+    Ok(())
 }
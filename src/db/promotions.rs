@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+
+use actix::{Handler, Message};
+use chrono::NaiveDateTime;
+use diesel::{dsl::exists, prelude::*};
+use failure::Error;
+use uuid::Uuid;
+
+use super::audit::{self, Action, EntityType};
+use super::{DbActor, VersionConflict};
+use crate::auth::Principal;
+use crate::models::{PriceRules, Promotion, PromotionData, ValidationErrors};
+use crate::schema::categories::dsl as c_dsl;
+use crate::schema::manufacturers::dsl as m_dsl;
+use crate::schema::products::dsl as p_dsl;
+use crate::schema::promotions::dsl;
+
+#[derive(Debug)]
+pub struct ListPromotions {}
+
+impl Message for ListPromotions {
+    type Result = Result<Vec<Promotion>, Error>;
+}
+
+impl Handler<ListPromotions> for DbActor {
+    type Result = Result<Vec<Promotion>, Error>;
+
+    fn handle(&mut self, _msg: ListPromotions, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let promotions = dsl::promotions
+            .order((dsl::created_at, dsl::id))
+            .load::<Promotion>(&conn)?;
+        Ok(promotions)
+    }
+}
+
+#[derive(Debug)]
+pub struct GetPromotion {
+    pub id: Uuid,
+}
+
+impl Message for GetPromotion {
+    type Result = Result<Option<Promotion>, Error>;
+}
+
+impl Handler<GetPromotion> for DbActor {
+    type Result = Result<Option<Promotion>, Error>;
+
+    fn handle(&mut self, msg: GetPromotion, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let promotion = dsl::promotions
+            .find(msg.id)
+            .first::<Promotion>(&conn)
+            .optional()?;
+        Ok(promotion)
+    }
+}
+
+#[derive(Debug)]
+pub struct InsertPromotion {
+    pub author: Principal,
+    pub data: PromotionData,
+}
+
+impl Message for InsertPromotion {
+    type Result = Result<Promotion, Error>;
+}
+
+impl Handler<InsertPromotion> for DbActor {
+    type Result = Result<Promotion, Error>;
+
+    fn handle(&mut self, msg: InsertPromotion, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            check_targets(&conn, &msg.data)?;
+            let promotion = diesel::insert_into(dsl::promotions)
+                .values(&msg.data)
+                .get_result::<Promotion>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Promotion,
+                promotion.id,
+                Action::Create,
+                None,
+                Some(&promotion),
+            )?;
+            Ok(promotion)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct UpdatePromotion {
+    pub author: Principal,
+    pub id: Uuid,
+    pub version: Option<i32>,
+    pub data: PromotionData,
+}
+
+impl Message for UpdatePromotion {
+    type Result = Result<Promotion, Error>;
+}
+
+impl Handler<UpdatePromotion> for DbActor {
+    type Result = Result<Promotion, Error>;
+
+    fn handle(&mut self, msg: UpdatePromotion, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before = dsl::promotions.find(msg.id).first::<Promotion>(&conn)?;
+            let expected = msg.version.unwrap_or(before.version);
+            check_targets(&conn, &msg.data)?;
+            let promotion = diesel::update(dsl::promotions.find(msg.id))
+                .filter(dsl::version.eq(expected))
+                .set((&msg.data, dsl::version.eq(dsl::version + 1)))
+                .get_result::<Promotion>(&conn)
+                .optional()?;
+            let promotion = match promotion {
+                Some(promotion) => promotion,
+                None => return Err(VersionConflict::new(before.version, &before).into()),
+            };
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Promotion,
+                msg.id,
+                Action::Update,
+                Some(&before),
+                Some(&promotion),
+            )?;
+            Ok(promotion)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DeletePromotion {
+    pub author: Principal,
+    pub id: Uuid,
+}
+
+impl Message for DeletePromotion {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeletePromotion> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeletePromotion, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before =
+                diesel::delete(dsl::promotions.find(msg.id)).get_result::<Promotion>(&conn)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Promotion,
+                msg.id,
+                Action::Delete,
+                Some(&before),
+                None,
+            )
+        })
+    }
+}
+
+/// Removes purged entities from the promotions on behalf of the author.
+/// Promotions of purged categories and manufacturers are deleted. Purged
+/// products are removed from the products of a promotion, which is deleted
+/// when no products are left. Should be called inside the transaction of the
+/// purge, before the entities are deleted.
+pub(super) fn purge_targets(
+    conn: &PgConnection,
+    author: &Principal,
+    product_ids: &[Uuid],
+    category_ids: &[Uuid],
+    manufacturer_ids: &[Uuid],
+) -> Result<(), Error> {
+    let promotions = dsl::promotions
+        .filter(
+            dsl::category_id
+                .eq_any(category_ids)
+                .or(dsl::manufacturer_id.eq_any(manufacturer_ids))
+                .or(dsl::product_ids.overlaps_with(product_ids)),
+        )
+        .load::<Promotion>(conn)?;
+    for before in promotions {
+        let remaining: Vec<Uuid> = before
+            .product_ids
+            .iter()
+            .filter(|id| !product_ids.contains(id))
+            .copied()
+            .collect();
+        if before.product_ids.is_empty() || remaining.is_empty() {
+            diesel::delete(dsl::promotions.find(before.id)).execute(conn)?;
+            audit::record(
+                conn,
+                author,
+                EntityType::Promotion,
+                before.id,
+                Action::Delete,
+                Some(&before),
+                None,
+            )?;
+        } else {
+            let after = diesel::update(dsl::promotions.find(before.id))
+                .set((
+                    dsl::product_ids.eq(remaining),
+                    dsl::version.eq(dsl::version + 1),
+                ))
+                .get_result::<Promotion>(conn)?;
+            audit::record(
+                conn,
+                author,
+                EntityType::Promotion,
+                before.id,
+                Action::Update,
+                Some(&before),
+                Some(&after),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the promotions which run at the provided moment. The category
+/// hierarchy is only loaded if a promotion applies to a category, or if
+/// `with_categories` requests it, e.g. for coupons of categories.
+pub(super) fn price_rules(
+    conn: &PgConnection,
+    now: NaiveDateTime,
+    prices_include_tax: bool,
+    with_categories: bool,
+) -> QueryResult<PriceRules> {
+    let promotions = dsl::promotions
+        .filter(dsl::starts_at.is_null().or(dsl::starts_at.le(now)))
+        .filter(dsl::ends_at.is_null().or(dsl::ends_at.gt(now)))
+        .order((dsl::created_at, dsl::id))
+        .load::<Promotion>(conn)?;
+    let parents: HashMap<Uuid, Option<Uuid>> =
+        if with_categories || promotions.iter().any(|p| p.category_id.is_some()) {
+            c_dsl::categories
+                .filter(c_dsl::deleted_at.is_null())
+                .select((c_dsl::id, c_dsl::parent_id))
+                .load::<(Uuid, Option<Uuid>)>(conn)?
+                .into_iter()
+                .collect()
+        } else {
+            HashMap::new()
+        };
+    Ok(PriceRules::new(
+        now,
+        promotions,
+        parents,
+        prices_include_tax,
+    ))
+}
+
+/// Verifies the category, manufacturer and products of the promotion exist
+fn check_targets(conn: &PgConnection, data: &PromotionData) -> Result<(), Error> {
+    let mut errors = ValidationErrors::new();
+    if let Some(id) = data.category_id {
+        let category = c_dsl::categories
+            .find(id)
+            .filter(c_dsl::deleted_at.is_null());
+        let exists: bool = diesel::select(exists(category)).get_result(conn)?;
+        if !exists {
+            errors.add("category_id", format!("Category {} doesn't exist", id));
+        }
+    }
+    if let Some(id) = data.manufacturer_id {
+        let manufacturer = m_dsl::manufacturers
+            .find(id)
+            .filter(m_dsl::deleted_at.is_null());
+        let exists: bool = diesel::select(exists(manufacturer)).get_result(conn)?;
+        if !exists {
+            errors.add(
+                "manufacturer_id",
+                format!("Manufacturer {} doesn't exist", id),
+            );
+        }
+    }
+
+    let found = p_dsl::products
+        .filter(p_dsl::id.eq_any(&data.product_ids))
+        .filter(p_dsl::deleted_at.is_null())
+        .select(p_dsl::id)
+        .load::<Uuid>(conn)?;
+    for id in data.product_ids.iter().filter(|id| !found.contains(id)) {
+        errors.add("product_ids", format!("Product {} doesn't exist", id));
+    }
+    Ok(errors.into_result()?)
+}
@@ -0,0 +1,138 @@
+use actix::{Handler, Message};
+use diesel::result::Error::NotFound;
+use diesel::{dsl::exists, prelude::*};
+use failure::Error;
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::helpers;
+use super::products::replace_audited_product;
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{
+    ProductDataWithMeta, ProductRevision, ProductRevisionData, ProductWithMeta, ValidationErrors,
+};
+use crate::schema::product_revisions::dsl;
+use crate::schema::products::dsl as p_dsl;
+
+/// Lists the revisions of a product, newest first
+#[derive(Debug)]
+pub struct ListProductRevisions {
+    pub product_id: Uuid,
+}
+
+impl Message for ListProductRevisions {
+    type Result = Result<Option<Vec<ProductRevision>>, Error>;
+}
+
+impl Handler<ListProductRevisions> for DbActor {
+    type Result = Result<Option<Vec<ProductRevision>>, Error>;
+
+    fn handle(&mut self, msg: ListProductRevisions, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        if !product_exists(&conn, msg.product_id)? {
+            return Ok(None);
+        }
+        let revisions = dsl::product_revisions
+            .filter(dsl::product_id.eq(msg.product_id))
+            .order(dsl::revision.desc())
+            .load::<ProductRevision>(&conn)?;
+        Ok(Some(revisions))
+    }
+}
+
+/// Returns the fields which changed between two revisions of a product
+#[derive(Debug)]
+pub struct DiffProductRevisions {
+    pub product_id: Uuid,
+    pub from: i32,
+    pub to: i32,
+}
+
+impl Message for DiffProductRevisions {
+    type Result = Result<Value, Error>;
+}
+
+impl Handler<DiffProductRevisions> for DbActor {
+    type Result = Result<Value, Error>;
+
+    fn handle(&mut self, msg: DiffProductRevisions, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        if !product_exists(&conn, msg.product_id)? {
+            return Err(NotFound.into());
+        }
+        let from = get_revision(&conn, msg.product_id, msg.from)?;
+        let to = get_revision(&conn, msg.product_id, msg.to)?;
+        Ok(helpers::json_diff(&from.data, &to.data))
+    }
+}
+
+/// Updates the product to the state of a previous revision. The rollback is
+/// recorded as a new revision.
+#[derive(Debug)]
+pub struct RestoreProductRevision {
+    pub author: Principal,
+    pub product_id: Uuid,
+    pub revision: i32,
+    pub version: Option<i32>,
+}
+
+impl Message for RestoreProductRevision {
+    type Result = Result<ProductWithMeta, Error>;
+}
+
+impl Handler<RestoreProductRevision> for DbActor {
+    type Result = Result<ProductWithMeta, Error>;
+
+    fn handle(&mut self, msg: RestoreProductRevision, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            if !product_exists(&conn, msg.product_id)? {
+                return Err(NotFound.into());
+            }
+            let revision = get_revision(&conn, msg.product_id, msg.revision)?;
+            let data: ProductDataWithMeta = serde_json::from_value(revision.data)?;
+            data.product.validate().map_err(ValidationErrors::from)?;
+            replace_audited_product(&conn, &msg.author, msg.product_id, msg.version, data)
+        })
+    }
+}
+
+/// Stores a snapshot of the product as the revision of its current version.
+/// Should be called inside the transaction of the mutation.
+pub(super) fn record(
+    conn: &PgConnection,
+    author: &Principal,
+    product: &ProductWithMeta,
+) -> Result<(), Error> {
+    let data = ProductDataWithMeta::from(product);
+    let revision = ProductRevisionData {
+        product_id: product.product.id,
+        revision: product.product.version,
+        user_issuer: author.issuer.clone(),
+        user_subject: author.subject.clone(),
+        user_name: author.username.clone(),
+        data: serde_json::to_value(&data)?,
+    };
+    diesel::insert_into(dsl::product_revisions)
+        .values(revision)
+        .execute(conn)?;
+    Ok(())
+}
+
+fn get_revision(
+    conn: &PgConnection,
+    product_id: Uuid,
+    revision: i32,
+) -> QueryResult<ProductRevision> {
+    dsl::product_revisions
+        .find((product_id, revision))
+        .first(conn)
+}
+
+/// Checks if the product exists and isn't in the trash
+fn product_exists(conn: &PgConnection, id: Uuid) -> QueryResult<bool> {
+    let product = p_dsl::products.find(id).filter(p_dsl::deleted_at.is_null());
+    diesel::select(exists(product)).get_result(conn)
+}
@@ -0,0 +1,77 @@
+use actix::{Handler, Message};
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+use super::products::{get_product, patch_audited_product};
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{ProductPatch, ProductPatchWithMeta};
+use crate::schema::products::dsl;
+
+/// Applies the scheduled status changes which are due. Products become
+/// AVAILABLE at their publish_at and ARCHIVED at their unpublish_at, after
+/// which the applied timestamp is cleared. Returns the number of changed
+/// products.
+#[derive(Debug)]
+pub struct ApplySchedule {
+    pub now: NaiveDateTime,
+}
+
+impl Message for ApplySchedule {
+    type Result = Result<usize, Error>;
+}
+
+impl Handler<ApplySchedule> for DbActor {
+    type Result = Result<usize, Error>;
+
+    fn handle(&mut self, msg: ApplySchedule, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let author = Principal::system("scheduler");
+        let due = dsl::products
+            .filter(dsl::deleted_at.is_null())
+            .filter(
+                dsl::publish_at
+                    .le(msg.now)
+                    .or(dsl::unpublish_at.le(msg.now)),
+            )
+            .select(dsl::id)
+            .load::<Uuid>(&conn)?;
+
+        // A failing product shouldn't block the others
+        let mut changed = 0;
+        for id in due {
+            match conn.transaction(|| apply_product_schedule(&conn, &author, id, msg.now)) {
+                Ok(()) => changed += 1,
+                Err(e) => log::warn!("Failed to apply schedule of product {}: {}", id, e),
+            }
+        }
+        Ok(changed)
+    }
+}
+
+fn apply_product_schedule(
+    conn: &PgConnection,
+    author: &Principal,
+    id: Uuid,
+    now: NaiveDateTime,
+) -> Result<(), Error> {
+    let before = get_product(conn, id)?.ok_or(diesel::NotFound)?;
+    let product = &before.product;
+    let mut patch = ProductPatch::default();
+    if product.publish_at.is_some_and(|at| at <= now) {
+        patch.status = Some("AVAILABLE".to_string());
+        patch.publish_at = Some(None);
+    }
+    if product.unpublish_at.is_some_and(|at| at <= now) {
+        patch.status = Some("ARCHIVED".to_string());
+        patch.unpublish_at = Some(None);
+    }
+    let patch = ProductPatchWithMeta {
+        product: patch,
+        category_ids: None,
+    };
+    patch_audited_product(conn, author, &before, patch)?;
+    Ok(())
+}
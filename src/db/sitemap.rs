@@ -0,0 +1,70 @@
+use actix::{Handler, Message};
+use chrono::{NaiveDateTime, Utc};
+use diesel::prelude::*;
+use failure::Error;
+
+use super::products::is_published;
+use super::DbActor;
+use crate::models::{published_categories, Category, SitemapKind, SitemapRecord};
+use crate::schema::categories::dsl as c_dsl;
+use crate::schema::manufacturers::dsl as m_dsl;
+use crate::schema::products::dsl as p_dsl;
+
+/// Lists the products, categories and manufacturers which may be indexed by
+/// search engines. Archived or unpublished products, pages marked noindex and
+/// entities in the trash are excluded.
+#[derive(Debug)]
+pub struct ListSitemapRecords {}
+
+impl Message for ListSitemapRecords {
+    type Result = Result<Vec<SitemapRecord>, Error>;
+}
+
+impl Handler<ListSitemapRecords> for DbActor {
+    type Result = Result<Vec<SitemapRecord>, Error>;
+
+    fn handle(&mut self, _msg: ListSitemapRecords, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let now = Utc::now().naive_utc();
+        let products = p_dsl::products
+            .filter(p_dsl::status.ne("ARCHIVED"))
+            .filter(p_dsl::noindex.eq(false))
+            .filter(p_dsl::deleted_at.is_null())
+            .filter(is_published(now))
+            .select((p_dsl::slug, p_dsl::updated_at, p_dsl::canonical_url))
+            .order(p_dsl::slug)
+            .load::<(String, NaiveDateTime, Option<String>)>(&conn)?;
+        let categories = c_dsl::categories
+            .filter(c_dsl::deleted_at.is_null())
+            .order(c_dsl::slug)
+            .load::<Category>(&conn)?;
+        let categories = published_categories(categories, now)
+            .into_iter()
+            .filter(|c| !c.noindex)
+            .map(|c| (c.slug, c.updated_at, c.canonical_url))
+            .collect();
+        let manufacturers = m_dsl::manufacturers
+            .filter(m_dsl::deleted_at.is_null())
+            .select((m_dsl::slug, m_dsl::updated_at))
+            .order(m_dsl::slug)
+            .load::<(String, NaiveDateTime)>(&conn)?;
+
+        let records = |kind, rows: Vec<(String, NaiveDateTime, Option<String>)>| {
+            rows.into_iter()
+                .map(move |(slug, updated_at, canonical_url)| SitemapRecord {
+                    kind,
+                    slug,
+                    updated_at,
+                    canonical_url,
+                })
+        };
+        let manufacturers = manufacturers
+            .into_iter()
+            .map(|(slug, updated_at)| (slug, updated_at, None))
+            .collect();
+        Ok(records(SitemapKind::Product, products)
+            .chain(records(SitemapKind::Category, categories))
+            .chain(records(SitemapKind::Manufacturer, manufacturers))
+            .collect())
+    }
+}
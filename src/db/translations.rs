@@ -0,0 +1,451 @@
+use actix::{Handler, Message};
+use diesel::prelude::*;
+use diesel::result::Error::NotFound;
+use failure::Error;
+use uuid::Uuid;
+
+use super::audit::{self, Action, EntityType};
+use super::helpers;
+use super::products::retire_slug;
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{
+    Category, CategoryTranslation, CategoryTranslationData, MissingTranslation, Product,
+    ProductTranslation, ProductTranslationData, ValidationErrors,
+};
+use crate::schema::categories::dsl as c_dsl;
+use crate::schema::category_translations::dsl as ct_dsl;
+use crate::schema::product_translations::dsl as pt_dsl;
+use crate::schema::products::dsl as p_dsl;
+
+/// Lists the translations of a product. Returns `None` if the product doesn't
+/// exist.
+#[derive(Debug)]
+pub struct ListProductTranslations {
+    pub product_id: Uuid,
+}
+
+impl Message for ListProductTranslations {
+    type Result = Result<Option<Vec<ProductTranslation>>, Error>;
+}
+
+impl Handler<ListProductTranslations> for DbActor {
+    type Result = Result<Option<Vec<ProductTranslation>>, Error>;
+
+    fn handle(&mut self, msg: ListProductTranslations, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        if live_product(&conn, msg.product_id)?.is_none() {
+            return Ok(None);
+        }
+        let translations = pt_dsl::product_translations
+            .filter(pt_dsl::product_id.eq(msg.product_id))
+            .order(pt_dsl::locale)
+            .load::<ProductTranslation>(&conn)?;
+        Ok(Some(translations))
+    }
+}
+
+/// Adds or replaces the translation of a product to a locale
+#[derive(Debug)]
+pub struct SetProductTranslation {
+    pub author: Principal,
+    pub data: ProductTranslationData,
+}
+
+impl Message for SetProductTranslation {
+    type Result = Result<ProductTranslation, Error>;
+}
+
+impl Handler<SetProductTranslation> for DbActor {
+    type Result = Result<ProductTranslation, Error>;
+
+    fn handle(&mut self, msg: SetProductTranslation, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let (author, mut data) = (msg.author, msg.data);
+        conn.transaction(|| {
+            live_product(&conn, data.product_id)?.ok_or(NotFound)?;
+            data.slug = translated_slug(&data.product_id, &data.name, data.slug.take());
+            let before = pt_dsl::product_translations
+                .find((data.product_id, &data.locale))
+                .first::<ProductTranslation>(&conn)
+                .optional()?;
+            retire_slug(
+                &conn,
+                data.product_id,
+                before
+                    .as_ref()
+                    .and_then(|t| t.slug.as_deref())
+                    .unwrap_or_default(),
+                data.slug.as_deref().unwrap_or_default(),
+            )?;
+            let translation = diesel::insert_into(pt_dsl::product_translations)
+                .values(&data)
+                .on_conflict((pt_dsl::product_id, pt_dsl::locale))
+                .do_update()
+                .set(&data)
+                .get_result::<ProductTranslation>(&conn)?;
+            touch_product(&conn, translation.product_id)?;
+            audit::record(
+                &conn,
+                &author,
+                EntityType::Product,
+                translation.product_id,
+                Action::Update,
+                before.as_ref(),
+                Some(&translation),
+            )?;
+            Ok(translation)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteProductTranslation {
+    pub author: Principal,
+    pub product_id: Uuid,
+    pub locale: String,
+}
+
+impl Message for DeleteProductTranslation {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeleteProductTranslation> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeleteProductTranslation, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before =
+                diesel::delete(pt_dsl::product_translations.find((msg.product_id, &msg.locale)))
+                    .get_result::<ProductTranslation>(&conn)?;
+            retire_slug(
+                &conn,
+                before.product_id,
+                before.slug.as_deref().unwrap_or_default(),
+                "",
+            )?;
+            touch_product(&conn, before.product_id)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Product,
+                before.product_id,
+                Action::Update,
+                Some(&before),
+                None::<&ProductTranslation>,
+            )
+        })
+    }
+}
+
+/// Lists the translations of a category. Returns `None` if the category
+/// doesn't exist.
+#[derive(Debug)]
+pub struct ListCategoryTranslations {
+    pub category_id: Uuid,
+}
+
+impl Message for ListCategoryTranslations {
+    type Result = Result<Option<Vec<CategoryTranslation>>, Error>;
+}
+
+impl Handler<ListCategoryTranslations> for DbActor {
+    type Result = Result<Option<Vec<CategoryTranslation>>, Error>;
+
+    fn handle(&mut self, msg: ListCategoryTranslations, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        if live_category(&conn, msg.category_id)?.is_none() {
+            return Ok(None);
+        }
+        let translations = ct_dsl::category_translations
+            .filter(ct_dsl::category_id.eq(msg.category_id))
+            .order(ct_dsl::locale)
+            .load::<CategoryTranslation>(&conn)?;
+        Ok(Some(translations))
+    }
+}
+
+/// Adds or replaces the translation of a category to a locale
+#[derive(Debug)]
+pub struct SetCategoryTranslation {
+    pub author: Principal,
+    pub data: CategoryTranslationData,
+}
+
+impl Message for SetCategoryTranslation {
+    type Result = Result<CategoryTranslation, Error>;
+}
+
+impl Handler<SetCategoryTranslation> for DbActor {
+    type Result = Result<CategoryTranslation, Error>;
+
+    fn handle(&mut self, msg: SetCategoryTranslation, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let (author, mut data) = (msg.author, msg.data);
+        conn.transaction(|| {
+            live_category(&conn, data.category_id)?.ok_or(NotFound)?;
+            data.slug = translated_slug(&data.category_id, &data.name, data.slug.take());
+            if let Some(slug) = &data.slug {
+                check_category_slug(&conn, &data.category_id, slug)?;
+            }
+
+            let before = ct_dsl::category_translations
+                .find((data.category_id, &data.locale))
+                .first::<CategoryTranslation>(&conn)
+                .optional()?;
+            let translation = diesel::insert_into(ct_dsl::category_translations)
+                .values(&data)
+                .on_conflict((ct_dsl::category_id, ct_dsl::locale))
+                .do_update()
+                .set(&data)
+                .get_result::<CategoryTranslation>(&conn)?;
+            touch_category(&conn, translation.category_id)?;
+            audit::record(
+                &conn,
+                &author,
+                EntityType::Category,
+                translation.category_id,
+                Action::Update,
+                before.as_ref(),
+                Some(&translation),
+            )?;
+            Ok(translation)
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct DeleteCategoryTranslation {
+    pub author: Principal,
+    pub category_id: Uuid,
+    pub locale: String,
+}
+
+impl Message for DeleteCategoryTranslation {
+    type Result = Result<(), Error>;
+}
+
+impl Handler<DeleteCategoryTranslation> for DbActor {
+    type Result = Result<(), Error>;
+
+    fn handle(&mut self, msg: DeleteCategoryTranslation, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            let before =
+                diesel::delete(ct_dsl::category_translations.find((msg.category_id, &msg.locale)))
+                    .get_result::<CategoryTranslation>(&conn)?;
+            touch_category(&conn, before.category_id)?;
+            audit::record(
+                &conn,
+                &msg.author,
+                EntityType::Category,
+                before.category_id,
+                Action::Update,
+                Some(&before),
+                None::<&CategoryTranslation>,
+            )
+        })
+    }
+}
+
+/// Fetches the translations of products to the locales, used to show them
+/// in the language of a visitor
+#[derive(Debug)]
+pub struct GetProductTranslations {
+    pub product_ids: Vec<Uuid>,
+    pub locales: Vec<String>,
+}
+
+impl Message for GetProductTranslations {
+    type Result = Result<Vec<ProductTranslation>, Error>;
+}
+
+impl Handler<GetProductTranslations> for DbActor {
+    type Result = Result<Vec<ProductTranslation>, Error>;
+
+    fn handle(&mut self, msg: GetProductTranslations, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let translations = pt_dsl::product_translations
+            .filter(pt_dsl::product_id.eq_any(&msg.product_ids))
+            .filter(pt_dsl::locale.eq_any(&msg.locales))
+            .load::<ProductTranslation>(&conn)?;
+        Ok(translations)
+    }
+}
+
+/// Fetches the translations of categories to the locales
+#[derive(Debug)]
+pub struct GetCategoryTranslations {
+    pub category_ids: Vec<Uuid>,
+    pub locales: Vec<String>,
+}
+
+impl Message for GetCategoryTranslations {
+    type Result = Result<Vec<CategoryTranslation>, Error>;
+}
+
+impl Handler<GetCategoryTranslations> for DbActor {
+    type Result = Result<Vec<CategoryTranslation>, Error>;
+
+    fn handle(&mut self, msg: GetCategoryTranslations, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let translations = ct_dsl::category_translations
+            .filter(ct_dsl::category_id.eq_any(&msg.category_ids))
+            .filter(ct_dsl::locale.eq_any(&msg.locales))
+            .load::<CategoryTranslation>(&conn)?;
+        Ok(translations)
+    }
+}
+
+/// Lists the products and categories outside the trash of which
+/// fields with content aren't translated to the locales
+#[derive(Debug)]
+pub struct ListMissingTranslations {
+    pub locales: Vec<String>,
+}
+
+impl Message for ListMissingTranslations {
+    type Result = Result<Vec<MissingTranslation>, Error>;
+}
+
+impl Handler<ListMissingTranslations> for DbActor {
+    type Result = Result<Vec<MissingTranslation>, Error>;
+
+    fn handle(&mut self, msg: ListMissingTranslations, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let mut missing = Vec::new();
+
+        let products = p_dsl::products
+            .filter(p_dsl::deleted_at.is_null())
+            .order(p_dsl::name)
+            .load::<Product>(&conn)?;
+        let translations = pt_dsl::product_translations
+            .filter(pt_dsl::locale.eq_any(&msg.locales))
+            .load::<ProductTranslation>(&conn)?;
+        for locale in &msg.locales {
+            for product in &products {
+                let translation = translations
+                    .iter()
+                    .find(|t| t.product_id == product.id && &t.locale == locale);
+                let fields = product.untranslated_fields(translation);
+                if !fields.is_empty() {
+                    missing.push(MissingTranslation {
+                        entity_type: EntityType::Product.as_str(),
+                        entity_id: product.id,
+                        name: product.name.clone(),
+                        locale: locale.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+
+        let categories = c_dsl::categories
+            .filter(c_dsl::deleted_at.is_null())
+            .order(c_dsl::name)
+            .load::<Category>(&conn)?;
+        let translations = ct_dsl::category_translations
+            .filter(ct_dsl::locale.eq_any(&msg.locales))
+            .load::<CategoryTranslation>(&conn)?;
+        for locale in &msg.locales {
+            for category in &categories {
+                let translation = translations
+                    .iter()
+                    .find(|t| t.category_id == category.id && &t.locale == locale);
+                let fields = category.untranslated_fields(translation);
+                if !fields.is_empty() {
+                    missing.push(MissingTranslation {
+                        entity_type: EntityType::Category.as_str(),
+                        entity_id: category.id,
+                        name: category.name.clone(),
+                        locale: locale.clone(),
+                        fields,
+                    });
+                }
+            }
+        }
+        Ok(missing)
+    }
+}
+
+/// Finds the product which has the slug in one of its translations
+pub fn translated_product_id(conn: &PgConnection, slug: &str) -> QueryResult<Option<Uuid>> {
+    pt_dsl::product_translations
+        .filter(pt_dsl::slug.eq(slug))
+        .select(pt_dsl::product_id)
+        .first::<Uuid>(conn)
+        .optional()
+}
+
+/// Finds the category which has the slug in one of its translations
+pub fn translated_category_id(conn: &PgConnection, slug: &str) -> QueryResult<Option<Uuid>> {
+    ct_dsl::category_translations
+        .filter(ct_dsl::slug.eq(slug))
+        .select(ct_dsl::category_id)
+        .first::<Uuid>(conn)
+        .optional()
+}
+
+fn live_product(conn: &PgConnection, id: Uuid) -> QueryResult<Option<Product>> {
+    p_dsl::products
+        .find(id)
+        .filter(p_dsl::deleted_at.is_null())
+        .first::<Product>(conn)
+        .optional()
+}
+
+fn live_category(conn: &PgConnection, id: Uuid) -> QueryResult<Option<Category>> {
+    c_dsl::categories
+        .find(id)
+        .filter(c_dsl::deleted_at.is_null())
+        .first::<Category>(conn)
+        .optional()
+}
+
+/// Bumps the version of a product of which a translation changed, which moves
+/// its modification time as well, so cached representations expire
+fn touch_product(conn: &PgConnection, id: Uuid) -> QueryResult<()> {
+    diesel::update(p_dsl::products.find(id))
+        .set(p_dsl::version.eq(p_dsl::version + 1))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Bumps the version of a category of which a translation changed
+fn touch_category(conn: &PgConnection, id: Uuid) -> QueryResult<()> {
+    diesel::update(c_dsl::categories.find(id))
+        .set(c_dsl::version.eq(c_dsl::version + 1))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// Keeps a custom slug, or generates one from the translated name. Without
+/// either the slug falls back to the next locale.
+fn translated_slug(id: &Uuid, name: &Option<String>, slug: Option<String>) -> Option<String> {
+    match (slug.filter(|s| !s.is_empty()), name) {
+        (Some(slug), _) => Some(slug),
+        (None, Some(name)) => Some(helpers::generate_slug(name, id)),
+        (None, None) => None,
+    }
+}
+
+/// Rejects slugs which lead to another category in any locale
+pub(super) fn check_category_slug(conn: &PgConnection, id: &Uuid, slug: &str) -> Result<(), Error> {
+    let in_categories = c_dsl::categories
+        .filter(c_dsl::slug.eq(slug))
+        .filter(c_dsl::id.ne(id))
+        .count()
+        .get_result::<i64>(conn)?;
+    let in_translations = ct_dsl::category_translations
+        .filter(ct_dsl::slug.eq(slug))
+        .filter(ct_dsl::category_id.ne(id))
+        .count()
+        .get_result::<i64>(conn)?;
+    if in_categories + in_translations > 0 {
+        let mut errors = ValidationErrors::new();
+        errors.add("slug", "Is already used by another category");
+        return Err(errors.into());
+    }
+    Ok(())
+}
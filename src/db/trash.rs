@@ -0,0 +1,141 @@
+use std::cmp::Reverse;
+
+use actix::{Handler, Message};
+use chrono::{Duration, NaiveDateTime};
+use diesel::prelude::*;
+use failure::Error;
+use uuid::Uuid;
+
+use super::products;
+use super::promotions;
+use super::DbActor;
+use crate::auth::Principal;
+use crate::models::{TrashEntityType, TrashItem};
+use crate::schema::categories::dsl as c_dsl;
+use crate::schema::category_products::dsl as cp_dsl;
+use crate::schema::manufacturers::dsl as m_dsl;
+use crate::schema::products::dsl as p_dsl;
+
+/// Lists the entities in the trash, most recently deleted first
+#[derive(Debug)]
+pub struct ListTrash {
+    pub entity_type: Option<TrashEntityType>,
+
+    /// Time entities are kept in the trash, used to calculate when they're purged
+    pub retention: Duration,
+}
+
+impl Message for ListTrash {
+    type Result = Result<Vec<TrashItem>, Error>;
+}
+
+impl Handler<ListTrash> for DbActor {
+    type Result = Result<Vec<TrashItem>, Error>;
+
+    fn handle(&mut self, msg: ListTrash, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        let included = |entity_type| msg.entity_type.is_none_or(|t| t == entity_type);
+        let mut rows = Vec::new();
+        if included(TrashEntityType::Product) {
+            let products = p_dsl::products
+                .filter(p_dsl::deleted_at.is_not_null())
+                .select((p_dsl::id, p_dsl::name, p_dsl::deleted_at))
+                .load::<(Uuid, String, Option<NaiveDateTime>)>(&conn)?;
+            rows.extend(products.into_iter().map(|r| (TrashEntityType::Product, r)));
+        }
+        if included(TrashEntityType::Category) {
+            let categories = c_dsl::categories
+                .filter(c_dsl::deleted_at.is_not_null())
+                .select((c_dsl::id, c_dsl::name, c_dsl::deleted_at))
+                .load::<(Uuid, String, Option<NaiveDateTime>)>(&conn)?;
+            rows.extend(
+                categories
+                    .into_iter()
+                    .map(|r| (TrashEntityType::Category, r)),
+            );
+        }
+        if included(TrashEntityType::Manufacturer) {
+            let manufacturers = m_dsl::manufacturers
+                .filter(m_dsl::deleted_at.is_not_null())
+                .select((m_dsl::id, m_dsl::name, m_dsl::deleted_at))
+                .load::<(Uuid, String, Option<NaiveDateTime>)>(&conn)?;
+            rows.extend(
+                manufacturers
+                    .into_iter()
+                    .map(|r| (TrashEntityType::Manufacturer, r)),
+            );
+        }
+
+        let mut items: Vec<_> = rows
+            .into_iter()
+            .filter_map(|(entity_type, (id, name, deleted_at))| {
+                let deleted_at = deleted_at?;
+                Some(TrashItem {
+                    entity_type,
+                    id,
+                    name,
+                    deleted_at,
+                    purge_at: deleted_at + msg.retention,
+                })
+            })
+            .collect();
+        items.sort_by_key(|item| Reverse(item.deleted_at));
+        Ok(items)
+    }
+}
+
+/// Permanently deletes the entities which were moved to the trash before the
+/// provided moment. Returns the IDs of the deleted entities, so their images
+/// can be deleted as well.
+#[derive(Debug)]
+pub struct PurgeTrash {
+    pub before: NaiveDateTime,
+}
+
+impl Message for PurgeTrash {
+    type Result = Result<Vec<Uuid>, Error>;
+}
+
+impl Handler<PurgeTrash> for DbActor {
+    type Result = Result<Vec<Uuid>, Error>;
+
+    fn handle(&mut self, msg: PurgeTrash, _: &mut Self::Context) -> Self::Result {
+        let conn = self.pool.get()?;
+        conn.transaction(|| {
+            // CategoryProducts and slug history of products are deleted by cascade
+            let mut purged =
+                diesel::delete(p_dsl::products.filter(p_dsl::deleted_at.lt(msg.before)))
+                    .returning(p_dsl::id)
+                    .get_results::<Uuid>(&conn)?;
+            let categories = c_dsl::categories
+                .filter(c_dsl::deleted_at.lt(msg.before))
+                .select(c_dsl::id)
+                .load::<Uuid>(&conn)?;
+            let manufacturers = m_dsl::manufacturers
+                .filter(m_dsl::deleted_at.lt(msg.before))
+                .select(m_dsl::id)
+                .load::<Uuid>(&conn)?;
+            let author = Principal::system("purge");
+            promotions::purge_targets(&conn, &author, &purged, &categories, &manufacturers)?;
+
+            // Remaining references to expired categories and manufacturers
+            // are held by entities in the trash
+            diesel::delete(
+                cp_dsl::category_products.filter(cp_dsl::category_id.eq_any(&categories)),
+            )
+            .execute(&conn)?;
+            diesel::update(c_dsl::categories.filter(c_dsl::parent_id.eq_any(&categories)))
+                .set(c_dsl::parent_id.eq(None::<Uuid>))
+                .execute(&conn)?;
+            diesel::delete(c_dsl::categories.filter(c_dsl::id.eq_any(&categories)))
+                .execute(&conn)?;
+            purged.extend(categories);
+
+            products::detach_manufacturers(&conn, &author, &manufacturers)?;
+            diesel::delete(m_dsl::manufacturers.filter(m_dsl::id.eq_any(&manufacturers)))
+                .execute(&conn)?;
+            purged.extend(manufacturers);
+            Ok(purged)
+        })
+    }
+}
@@ -0,0 +1,192 @@
+mod sitemap;
+
+use std::fmt::Write;
+
+use failure::Error;
+use uuid::Uuid;
+
+use crate::actors::ImageName;
+use crate::models::{Config, Product, ProductPrice, TaxAmounts};
+
+pub use self::sitemap::*;
+
+/// Product as advertised in a feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedItem {
+    pub id: Uuid,
+    pub title: String,
+    pub description: String,
+    pub link: String,
+    pub image_link: Option<String>,
+
+    /// Price including tax with currency, e.g. "12.95 EUR"
+    pub price: String,
+
+    /// Reduced price with currency if the product is on sale or in a promotion
+    pub sale_price: Option<String>,
+    pub in_stock: bool,
+    pub brand: Option<String>,
+}
+
+impl FeedItem {
+    pub fn new(
+        config: &Config,
+        product: Product,
+        price: ProductPrice,
+        manufacturer: Option<String>,
+        image_hash: Option<String>,
+    ) -> Self {
+        let description = [&product.description_short, &product.description_long]
+            .iter()
+            .find(|d| !d.trim().is_empty())
+            .map_or_else(|| product.name.clone(), |d| d.to_string());
+        let image_link = image_hash.map(|hash| {
+            let name = ImageName {
+                id: product.id,
+                hash: Some(hash),
+                thumbnail: None,
+            };
+            format!("{}/images/{}", config.public_url, name.file_name())
+        });
+        // Consumers are shown prices including tax
+        let tax_rate = product.tax_rate;
+        let gross = |cents| TaxAmounts::new(cents, tax_rate, config.prices_include_tax).gross;
+        Self {
+            id: product.id,
+            link: config.product_url(&product.slug),
+            title: product.name,
+            description,
+            image_link,
+            price: format_price(config, gross(price.regular_price)),
+            sale_price: Some(price.effective_price)
+                .filter(|&p| p < price.regular_price)
+                .map(|p| format_price(config, gross(p))),
+            in_stock: product.stock_count > 0,
+            brand: manufacturer,
+        }
+    }
+}
+
+fn format_price(config: &Config, cents: i32) -> String {
+    format!("{}.{:02} {}", cents / 100, cents % 100, config.currency)
+}
+
+/// Builds an RSS 2.0 feed for Google Merchant Center
+pub fn google_rss(shop_name: &str, shop_url: &str, items: &[FeedItem]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\" xmlns:g=\"http://base.google.com/ns/1.0\">\n");
+    xml.push_str("<channel>\n");
+    tag(&mut xml, "title", shop_name);
+    tag(&mut xml, "link", shop_url);
+    tag(&mut xml, "description", shop_name);
+    for item in items {
+        xml.push_str("<item>\n");
+        tag(&mut xml, "g:id", &item.id.to_string());
+        tag(&mut xml, "g:title", &item.title);
+        tag(&mut xml, "g:description", &item.description);
+        tag(&mut xml, "g:link", &item.link);
+        if let Some(image_link) = &item.image_link {
+            tag(&mut xml, "g:image_link", image_link);
+        }
+        tag(&mut xml, "g:condition", "new");
+        let availability = if item.in_stock {
+            "in_stock"
+        } else {
+            "out_of_stock"
+        };
+        tag(&mut xml, "g:availability", availability);
+        tag(&mut xml, "g:price", &item.price);
+        if let Some(sale_price) = &item.sale_price {
+            tag(&mut xml, "g:sale_price", sale_price);
+        }
+        if let Some(brand) = &item.brand {
+            tag(&mut xml, "g:brand", brand);
+        }
+        xml.push_str("</item>\n");
+    }
+    xml.push_str("</channel>\n</rss>\n");
+    xml
+}
+
+/// Builds a CSV feed for Facebook Catalog
+pub fn facebook_csv(items: &[FeedItem]) -> Result<Vec<u8>, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record([
+        "id",
+        "title",
+        "description",
+        "availability",
+        "condition",
+        "price",
+        "sale_price",
+        "link",
+        "image_link",
+        "brand",
+    ])?;
+    for item in items {
+        let availability = if item.in_stock {
+            "in stock"
+        } else {
+            "out of stock"
+        };
+        writer.write_record([
+            item.id.to_string().as_str(),
+            &item.title,
+            &item.description,
+            availability,
+            "new",
+            &item.price,
+            item.sale_price.as_deref().unwrap_or_default(),
+            &item.link,
+            item.image_link.as_deref().unwrap_or_default(),
+            item.brand.as_deref().unwrap_or_default(),
+        ])?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+fn tag(xml: &mut String, name: &str, value: &str) {
+    let _ = writeln!(xml, "<{0}>{1}</{0}>", name, escape_xml(value));
+}
+
+pub fn escape_xml(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_google_rss() {
+        let item = FeedItem {
+            id: Uuid::nil(),
+            title: "Zeep & <Co>".to_string(),
+            description: "Zeep".to_string(),
+            link: "https://example.com/products/zeep".to_string(),
+            image_link: None,
+            price: "3.50 EUR".to_string(),
+            sale_price: Some("2.95 EUR".to_string()),
+            in_stock: false,
+            brand: Some("Savon".to_string()),
+        };
+        let xml = google_rss("Shop", "https://example.com", &[item]);
+        assert!(xml.contains("<g:title>Zeep &amp; &lt;Co&gt;</g:title>"));
+        assert!(xml.contains("<g:availability>out_of_stock</g:availability>"));
+        assert!(xml.contains("<g:price>3.50 EUR</g:price>"));
+        assert!(xml.contains("<g:sale_price>2.95 EUR</g:sale_price>"));
+        assert!(!xml.contains("g:image_link"));
+    }
+}
@@ -0,0 +1,85 @@
+use std::fmt::Write;
+
+use chrono::NaiveDateTime;
+
+use super::escape_xml;
+use crate::models::{Config, SitemapKind, SitemapRecord};
+
+/// Max number of URLs in a single sitemap, as defined by sitemaps.org
+pub const SITEMAP_MAX_URLS: usize = 50_000;
+
+/// Location of a page or of a sitemap in a sitemap index
+#[derive(Debug, Clone, PartialEq)]
+pub struct SitemapUrl {
+    pub loc: String,
+    pub lastmod: NaiveDateTime,
+}
+
+impl SitemapUrl {
+    /// Builds the URL of the page in the storefront. Returns `None` if the
+    /// canonical URL of the page refers to another page.
+    pub fn from_record(config: &Config, record: SitemapRecord) -> Option<Self> {
+        let loc = match record.kind {
+            SitemapKind::Product => config.product_url(&record.slug),
+            SitemapKind::Category => config.category_url(&record.slug),
+            SitemapKind::Manufacturer => config.manufacturer_url(&record.slug),
+        };
+        match record.canonical_url {
+            Some(canonical_url) if canonical_url != loc => None,
+            _ => Some(Self {
+                loc,
+                lastmod: record.updated_at,
+            }),
+        }
+    }
+}
+
+/// Builds a sitemap listing the given pages
+pub fn sitemap_urlset(urls: &[SitemapUrl]) -> String {
+    sitemap_xml("urlset", "url", urls)
+}
+
+/// Builds a sitemap index listing the given sitemaps
+pub fn sitemap_index(sitemaps: &[SitemapUrl]) -> String {
+    sitemap_xml("sitemapindex", "sitemap", sitemaps)
+}
+
+fn sitemap_xml(root: &str, element: &str, urls: &[SitemapUrl]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<{} xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">",
+        root
+    );
+    for url in urls {
+        let _ = writeln!(
+            xml,
+            "<{0}><loc>{1}</loc><lastmod>{2}</lastmod></{0}>",
+            element,
+            escape_xml(&url.loc),
+            url.lastmod.format("%Y-%m-%dT%H:%M:%SZ")
+        );
+    }
+    let _ = writeln!(xml, "</{}>", root);
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sitemap_urlset() {
+        let url = SitemapUrl {
+            loc: "https://example.com/products/zeep?a=1&b=2".to_string(),
+            lastmod: NaiveDateTime::from_timestamp(1_600_000_000, 0),
+        };
+        let xml = sitemap_urlset(&[url]);
+        assert!(xml.contains("<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">"));
+        assert!(xml.contains(
+            "<url><loc>https://example.com/products/zeep?a=1&amp;b=2</loc>\
+             <lastmod>2020-09-13T12:26:40Z</lastmod></url>"
+        ));
+    }
+}
@@ -0,0 +1,145 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::SystemTime;
+
+use actix_web::dev::{Body, ResponseBody, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap, HeaderValue, HttpDate};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{Error, HttpResponse};
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use super::{etag, etag_matches};
+
+/// Middleware which adds `ETag` and `Cache-Control` headers to successful
+/// reads and answers conditional requests with status 304. Handlers can
+/// provide `Last-Modified` or their own `ETag`. If no `ETag` is provided,
+/// a strong tag is generated from the body.
+#[derive(Clone)]
+pub struct HttpCache {
+    cache_control: HeaderValue,
+}
+
+impl HttpCache {
+    pub fn new(cache_control: &str) -> Self {
+        Self {
+            cache_control: HeaderValue::from_str(cache_control)
+                .expect("Cache-Control should be a valid header value"),
+        }
+    }
+}
+
+impl<S> Transform<S> for HttpCache
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = HttpCacheMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(HttpCacheMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            config: self.clone(),
+        })
+    }
+}
+
+pub struct HttpCacheMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    config: HttpCache,
+}
+
+impl<S> Service for HttpCacheMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<Body>, Error = Error> + 'static,
+    S::Future: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<Body>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let cache_control = self.config.cache_control.clone();
+        let is_read = matches!(*req.method(), Method::GET | Method::HEAD);
+        let conditions = Conditions::from_headers(req.headers());
+
+        Box::pin(async move {
+            let res = service.borrow_mut().call(req);
+            let mut res = res.await?;
+            if !is_read || res.status() != StatusCode::OK {
+                return Ok(res);
+            }
+
+            // Add validators and caching policy
+            let generated_etag = match res.response().body() {
+                ResponseBody::Body(Body::Bytes(bytes)) => Some(etag(bytes)),
+                _ => None,
+            };
+            let headers = res.headers_mut();
+            if !headers.contains_key(header::ETAG) {
+                if let Some(value) = generated_etag.and_then(|t| HeaderValue::from_str(&t).ok()) {
+                    headers.insert(header::ETAG, value);
+                }
+            }
+            if !headers.contains_key(header::CACHE_CONTROL) {
+                headers.insert(header::CACHE_CONTROL, cache_control);
+            }
+
+            // Evaluate conditional request
+            if !conditions.is_not_modified(res.headers()) {
+                return Ok(res);
+            }
+            let mut not_modified = HttpResponse::NotModified();
+            for name in &[header::ETAG, header::LAST_MODIFIED, header::CACHE_CONTROL] {
+                if let Some(value) = res.headers().get(name) {
+                    not_modified.header(name.clone(), value.clone());
+                }
+            }
+            Ok(res.into_response(not_modified.finish()))
+        })
+    }
+}
+
+/// Preconditions of a conditional GET request
+struct Conditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<SystemTime>,
+}
+
+impl Conditions {
+    fn from_headers(headers: &HeaderMap) -> Self {
+        let value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        Self {
+            if_none_match: value(header::IF_NONE_MATCH).map(str::to_string),
+            if_modified_since: value(header::IF_MODIFIED_SINCE)
+                .and_then(|v| v.parse::<HttpDate>().ok())
+                .map(SystemTime::from),
+        }
+    }
+
+    /// If-Modified-Since is ignored when If-None-Match is provided (RFC 7232)
+    fn is_not_modified(&self, headers: &HeaderMap) -> bool {
+        let value = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        if let Some(if_none_match) = &self.if_none_match {
+            return value(header::ETAG).is_some_and(|t| etag_matches(if_none_match, t));
+        }
+        let last_modified = value(header::LAST_MODIFIED)
+            .and_then(|v| v.parse::<HttpDate>().ok())
+            .map(SystemTime::from);
+        match (self.if_modified_since, last_modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+}
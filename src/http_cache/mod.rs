@@ -0,0 +1,96 @@
+mod middleware;
+
+pub use self::middleware::*;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::dev::HttpResponseBuilder;
+use actix_web::http::header::{self, HttpDate};
+use actix_web::{HttpRequest, HttpResponse};
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+/// Generates a strong entity tag based on the content of the body
+pub fn etag(body: &[u8]) -> String {
+    let hash = format!("{:x}", Sha256::digest(body));
+    format!("\"{}\"", &hash[..32])
+}
+
+/// Generates a strong entity tag "<version>-<hash>" for a versioned entity.
+/// The version is used to evaluate `If-Match` on updates.
+pub fn versioned_etag(version: i32, body: &[u8]) -> String {
+    let hash = format!("{:x}", Sha256::digest(body));
+    format!("\"{}-{}\"", version, &hash[..16])
+}
+
+/// Extracts the version from an entity tag generated by `versioned_etag`
+pub fn etag_version(etag: &str) -> Option<i32> {
+    let etag = etag.trim().strip_prefix('"')?.strip_suffix('"')?;
+    etag.split('-').next()?.parse().ok()
+}
+
+/// Returns the version required by the `If-Match` header of the request, if any.
+/// Unknown or weak entity tags result in version 0, which never matches.
+pub fn if_match_version(req: &HttpRequest) -> Option<i32> {
+    let value = req.headers().get(header::IF_MATCH)?;
+    match value.to_str().map(str::trim) {
+        Ok("*") => None,
+        Ok(value) => Some(etag_version(value).unwrap_or(0)),
+        Err(_) => Some(0),
+    }
+}
+
+/// Serializes the entity as JSON response with a versioned entity tag
+pub fn versioned_json<T: Serialize>(
+    res: &mut HttpResponseBuilder,
+    version: i32,
+    value: &T,
+) -> HttpResponse {
+    match serde_json::to_vec(value) {
+        Ok(body) => res
+            .header(header::ETAG, versioned_etag(version, &body))
+            .content_type("application/json")
+            .body(body),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
+
+/// Converts a timestamp from the database (UTC) to a value for `Last-Modified`
+pub fn http_date(timestamp: NaiveDateTime) -> HttpDate {
+    let secs = timestamp.timestamp().max(0) as u64;
+    HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Checks if an `If-None-Match` header matches the provided entity tag. As
+/// defined in RFC 7232, the weak comparison function is used.
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match
+        .split(',')
+        .map(str::trim)
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_etag_matches() {
+        let tag = etag(b"[]");
+        assert_eq!(tag.len(), 34);
+        assert!(etag_matches(&tag, &tag));
+        assert!(etag_matches(&format!("\"other\", W/{}", tag), &tag));
+        assert!(etag_matches("*", &tag));
+        assert!(!etag_matches("\"other\"", &tag));
+    }
+
+    #[test]
+    fn test_etag_version() {
+        let tag = versioned_etag(12, b"{}");
+        assert_eq!(etag_version(&tag), Some(12));
+        assert_eq!(etag_version("W/\"12-abc\""), None);
+        assert_eq!(etag_version("\"abc\""), None);
+    }
+}
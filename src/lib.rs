@@ -5,8 +5,9 @@ extern crate diesel;
 extern crate diesel_migrations;
 
 use std::env;
+use std::sync::Arc;
 
-use actix::{Addr, SyncArbiter};
+use actix::{Actor, Addr, SyncArbiter};
 use actix_cors::Cors;
 use actix_files as fs;
 use actix_web::{
@@ -15,15 +16,27 @@ use actix_web::{
 use diesel::prelude::*;
 use diesel::r2d2::{self, ConnectionManager};
 
-use crate::actors::ImageActor;
-use crate::api::{auth, categories, images, manufacturers, products};
+use crate::actors::{ImageActor, PurgeActor, SchedulerActor};
+use crate::api::{
+    api_keys, audit, carts, categories, coupons, exchange_rates, export, feeds, images, import,
+    manufacturers, orders, products, promotions, sitemap, translations, trash,
+};
+use crate::auth::{AuthProvider, Authentication, OidcConfig, OidcProvider};
 use crate::db::DbActor;
+use crate::http_cache::HttpCache;
+use crate::rate_limit::{MemoryStore, RateLimitStore, RateLimiter, RateLimits};
 
 mod actors;
 mod api;
+mod auth;
 mod db;
+mod feed;
+mod http_cache;
 pub mod models;
+mod rate_limit;
+#[allow(non_local_definitions)]
 mod schema;
+mod spreadsheet;
 
 pub use models::Config;
 
@@ -33,6 +46,7 @@ diesel_migrations::embed_migrations!();
 struct Context {
     pub db: Addr<DbActor>,
     pub image: Addr<ImageActor>,
+    pub config: Config,
 }
 
 pub async fn run(config: Config) -> std::io::Result<()> {
@@ -57,13 +71,55 @@ pub async fn run(config: Config) -> std::io::Result<()> {
 
     // Build state
     let images_path = config.images_path.clone();
+    let prices_include_tax = config.prices_include_tax;
     let ctx = Context {
-        db: SyncArbiter::start(3, move || DbActor::new(pool.clone())),
+        db: SyncArbiter::start(3, move || DbActor::new(pool.clone(), prices_include_tax)),
         image: SyncArbiter::start(3, move || ImageActor::new(images_path.clone())),
+        config: config.clone(),
+    };
+
+    // Start purging expired trash and abandoned carts
+    PurgeActor::new(
+        ctx.db.clone(),
+        ctx.image.clone(),
+        config.trash_retention,
+        config.cart_retention,
+        config.purge_interval,
+    )
+    .start();
+
+    // Start applying scheduled publishing
+    SchedulerActor::new(ctx.db.clone(), config.scheduler_interval).start();
+
+    // Create auth middlewares
+    let providers = config
+        .oidc_issuer_urls
+        .iter()
+        .map(|issuer_url| {
+            let provider = OidcProvider::new(OidcConfig {
+                roles_claim: config.oidc_roles_claim.clone(),
+                ..OidcConfig::keycloak(issuer_url, &config.oidc_audience, config.oidc_jwks_ttl)
+            });
+            Arc::new(provider) as Arc<dyn AuthProvider>
+        })
+        .collect();
+    let admin_auth =
+        Authentication::new(providers, &config.oidc_admin_role).with_api_keys(ctx.db.clone());
+
+    // Create rate limiter
+    let store = Arc::new(MemoryStore::new()) as Arc<dyn RateLimitStore>;
+    let limits = RateLimits {
+        listing: config.rate_limit_listing,
+        cart: config.rate_limit_cart,
     };
+    let rate_limiter =
+        RateLimiter::new(store, limits).with_trusted_proxies(config.trusted_proxies.clone());
 
-    // Create Keycloak middlewares
-    let keycloak_admin = auth::get_keycloak_admin(config.keycloak_public_key);
+    // Create caching middlewares
+    let public_cache = HttpCache::new(&config.cache_control);
+    let admin_cache = HttpCache::new("private, no-cache");
+    let cache_control = config.cache_control.clone();
+    let images_cache_control = config.images_cache_control.clone();
 
     // Start HTTP server
     log::info!("Starting server at: {}:{}", config.host, config.port);
@@ -77,23 +133,46 @@ pub async fn run(config: Config) -> std::io::Result<()> {
             .data(ctx.clone())
             .service(
                 web::scope("/public")
-                    .service(categories::public_scope("/categories"))
-                    .service(manufacturers::public_scope("/manufacturers"))
-                    .service(products::public_scope("/products")),
+                    .wrap(rate_limiter.clone())
+                    .service(categories::public_scope("/categories").wrap(public_cache.clone()))
+                    .service(
+                        manufacturers::public_scope("/manufacturers").wrap(public_cache.clone()),
+                    )
+                    .service(products::public_scope("/products").wrap(public_cache.clone()))
+                    .service(feeds::public_scope("/feeds").wrap(public_cache.clone()))
+                    .service(carts::public_scope("/carts"))
+                    // Scope without prefix, so it should be registered last
+                    .service(sitemap::public_scope("").wrap(public_cache.clone())),
             )
             .service(
                 web::scope("/admin")
-                    .wrap(keycloak_admin.clone())
-                    .service(categories::admin_scope("/categories"))
+                    .wrap(admin_auth.clone())
+                    .service(api_keys::admin_scope("/api-keys"))
+                    .service(audit::admin_scope("/audit"))
+                    .service(categories::admin_scope("/categories").wrap(admin_cache.clone()))
+                    .service(coupons::admin_scope("/coupons").wrap(admin_cache.clone()))
+                    .service(
+                        exchange_rates::admin_scope("/exchange-rates").wrap(admin_cache.clone()),
+                    )
+                    .service(export::admin_scope("/export"))
                     .service(images::admin_scope("/images"))
-                    .service(manufacturers::admin_scope("/manufacturers"))
-                    .service(products::admin_scope("/products")),
+                    .service(import::admin_scope("/import"))
+                    .service(manufacturers::admin_scope("/manufacturers").wrap(admin_cache.clone()))
+                    .service(orders::admin_scope("/orders"))
+                    .service(products::admin_scope("/products").wrap(admin_cache.clone()))
+                    .service(promotions::admin_scope("/promotions").wrap(admin_cache.clone()))
+                    .service(translations::admin_scope("/translations").wrap(admin_cache.clone()))
+                    .service(trash::admin_scope("/trash")),
             )
             .wrap(Cors::permissive().allow_any_origin())
             .wrap(middleware::DefaultHeaders::new().header("Content-Type", "text/plain"))
             .wrap(middleware::Logger::default())
             .wrap(middleware::NormalizePath::new(TrailingSlash::Trim))
-            .service(fs::Files::new("/images", "images"))
+            .service(images::files_scope(
+                "/images",
+                &cache_control,
+                &images_cache_control,
+            ))
             .service(fs::Files::new("/", "docs").index_file("index.html"))
     })
     .bind((config.host, config.port))?
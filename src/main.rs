@@ -1,5 +1,3 @@
-use bjoetiek;
-
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
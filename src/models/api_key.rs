@@ -0,0 +1,59 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::schema::api_keys;
+
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub name: String,
+    pub prefix: String,
+    #[serde(skip_serializing)]
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+    pub revoked_at: Option<NaiveDateTime>,
+}
+
+impl ApiKey {
+    /// Checks if the key grants the provided scope. Scope "admin" grants
+    /// everything and "<resource>:write" implies "<resource>:read".
+    pub fn allows(&self, scope: &str) -> bool {
+        scopes_allow(&self.scopes, scope)
+    }
+}
+
+/// Checks if the scopes grant the provided scope, see `ApiKey::allows`
+pub fn scopes_allow(scopes: &[String], scope: &str) -> bool {
+    let read_via_write = scope
+        .strip_suffix(":read")
+        .map(|resource| format!("{}:write", resource));
+    scopes
+        .iter()
+        .any(|s| s == "admin" || s == scope || Some(s) == read_via_write.as_ref())
+}
+
+/// Key returned once on creation, as only its hash is stored
+#[derive(Debug, Serialize)]
+pub struct ApiKeyWithSecret {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
+#[derive(Debug, Insertable, Deserialize)]
+#[table_name = "api_keys"]
+pub struct ApiKeyData {
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<NaiveDateTime>,
+
+    #[serde(skip_deserializing)]
+    pub prefix: String,
+
+    #[serde(skip_deserializing)]
+    pub key_hash: String,
+}
@@ -0,0 +1,49 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::schema::audit_log;
+
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+#[table_name = "audit_log"]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub user_issuer: String,
+    pub user_subject: String,
+    pub user_name: Option<String>,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub action: String,
+    pub diff: Value,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "audit_log"]
+pub struct AuditEntryData {
+    pub user_issuer: String,
+    pub user_subject: String,
+    pub user_name: Option<String>,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub action: String,
+    pub diff: Value,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct AuditFilter {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<Uuid>,
+
+    /// Matches either the subject or the name of the user
+    pub user: Option<String>,
+
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    #[validate(range(min = 0))]
+    pub limit: Option<i64>,
+    #[validate(range(min = 0))]
+    pub offset: Option<i64>,
+}
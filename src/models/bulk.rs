@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{validate_product_status, ProductPatchWithMeta, ProductWithMeta, ValidationErrors};
+
+/// Max number of products affected by a single bulk request
+pub const MAX_BULK_ITEMS: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    /// Rolls back all changes after building the report
+    #[serde(default)]
+    pub dry_run: bool,
+
+    pub operations: Vec<BulkOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkOperation {
+    pub product_ids: Vec<Uuid>,
+    #[serde(flatten)]
+    pub action: BulkAction,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    SetStatus { status: String },
+    AdjustPrice { by: PriceAdjustment },
+    SetCategories { category_ids: Vec<Uuid> },
+    AddCategories { category_ids: Vec<Uuid> },
+    RemoveCategories { category_ids: Vec<Uuid> },
+    SetManufacturer { manufacturer_id: Option<Uuid> },
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceAdjustment {
+    /// Relative change, e.g. -10 for a discount of 10%. Rounded to whole cents.
+    Percentage(f64),
+
+    /// Absolute change in cents
+    Amount(i32),
+}
+
+impl BulkAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BulkAction::SetStatus { .. } => "set_status",
+            BulkAction::AdjustPrice { .. } => "adjust_price",
+            BulkAction::SetCategories { .. } => "set_categories",
+            BulkAction::AddCategories { .. } => "add_categories",
+            BulkAction::RemoveCategories { .. } => "remove_categories",
+            BulkAction::SetManufacturer { .. } => "set_manufacturer",
+            BulkAction::Delete => "delete",
+        }
+    }
+
+    /// Builds the patch which applies the action to the product. Returns
+    /// `None` for actions which don't update the product.
+    pub fn to_patch(
+        &self,
+        current: &ProductWithMeta,
+    ) -> Result<Option<ProductPatchWithMeta>, ValidationErrors> {
+        let mut patch = ProductPatchWithMeta::default();
+        let mut errors = ValidationErrors::new();
+        match self {
+            BulkAction::SetStatus { status } => {
+                if let Err(e) = validate_product_status(status) {
+                    errors.add("status", e.message.unwrap_or_default());
+                }
+                patch.product.status = Some(status.clone());
+            }
+            BulkAction::AdjustPrice { by } => match by.apply(current.product.price) {
+                Some(price) => patch.product.price = Some(price),
+                None => errors.add("price", "Adjusted price is out of range"),
+            },
+            BulkAction::SetCategories { category_ids } => {
+                patch.category_ids = Some(category_ids.clone());
+            }
+            BulkAction::AddCategories { category_ids } => {
+                let mut ids = current.category_ids.clone();
+                for id in category_ids {
+                    if !ids.contains(id) {
+                        ids.push(*id);
+                    }
+                }
+                patch.category_ids = Some(ids);
+            }
+            BulkAction::RemoveCategories { category_ids } => {
+                let mut ids = current.category_ids.clone();
+                ids.retain(|id| !category_ids.contains(id));
+                patch.category_ids = Some(ids);
+            }
+            BulkAction::SetManufacturer { manufacturer_id } => {
+                patch.product.manufacturer_id = Some(*manufacturer_id);
+            }
+            BulkAction::Delete => return Ok(None),
+        }
+        errors.into_result().map(|_| Some(patch))
+    }
+}
+
+impl PriceAdjustment {
+    /// Returns the adjusted price or `None` if it's out of range
+    pub fn apply(self, price: i32) -> Option<i32> {
+        let adjusted = match self {
+            PriceAdjustment::Percentage(percentage) => {
+                (f64::from(price) * (100.0 + percentage) / 100.0).round()
+            }
+            PriceAdjustment::Amount(amount) => f64::from(price) + f64::from(amount),
+        };
+        if adjusted.is_finite() && adjusted >= 0.0 && adjusted <= f64::from(i32::MAX) {
+            Some(adjusted as i32)
+        } else {
+            None
+        }
+    }
+}
+
+impl BulkRequest {
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let count: usize = self.operations.iter().map(|o| o.product_ids.len()).sum();
+        if count == 0 {
+            errors.add("operations", "Should affect at least 1 product");
+        } else if count > MAX_BULK_ITEMS {
+            let message = format!("Should affect at most {} products", MAX_BULK_ITEMS);
+            errors.add("operations", message);
+        }
+        errors.into_result()
+    }
+}
+
+/// Outcome of a bulk request. Changes are only applied if all items succeed.
+#[derive(Debug, Serialize)]
+pub struct BulkReport {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub results: Vec<BulkItemResult>,
+}
+
+impl BulkReport {
+    pub fn has_errors(&self) -> bool {
+        self.results.iter().any(|r| r.error.is_some())
+    }
+}
+
+/// Result of applying one operation to one product
+#[derive(Debug, Serialize)]
+pub struct BulkItemResult {
+    /// Index of the operation in the request
+    pub operation: usize,
+    pub action: &'static str,
+    pub product_id: Uuid,
+
+    /// Changed fields as `{"field": {"old": ..., "new": ...}}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Product;
+    use chrono::NaiveDateTime;
+
+    fn product(price: i32, category_ids: Vec<Uuid>) -> ProductWithMeta {
+        ProductWithMeta {
+            product: Product {
+                id: Uuid::nil(),
+                created_at: NaiveDateTime::from_timestamp(0, 0),
+                updated_at: NaiveDateTime::from_timestamp(0, 0),
+                name: "Soap".to_string(),
+                slug: "soap".to_string(),
+                description_short: String::new(),
+                description_long: String::new(),
+                price,
+                manufacturer_id: None,
+                status: "AVAILABLE".to_string(),
+                stock_count: 0,
+                version: 1,
+                sku: None,
+                meta_title: None,
+                meta_description: None,
+                canonical_url: None,
+                noindex: false,
+                deleted_at: None,
+                publish_at: None,
+                unpublish_at: None,
+                sale_price: None,
+                sale_starts_at: None,
+                sale_ends_at: None,
+                tax_rate: 21,
+            },
+            category_ids,
+            pricing: None,
+        }
+    }
+
+    #[test]
+    fn test_price_adjustment() {
+        assert_eq!(PriceAdjustment::Percentage(-10.0).apply(1995), Some(1796));
+        assert_eq!(PriceAdjustment::Percentage(50.0).apply(1000), Some(1500));
+        assert_eq!(PriceAdjustment::Amount(-500).apply(1000), Some(500));
+        assert_eq!(PriceAdjustment::Amount(-1001).apply(1000), None);
+        assert_eq!(PriceAdjustment::Percentage(-101.0).apply(1000), None);
+    }
+
+    #[test]
+    fn test_to_patch() {
+        let (a, b, c) = (Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3));
+        let current = product(1000, vec![a, b]);
+
+        let action = BulkAction::AddCategories {
+            category_ids: vec![b, c],
+        };
+        let patch = action.to_patch(&current).unwrap().unwrap();
+        assert_eq!(patch.category_ids, Some(vec![a, b, c]));
+
+        let action = BulkAction::RemoveCategories {
+            category_ids: vec![a],
+        };
+        let patch = action.to_patch(&current).unwrap().unwrap();
+        assert_eq!(patch.category_ids, Some(vec![b]));
+
+        let action = BulkAction::SetStatus {
+            status: "SOLD".to_string(),
+        };
+        assert!(action.to_patch(&current).is_err());
+        assert!(BulkAction::Delete.to_patch(&current).unwrap().is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::HashSet;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{
+    tax_summary, CouponDiscount, TaxAmounts, TaxLine, ValidationErrors, SHIPPING_TAX_RATE,
+};
+use crate::schema::{cart_items, carts};
+
+#[derive(Debug, Identifiable, Queryable)]
+pub struct Cart {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub coupon_id: Option<Uuid>,
+}
+
+#[derive(Debug, Queryable, Insertable)]
+#[table_name = "cart_items"]
+pub struct CartItem {
+    pub cart_id: Uuid,
+    pub product_id: Uuid,
+    pub quantity: i32,
+}
+
+/// New quantity of a product in a cart
+#[derive(Debug, Deserialize, Validate)]
+pub struct CartItemData {
+    #[validate(range(min = 1, max = 999))]
+    pub quantity: i32,
+}
+
+/// Product in a cart, priced with the current sales and promotions
+#[derive(Debug, Clone, Serialize)]
+pub struct CartLine {
+    pub product_id: Uuid,
+    pub name: String,
+    pub slug: String,
+    pub quantity: i32,
+    pub regular_price: i32,
+
+    /// Effective price of a single item
+    pub unit_price: i32,
+    pub total: i32,
+
+    /// VAT rate of the product in percent
+    pub tax_rate: i16,
+
+    /// Total split into net, tax and gross, before the discount
+    pub tax_breakdown: TaxAmounts,
+
+    #[serde(skip)]
+    pub manufacturer_id: Option<Uuid>,
+
+    /// Categories of the product and the categories above them
+    #[serde(skip)]
+    pub category_ids: HashSet<Uuid>,
+}
+
+/// Amounts in cents of a cart or an order. The subtotal, discount and
+/// shipping include tax if prices include tax.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct OrderTotals {
+    pub subtotal: i32,
+    pub discount: i32,
+    pub shipping: i32,
+    pub net: i32,
+    pub tax: i32,
+
+    /// Amount to pay, including tax
+    pub total: i32,
+
+    /// Amounts per tax rate. The discount is subtracted at the rates of the
+    /// discounted products, shipping is taxed at the standard rate.
+    pub taxes: Vec<TaxLine>,
+}
+
+impl OrderTotals {
+    /// Adds up the lines and applies the coupon. Shipping is only charged on
+    /// carts with products. Fails if the total including tax doesn't fit in
+    /// the stored amounts.
+    pub fn new(
+        lines: &[CartLine],
+        coupon: Option<&CouponDiscount>,
+        shipping_cost: i32,
+        prices_include_tax: bool,
+    ) -> Result<Self, ValidationErrors> {
+        let no_coupon = CouponDiscount::default();
+        let coupon = coupon.unwrap_or(&no_coupon);
+        let shipping = if lines.is_empty() || coupon.free_shipping {
+            0
+        } else {
+            shipping_cost
+        };
+
+        // Bound the total before adding up, so none of the amounts overflow
+        let amount: i64 = lines
+            .iter()
+            .map(|l| i64::from(l.total))
+            .chain(Some(i64::from(shipping)))
+            .sum();
+        let max_rate = lines
+            .iter()
+            .map(|l| l.tax_rate)
+            .chain(Some(SHIPPING_TAX_RATE))
+            .max()
+            .map_or(0, i64::from);
+        let max_total = if prices_include_tax {
+            amount
+        } else {
+            // Tax is rounded up by at most a cent per rate
+            amount * (100 + max_rate) / 100 + lines.len() as i64 + 1
+        };
+        if max_total > i64::from(i32::MAX) {
+            let mut errors = ValidationErrors::new();
+            errors.add("items", "Total of the order is too large");
+            return Err(errors);
+        }
+
+        let subtotal = lines.iter().map(|l| l.total).sum();
+        let discount = coupon.discount.min(subtotal);
+
+        let amounts = lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let discount = coupon.per_line.get(i).copied().unwrap_or_default();
+                (line.tax_rate, line.total - discount)
+            })
+            .chain(Some((SHIPPING_TAX_RATE, shipping)).filter(|_| shipping > 0));
+        let taxes = tax_summary(amounts, prices_include_tax);
+        Ok(Self {
+            subtotal,
+            discount,
+            shipping,
+            net: taxes.iter().map(|t| t.amounts.net).sum(),
+            tax: taxes.iter().map(|t| t.amounts.tax).sum(),
+            total: taxes.iter().map(|t| t.amounts.gross).sum(),
+            taxes,
+        })
+    }
+}
+
+/// Coupon applied to a cart
+#[derive(Debug, Serialize)]
+pub struct CartCoupon {
+    pub code: String,
+    pub discount_type: String,
+    pub discount_value: i32,
+
+    /// Reason the coupon doesn't apply to the cart at the moment, e.g. after
+    /// removing products
+    pub rejection: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CartView {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub items: Vec<CartLine>,
+    pub coupon: Option<CartCoupon>,
+    #[serde(flatten)]
+    pub totals: OrderTotals,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(total: i32, tax_rate: i16) -> CartLine {
+        CartLine {
+            product_id: Uuid::from_u128(total as u128),
+            name: "Soap".to_string(),
+            slug: "soap".to_string(),
+            quantity: 1,
+            regular_price: total,
+            unit_price: total,
+            total,
+            tax_rate,
+            tax_breakdown: TaxAmounts::new(total, tax_rate, true),
+            manufacturer_id: None,
+            category_ids: HashSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_order_totals() {
+        let lines = [line(2420, 21), line(1060, 6)];
+        let coupon = CouponDiscount {
+            discount: 348,
+            free_shipping: false,
+            per_line: vec![242, 106],
+        };
+
+        let totals = OrderTotals::new(&lines, Some(&coupon), 605, true).unwrap();
+        assert_eq!(totals.total, 2420 + 1060 - 348 + 605);
+        let rates: Vec<_> = totals
+            .taxes
+            .iter()
+            .map(|t| (t.rate, t.amounts.tax))
+            .collect();
+        assert_eq!(rates, vec![(21, 483), (6, 54)]);
+        assert_eq!(totals.net + totals.tax, totals.total);
+
+        let totals = OrderTotals::new(&lines, None, 500, false).unwrap();
+        assert_eq!(totals.net, 3980);
+        // 2920 * 0.21 = 613.2 and 1060 * 0.06 = 63.6
+        assert_eq!(totals.tax, 613 + 64);
+        assert_eq!(totals.total, totals.net + totals.tax);
+    }
+
+    #[test]
+    fn test_order_totals_too_large() {
+        let lines = [line(i32::MAX - 100, 21), line(1000, 6)];
+        assert!(OrderTotals::new(&lines, None, 0, true).is_err());
+
+        // Fits without tax, but not with
+        let lines = [line(2_000_000_000, 21)];
+        assert!(OrderTotals::new(&lines, None, 0, true).is_ok());
+        assert!(OrderTotals::new(&lines, None, 0, false).is_err());
+    }
+}
@@ -1,6 +1,10 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
+use validator::Validate;
 
+use super::{validate_not_blank, validate_website_url, MergePatch, ValidationErrors, Versioned};
 use crate::models::Product;
 use crate::schema::{categories, category_products};
 
@@ -12,19 +16,143 @@ pub struct Category {
     pub slug: String,
     pub description: String,
     pub sort_order: i16,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub version: i32,
+    pub meta_title: Option<String>,
+    pub meta_description: Option<String>,
+    pub canonical_url: Option<String>,
+    pub noindex: bool,
+    pub parent_id: Option<Uuid>,
+
+    /// Moment the entity was moved to the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
+
+    /// Start of the period in which the category is shown to visitors
+    pub publish_at: Option<NaiveDateTime>,
+
+    /// End of the period in which the category is shown to visitors
+    pub unpublish_at: Option<NaiveDateTime>,
+}
+
+impl Category {
+    /// Checks if the category is shown to visitors at the provided moment
+    pub fn is_published(&self, now: NaiveDateTime) -> bool {
+        self.publish_at.is_none_or(|at| at <= now) && self.unpublish_at.is_none_or(|at| at > now)
+    }
 }
 
-#[derive(Debug, Insertable, AsChangeset, Deserialize)]
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Validate)]
 #[table_name = "categories"]
+#[changeset_options(treat_none_as_null = "true")]
 pub struct CategoryData {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
     pub name: String,
+    #[validate(length(max = 5000))]
     pub description: String,
     pub sort_order: i16,
 
+    /// Parent category, a category without parent is at the top of the tree
+    #[serde(default)]
+    pub parent_id: Option<Uuid>,
+
+    /// Title for search engines, the name is used if not set
+    #[serde(default)]
+    #[validate(length(min = 1, max = 200))]
+    pub meta_title: Option<String>,
+    #[serde(default)]
+    #[validate(length(min = 1, max = 500))]
+    pub meta_description: Option<String>,
+
+    /// URL of the preferred page if this category is a duplicate
+    #[serde(default)]
+    #[validate(length(min = 1, max = 500), custom = "validate_website_url")]
+    pub canonical_url: Option<String>,
+
+    /// Hides the category from search engines and the sitemap
+    #[serde(default)]
+    pub noindex: bool,
+
+    /// Moment the category with its subcategories becomes visible
+    #[serde(default)]
+    pub publish_at: Option<NaiveDateTime>,
+
+    /// Moment the category with its subcategories is hidden again
+    #[serde(default)]
+    pub unpublish_at: Option<NaiveDateTime>,
+
     #[serde(skip_deserializing)]
     pub slug: String,
 }
 
+/// Partial update of a category. Only the provided columns are updated.
+#[derive(Debug, Default, AsChangeset, Validate)]
+#[table_name = "categories"]
+pub struct CategoryPatch {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
+    pub name: Option<String>,
+    #[validate(length(max = 5000))]
+    pub description: Option<String>,
+    pub sort_order: Option<i16>,
+    pub parent_id: Option<Option<Uuid>>,
+    #[validate(length(min = 1, max = 200))]
+    pub meta_title: Option<Option<String>>,
+    #[validate(length(min = 1, max = 500))]
+    pub meta_description: Option<Option<String>>,
+    #[validate(length(min = 1, max = 500), custom = "validate_website_url")]
+    pub canonical_url: Option<Option<String>>,
+    pub noindex: Option<bool>,
+    pub publish_at: Option<Option<NaiveDateTime>>,
+    pub unpublish_at: Option<Option<NaiveDateTime>>,
+    pub slug: Option<String>,
+}
+
+impl CategoryPatch {
+    /// Parses and validates a JSON Merge Patch of a category
+    pub fn from_merge_patch(patch: &Value) -> Result<Versioned<Self>, ValidationErrors> {
+        let mut reader = MergePatch::new(patch)?;
+        let category = Self {
+            name: reader.field("name"),
+            description: reader.field("description"),
+            sort_order: reader.field("sort_order"),
+            parent_id: reader.nullable_field("parent_id"),
+            meta_title: reader.nullable_field("meta_title"),
+            meta_description: reader.nullable_field("meta_description"),
+            canonical_url: reader.nullable_field("canonical_url"),
+            noindex: reader.field("noindex"),
+            publish_at: reader.nullable_field("publish_at"),
+            unpublish_at: reader.nullable_field("unpublish_at"),
+            slug: None,
+        };
+        let version = reader.field("version");
+
+        if let Err(errors) = category.validate() {
+            reader.errors(errors.into());
+        }
+
+        reader.finish()?;
+        Ok(Versioned {
+            data: category,
+            version,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.description.is_none()
+            && self.sort_order.is_none()
+            && self.parent_id.is_none()
+            && self.meta_title.is_none()
+            && self.meta_description.is_none()
+            && self.canonical_url.is_none()
+            && self.noindex.is_none()
+            && self.publish_at.is_none()
+            && self.unpublish_at.is_none()
+            && self.slug.is_none()
+    }
+}
+
 #[derive(Queryable, Identifiable, Associations, Insertable)]
 #[belongs_to(Category)]
 #[belongs_to(Product)]
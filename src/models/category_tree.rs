@@ -0,0 +1,240 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::Category;
+
+/// Category with its subcategories
+#[derive(Debug, Serialize)]
+pub struct CategoryNode {
+    #[serde(flatten)]
+    pub category: Category,
+    pub children: Vec<CategoryNode>,
+}
+
+/// New order of the subcategories of a parent, or of the top level
+/// categories if the parent is null
+#[derive(Debug, Deserialize)]
+pub struct CategoryOrder {
+    pub parent_id: Option<Uuid>,
+    pub category_ids: Vec<Uuid>,
+}
+
+/// Builds the tree below the root, or the whole tree if no root is provided.
+/// Siblings are sorted by sort order and name. Returns `None` if the root
+/// doesn't exist.
+pub fn category_tree(categories: Vec<Category>, root: Option<Uuid>) -> Option<Vec<CategoryNode>> {
+    let ids: HashSet<Uuid> = categories.iter().map(|c| c.id).collect();
+    let mut by_parent: HashMap<Option<Uuid>, Vec<Category>> = HashMap::new();
+    let mut root_category = None;
+    for category in categories {
+        if Some(category.id) == root {
+            root_category = Some(category);
+            continue;
+        }
+        // Categories with an unknown parent are shown at the top level
+        let parent_id = category.parent_id.filter(|id| ids.contains(id));
+        by_parent.entry(parent_id).or_default().push(category);
+    }
+
+    match root {
+        Some(_) => {
+            let category = root_category?;
+            Some(vec![build_node(&mut by_parent, category)])
+        }
+        None => Some(build_nodes(&mut by_parent, None)),
+    }
+}
+
+fn build_nodes(
+    by_parent: &mut HashMap<Option<Uuid>, Vec<Category>>,
+    parent_id: Option<Uuid>,
+) -> Vec<CategoryNode> {
+    let mut children = by_parent.remove(&parent_id).unwrap_or_default();
+    children.sort_by(|a, b| (a.sort_order, &a.name).cmp(&(b.sort_order, &b.name)));
+    children
+        .into_iter()
+        .map(|category| build_node(by_parent, category))
+        .collect()
+}
+
+fn build_node(
+    by_parent: &mut HashMap<Option<Uuid>, Vec<Category>>,
+    category: Category,
+) -> CategoryNode {
+    CategoryNode {
+        children: build_nodes(by_parent, Some(category.id)),
+        category,
+    }
+}
+
+/// Returns the path from the top level category to the category, or `None`
+/// if the category doesn't exist
+pub fn breadcrumbs(categories: Vec<Category>, id: Uuid) -> Option<Vec<Category>> {
+    let mut by_id: HashMap<Uuid, Category> = categories.into_iter().map(|c| (c.id, c)).collect();
+    let mut path = Vec::new();
+    let mut current = Some(id);
+    while let Some(category) = current.and_then(|id| by_id.remove(&id)) {
+        current = category.parent_id;
+        path.push(category);
+    }
+    if path.is_empty() {
+        return None;
+    }
+    path.reverse();
+    Some(path)
+}
+
+/// Returns the IDs of the category and all categories below it
+pub fn descendant_ids(categories: &[Category], id: Uuid) -> Vec<Uuid> {
+    let mut ids = vec![id];
+    let mut i = 0;
+    while i < ids.len() {
+        let parent_id = ids[i];
+        for category in categories {
+            if category.parent_id == Some(parent_id) && !ids.contains(&category.id) {
+                ids.push(category.id);
+            }
+        }
+        i += 1;
+    }
+    ids
+}
+
+/// Removes the categories which aren't published at the provided moment,
+/// together with their subcategories
+pub fn published_categories(categories: Vec<Category>, now: NaiveDateTime) -> Vec<Category> {
+    let hidden: HashSet<Uuid> = categories
+        .iter()
+        .filter(|c| !c.is_published(now))
+        .flat_map(|c| descendant_ids(&categories, c.id))
+        .collect();
+    categories
+        .into_iter()
+        .filter(|c| !hidden.contains(&c.id))
+        .collect()
+}
+
+/// Checks if the category is the ancestor or one of its descendants
+pub fn is_descendant_or_self(categories: &[Category], ancestor: Uuid, id: Uuid) -> bool {
+    let parents: HashMap<Uuid, Option<Uuid>> =
+        categories.iter().map(|c| (c.id, c.parent_id)).collect();
+    let mut visited = HashSet::new();
+    let mut current = Some(id);
+    while let Some(id) = current {
+        if id == ancestor {
+            return true;
+        }
+        if !visited.insert(id) {
+            return false;
+        }
+        current = parents.get(&id).copied().flatten();
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(n: u128, parent: Option<u128>, sort_order: i16) -> Category {
+        Category {
+            id: Uuid::from_u128(n),
+            name: format!("Category {}", n),
+            slug: format!("category-{}", n),
+            description: String::new(),
+            sort_order,
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+            updated_at: NaiveDateTime::from_timestamp(0, 0),
+            version: 1,
+            meta_title: None,
+            meta_description: None,
+            canonical_url: None,
+            noindex: false,
+            parent_id: parent.map(Uuid::from_u128),
+            deleted_at: None,
+            publish_at: None,
+            unpublish_at: None,
+        }
+    }
+
+    /// 1 > (3 > 4, 2), 5
+    fn categories() -> Vec<Category> {
+        vec![
+            category(1, None, 0),
+            category(2, Some(1), 1),
+            category(3, Some(1), 0),
+            category(4, Some(3), 0),
+            category(5, None, 1),
+        ]
+    }
+
+    fn ids(nodes: &[CategoryNode]) -> Vec<u128> {
+        nodes.iter().map(|n| n.category.id.as_u128()).collect()
+    }
+
+    #[test]
+    fn test_category_tree() {
+        let tree = category_tree(categories(), None).unwrap();
+        assert_eq!(ids(&tree), vec![1, 5]);
+        assert_eq!(ids(&tree[0].children), vec![3, 2]);
+        assert_eq!(ids(&tree[0].children[0].children), vec![4]);
+
+        let subtree = category_tree(categories(), Some(Uuid::from_u128(3))).unwrap();
+        assert_eq!(ids(&subtree), vec![3]);
+        assert_eq!(ids(&subtree[0].children), vec![4]);
+        assert!(category_tree(categories(), Some(Uuid::from_u128(9))).is_none());
+    }
+
+    #[test]
+    fn test_breadcrumbs() {
+        let path = breadcrumbs(categories(), Uuid::from_u128(4)).unwrap();
+        let path: Vec<_> = path.iter().map(|c| c.id.as_u128()).collect();
+        assert_eq!(path, vec![1, 3, 4]);
+        assert!(breadcrumbs(categories(), Uuid::from_u128(9)).is_none());
+    }
+
+    #[test]
+    fn test_descendants() {
+        let categories = categories();
+        let ids: Vec<_> = descendant_ids(&categories, Uuid::from_u128(1))
+            .iter()
+            .map(Uuid::as_u128)
+            .collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        let id = Uuid::from_u128;
+        assert!(is_descendant_or_self(&categories, id(1), id(4)));
+        assert!(is_descendant_or_self(&categories, id(4), id(4)));
+        assert!(!is_descendant_or_self(&categories, id(4), id(1)));
+        assert!(!is_descendant_or_self(&categories, id(5), id(4)));
+    }
+
+    #[test]
+    fn test_published_categories() {
+        let at = |secs| NaiveDateTime::from_timestamp(secs, 0);
+        let scheduled = || {
+            let mut categories = categories();
+            categories[2].publish_at = Some(at(100));
+            categories[4].unpublish_at = Some(at(200));
+            categories
+        };
+        let ids = |categories: Vec<Category>| -> Vec<u128> {
+            categories.iter().map(|c| c.id.as_u128()).collect()
+        };
+        assert_eq!(
+            ids(published_categories(scheduled(), at(50))),
+            vec![1, 2, 5]
+        );
+        assert_eq!(
+            ids(published_categories(scheduled(), at(100))),
+            vec![1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            ids(published_categories(scheduled(), at(200))),
+            vec![1, 2, 3, 4]
+        );
+    }
+}
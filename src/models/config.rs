@@ -1,16 +1,64 @@
-use lazy_static::lazy_static;
-use std::{env, net::IpAddr, path::PathBuf};
+use std::{env, net::IpAddr, path::PathBuf, time::Duration};
 
-lazy_static! {
-    static ref KEYCLOAK_PUBLIC_KEY: String = parse_required_string("KEYCLOAK_PUBLIC_KEY");
+use url::Url;
+
+/// Token bucket which allows `capacity` requests per `period`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub capacity: u32,
+    pub period: Duration,
+}
+
+/// IP address range in CIDR notation, e.g. 10.0.0.0/8
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IpNetwork {
+    pub addr: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let (network, ip, bits) = match (self.addr, ip) {
+            (IpAddr::V4(n), IpAddr::V4(i)) => (u32::from(n) as u128, u32::from(i) as u128, 32),
+            (IpAddr::V6(n), IpAddr::V6(i)) => (u128::from(n), u128::from(i), 128),
+            _ => return false,
+        };
+        let host_bits = bits - u32::from(self.prefix_len.min(bits as u8));
+        network.checked_shr(host_bits).unwrap_or(0) == ip.checked_shr(host_bits).unwrap_or(0)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub host: IpAddr,
     pub port: u16,
-    pub keycloak_public_key: &'static str,
+    pub oidc_issuer_urls: Vec<String>,
+    pub oidc_audience: String,
+    pub oidc_roles_claim: String,
+    pub oidc_admin_role: String,
+    pub oidc_jwks_ttl: Duration,
     pub images_path: PathBuf,
+    pub cache_control: String,
+    pub images_cache_control: String,
+    pub rate_limit_listing: Option<RateLimit>,
+    pub rate_limit_cart: Option<RateLimit>,
+    pub trusted_proxies: Vec<IpNetwork>,
+    pub shop_name: String,
+    pub shop_url: String,
+    pub public_url: String,
+    pub currency: String,
+    pub product_url_template: String,
+    pub category_url_template: String,
+    pub manufacturer_url_template: String,
+    pub trash_retention: Duration,
+    pub cart_retention: Duration,
+    pub purge_interval: Duration,
+    pub scheduler_interval: Duration,
+    pub shipping_cost: i32,
+    pub prices_include_tax: bool,
+
+    /// Supported locales of the content, starting with the default locale
+    pub locales: Vec<String>,
 }
 
 impl Config {
@@ -18,10 +66,66 @@ impl Config {
         Self {
             host: parse_ip_addr("BIND_HOST", [0, 0, 0, 0].into()),
             port: parse_port("BIND_PORT", 8090),
-            keycloak_public_key: KEYCLOAK_PUBLIC_KEY.as_str(),
+            oidc_issuer_urls: parse_required_list("OIDC_ISSUER_URLS"),
+            oidc_audience: parse_required_string("OIDC_AUDIENCE"),
+            oidc_roles_claim: parse_string("OIDC_ROLES_CLAIM", "realm_access.roles"),
+            oidc_admin_role: parse_string("OIDC_ADMIN_ROLE", "admin"),
+            oidc_jwks_ttl: parse_duration_secs("OIDC_JWKS_TTL", 3600),
             images_path: parse_required_pathbuf("IMAGES_PATH"),
+            cache_control: parse_string("CACHE_CONTROL", "public, max-age=60"),
+            images_cache_control: parse_string(
+                "IMAGES_CACHE_CONTROL",
+                "public, max-age=31536000, immutable",
+            ),
+            rate_limit_listing: parse_rate_limit("RATE_LIMIT_LISTING", "120/60"),
+            rate_limit_cart: parse_rate_limit("RATE_LIMIT_CART", "60/60"),
+            trusted_proxies: parse_ip_networks("TRUSTED_PROXIES"),
+            shop_name: parse_string("SHOP_NAME", "Bjoetiek Y"),
+            shop_url: parse_base_url("SHOP_URL", "http://localhost:8080"),
+            public_url: parse_base_url("PUBLIC_URL", "http://localhost:8090"),
+            currency: parse_currency("CURRENCY", "EUR"),
+            product_url_template: parse_url_template("PRODUCT_URL_TEMPLATE", "/products/{slug}"),
+            category_url_template: parse_url_template(
+                "CATEGORY_URL_TEMPLATE",
+                "/categories/{slug}",
+            ),
+            manufacturer_url_template: parse_url_template(
+                "MANUFACTURER_URL_TEMPLATE",
+                "/manufacturers/{slug}",
+            ),
+            trash_retention: parse_duration_secs("TRASH_RETENTION", 30 * 24 * 3600),
+            cart_retention: parse_duration_secs("CART_RETENTION", 7 * 24 * 3600),
+            purge_interval: parse_interval_secs("PURGE_INTERVAL", 3600),
+            scheduler_interval: parse_interval_secs("SCHEDULER_INTERVAL", 60),
+            shipping_cost: parse_cents("SHIPPING_COST", 0),
+            prices_include_tax: parse_bool("PRICES_INCLUDE_TAX", true),
+            locales: parse_locales("LOCALES", "nl,fr,en"),
         }
     }
+
+    /// URL of the page of a product in the storefront
+    pub fn product_url(&self, slug: &str) -> String {
+        self.shop_page_url(&self.product_url_template, slug)
+    }
+
+    /// URL of the page of a category in the storefront
+    pub fn category_url(&self, slug: &str) -> String {
+        self.shop_page_url(&self.category_url_template, slug)
+    }
+
+    /// URL of the page of a manufacturer in the storefront
+    pub fn manufacturer_url(&self, slug: &str) -> String {
+        self.shop_page_url(&self.manufacturer_url_template, slug)
+    }
+
+    /// Locale of the content stored in products and categories themselves
+    pub fn default_locale(&self) -> &str {
+        &self.locales[0]
+    }
+
+    fn shop_page_url(&self, template: &str, slug: &str) -> String {
+        format!("{}{}", self.shop_url, template.replace("{slug}", slug))
+    }
 }
 
 pub fn parse_ip_addr(env_var: &str, default: IpAddr) -> IpAddr {
@@ -44,19 +148,182 @@ pub fn parse_port(env_var: &str, default: u16) -> u16 {
     }
 }
 
+pub fn parse_duration_secs(env_var: &str, default: u64) -> Duration {
+    let secs = env::var(env_var);
+    if let Ok(secs) = secs {
+        secs.parse().map(Duration::from_secs).unwrap_or_else(|_| {
+            panic!(
+                "Provided {} is not a valid number of seconds: {}",
+                env_var, secs
+            )
+        })
+    } else {
+        Duration::from_secs(default)
+    }
+}
+
+/// Parses a positive number of seconds between runs of a job
+pub fn parse_interval_secs(env_var: &str, default: u64) -> Duration {
+    let interval = parse_duration_secs(env_var, default);
+    if interval.as_secs() == 0 {
+        panic!("Provided {} should be at least 1 second", env_var);
+    }
+    interval
+}
+
+/// Parses a non-negative amount in cents
+pub fn parse_cents(env_var: &str, default: i32) -> i32 {
+    let cents = env::var(env_var);
+    if let Ok(cents) = cents {
+        match cents.parse() {
+            Ok(cents) if cents >= 0 => cents,
+            _ => panic!(
+                "Provided {} is not a valid amount in cents: {}",
+                env_var, cents
+            ),
+        }
+    } else {
+        default
+    }
+}
+
+pub fn parse_bool(env_var: &str, default: bool) -> bool {
+    let value = env::var(env_var);
+    if let Ok(value) = value {
+        match value.as_str() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => panic!("Provided {} is not a valid boolean: {}", env_var, value),
+        }
+    } else {
+        default
+    }
+}
+
+/// Parses a rate limit as "<requests>/<seconds>" or "off"
+pub fn parse_rate_limit(env_var: &str, default: &str) -> Option<RateLimit> {
+    let value = parse_string(env_var, default);
+    if value == "off" {
+        return None;
+    }
+
+    let mut parts = value.splitn(2, '/');
+    let capacity = parts.next().and_then(|c| c.parse().ok());
+    let period = parts.next().and_then(|p| p.parse().ok());
+    match (capacity, period) {
+        (Some(capacity), Some(period)) if capacity > 0 && period > 0 => Some(RateLimit {
+            capacity,
+            period: Duration::from_secs(period),
+        }),
+        _ => panic!("Provided {} is not a valid rate limit: {}", env_var, value),
+    }
+}
+
+/// Parses a comma separated list of IP addresses and CIDR ranges
+pub fn parse_ip_networks(env_var: &str) -> Vec<IpNetwork> {
+    parse_string(env_var, "")
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|value| {
+            let mut parts = value.splitn(2, '/');
+            let addr = parts.next().and_then(|a| a.parse::<IpAddr>().ok());
+            let prefix_len = parts.next().map(|l| l.parse::<u8>().ok());
+            match (addr, prefix_len) {
+                (Some(addr), None) => IpNetwork {
+                    addr,
+                    prefix_len: if addr.is_ipv4() { 32 } else { 128 },
+                },
+                (Some(addr), Some(Some(prefix_len)))
+                    if prefix_len <= if addr.is_ipv4() { 32 } else { 128 } =>
+                {
+                    IpNetwork { addr, prefix_len }
+                }
+                _ => panic!(
+                    "Provided {} contains an invalid network: {}",
+                    env_var, value
+                ),
+            }
+        })
+        .collect()
+}
+
+/// Parses an absolute http(s) URL. The trailing slash is removed.
+pub fn parse_base_url(env_var: &str, default: &str) -> String {
+    let value = parse_string(env_var, default);
+    match Url::parse(&value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            value.trim_end_matches('/').to_string()
+        }
+        _ => panic!("Provided {} is not a valid http(s) URL: {}", env_var, value),
+    }
+}
+
+/// Parses a path relative to the shop URL, in which "{slug}" is replaced
+/// by the slug of an entity
+pub fn parse_url_template(env_var: &str, default: &str) -> String {
+    let value = parse_string(env_var, default);
+    if value.starts_with('/') && value.contains("{slug}") {
+        value
+    } else {
+        panic!(
+            "Provided {} should be a path starting with / and containing {{slug}}: {}",
+            env_var, value
+        )
+    }
+}
+
+/// Parses an ISO 4217 currency code like "EUR"
+pub fn parse_currency(env_var: &str, default: &str) -> String {
+    let value = parse_string(env_var, default);
+    if value.len() == 3 && value.chars().all(|c| c.is_ascii_uppercase()) {
+        value
+    } else {
+        panic!(
+            "Provided {} is not a valid currency code: {}",
+            env_var, value
+        )
+    }
+}
+
+/// Parses a comma separated list of ISO 639-1 language codes like `nl`
+pub fn parse_locales(env_var: &str, default: &str) -> Vec<String> {
+    let value = parse_string(env_var, default);
+    let locales: Vec<String> = value.split(',').map(|v| v.trim().to_string()).collect();
+    if locales
+        .iter()
+        .any(|l| l.len() != 2 || !l.chars().all(|c| c.is_ascii_lowercase()))
+    {
+        panic!(
+            "Provided {} is not a valid list of locales: {}",
+            env_var, value
+        )
+    }
+    locales
+}
+
 pub fn parse_string(env_var: &str, default: &str) -> String {
-    env::var(env_var).unwrap_or(default.to_string())
+    env::var(env_var).unwrap_or_else(|_| default.to_string())
 }
 
 pub fn parse_required_string(env_var: &str) -> String {
     let value = parse_string(env_var, "");
-    if value != "" {
+    if !value.is_empty() {
         value
     } else {
         panic!("Setting {} is mandatory and should not be empty", env_var)
     }
 }
 
+/// Parses a comma separated list of non-empty values
+pub fn parse_required_list(env_var: &str) -> Vec<String> {
+    parse_required_string(env_var)
+        .split(',')
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
 pub fn parse_required_pathbuf(env_var: &str) -> PathBuf {
     let path = parse_required_string(env_var);
     path.parse()
@@ -0,0 +1,356 @@
+use std::fmt;
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{allocate, validate_coupon_code, validate_coupon_type, CartLine, ValidationErrors};
+use crate::schema::coupons;
+
+/// Allowed values for the discount type of a coupon
+pub const COUPON_TYPES: [&str; 3] = ["PERCENTAGE", "FIXED", "FREE_SHIPPING"];
+
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+pub struct Coupon {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub version: i32,
+
+    /// Code entered by the customer, stored in uppercase
+    pub code: String,
+    pub discount_type: String,
+
+    /// Percentage for PERCENTAGE coupons, cents for FIXED coupons
+    pub discount_value: i32,
+
+    /// Minimum subtotal of the cart in cents
+    pub minimum_order_value: i32,
+
+    /// Restricts the discount to products in these categories or their
+    /// subcategories, or of these manufacturers
+    pub category_ids: Vec<Uuid>,
+    pub manufacturer_ids: Vec<Uuid>,
+
+    /// Maximum number of orders with the coupon, unlimited if not set
+    pub usage_limit: Option<i32>,
+
+    /// Maximum number of orders with the coupon per customer email
+    pub usage_limit_per_customer: Option<i32>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+}
+
+/// Number of orders in which a coupon was used
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CouponUsage {
+    pub total: i64,
+
+    /// Orders of the customer, if the customer is known
+    pub by_customer: Option<i64>,
+}
+
+/// Discount granted by a coupon on a cart
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CouponDiscount {
+    pub discount: i32,
+    pub free_shipping: bool,
+
+    /// Part of the discount per line of the cart, in proportion to the
+    /// totals of the eligible lines
+    pub per_line: Vec<i32>,
+}
+
+/// Reason a coupon can't be used on a cart
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CouponRejection {
+    NotStarted,
+    Expired,
+    MinimumOrderValue(i32),
+    NoEligibleProducts,
+    UsageLimitReached,
+    CustomerLimitReached,
+}
+
+impl fmt::Display for CouponRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CouponRejection::NotStarted => write!(f, "Coupon isn't valid yet"),
+            CouponRejection::Expired => write!(f, "Coupon has expired"),
+            CouponRejection::MinimumOrderValue(cents) => write!(
+                f,
+                "Order value should be at least {}.{:02}",
+                cents / 100,
+                cents % 100
+            ),
+            CouponRejection::NoEligibleProducts => {
+                write!(f, "None of the products in the cart qualify for the coupon")
+            }
+            CouponRejection::UsageLimitReached => write!(f, "Coupon has been used up"),
+            CouponRejection::CustomerLimitReached => {
+                write!(f, "Coupon has already been used by this customer")
+            }
+        }
+    }
+}
+
+impl Coupon {
+    /// Checks if the coupon can be used on the cart and calculates its discount
+    pub fn evaluate(
+        &self,
+        lines: &[CartLine],
+        now: NaiveDateTime,
+        usage: CouponUsage,
+    ) -> Result<CouponDiscount, CouponRejection> {
+        if self.starts_at.is_some_and(|at| at > now) {
+            return Err(CouponRejection::NotStarted);
+        }
+        if self.ends_at.is_some_and(|at| at <= now) {
+            return Err(CouponRejection::Expired);
+        }
+        if self
+            .usage_limit
+            .is_some_and(|limit| usage.total >= i64::from(limit))
+        {
+            return Err(CouponRejection::UsageLimitReached);
+        }
+        if let (Some(limit), Some(uses)) = (self.usage_limit_per_customer, usage.by_customer) {
+            if uses >= i64::from(limit) {
+                return Err(CouponRejection::CustomerLimitReached);
+            }
+        }
+        let subtotal: i64 = lines.iter().map(|l| i64::from(l.total)).sum();
+        if subtotal < i64::from(self.minimum_order_value) {
+            return Err(CouponRejection::MinimumOrderValue(self.minimum_order_value));
+        }
+
+        let weights: Vec<i32> = lines
+            .iter()
+            .map(|line| {
+                if self.is_eligible(line) {
+                    line.total
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let eligible: i64 = weights.iter().copied().map(i64::from).sum();
+        if eligible == 0 {
+            return Err(CouponRejection::NoEligibleProducts);
+        }
+        let discount = match self.discount_type.as_str() {
+            "PERCENTAGE" => (eligible * i64::from(self.discount_value) + 50) / 100,
+            "FIXED" => i64::from(self.discount_value).min(eligible),
+            _ => 0,
+        };
+        Ok(CouponDiscount {
+            discount: discount as i32,
+            free_shipping: self.discount_type == "FREE_SHIPPING",
+            per_line: allocate(discount as i32, &weights),
+        })
+    }
+
+    /// Checks if the restrictions of the coupon allow the product of the line
+    fn is_eligible(&self, line: &CartLine) -> bool {
+        if self.category_ids.is_empty() && self.manufacturer_ids.is_empty() {
+            return true;
+        }
+        self.category_ids
+            .iter()
+            .any(|id| line.category_ids.contains(id))
+            || line
+                .manufacturer_id
+                .is_some_and(|id| self.manufacturer_ids.contains(&id))
+    }
+}
+
+/// Normalizes a code entered by a customer or an admin
+pub fn normalize_coupon_code(code: &str) -> String {
+    code.trim().to_uppercase()
+}
+
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Validate)]
+#[table_name = "coupons"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CouponData {
+    #[validate(length(min = 3, max = 32), custom = "validate_coupon_code")]
+    pub code: String,
+    #[validate(custom = "validate_coupon_type")]
+    pub discount_type: String,
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub discount_value: i32,
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub minimum_order_value: i32,
+    #[serde(default)]
+    pub category_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub manufacturer_ids: Vec<Uuid>,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub usage_limit: Option<i32>,
+    #[serde(default)]
+    #[validate(range(min = 1))]
+    pub usage_limit_per_customer: Option<i32>,
+    #[serde(default)]
+    pub starts_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub ends_at: Option<NaiveDateTime>,
+}
+
+impl CouponData {
+    /// Validates the fields and the rules which involve multiple fields
+    pub fn check(&self) -> Result<(), ValidationErrors> {
+        let mut errors = match self.validate() {
+            Ok(()) => ValidationErrors::new(),
+            Err(errors) => errors.into(),
+        };
+        match self.discount_type.as_str() {
+            "PERCENTAGE" if !(1..=100).contains(&self.discount_value) => {
+                errors.add(
+                    "discount_value",
+                    "Should be between 1 and 100 for a percentage",
+                );
+            }
+            "FIXED" if self.discount_value < 1 => {
+                errors.add("discount_value", "Should be at least 1 for a fixed amount");
+            }
+            "FREE_SHIPPING" if self.discount_value != 0 => {
+                errors.add("discount_value", "Should be 0 for free shipping");
+            }
+            _ => (),
+        }
+        if let (Some(starts_at), Some(ends_at)) = (self.starts_at, self.ends_at) {
+            if ends_at <= starts_at {
+                errors.add("ends_at", "Should be after starts_at");
+            }
+        }
+        errors.into_result()
+    }
+}
+
+/// Code entered by a customer
+#[derive(Debug, Deserialize)]
+pub struct CouponCode {
+    pub code: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::models::TaxAmounts;
+
+    fn at(secs: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(secs, 0)
+    }
+
+    fn coupon(discount_type: &str, discount_value: i32) -> Coupon {
+        Coupon {
+            id: Uuid::nil(),
+            created_at: at(0),
+            updated_at: at(0),
+            version: 1,
+            code: "SPRING".to_string(),
+            discount_type: discount_type.to_string(),
+            discount_value,
+            minimum_order_value: 0,
+            category_ids: Vec::new(),
+            manufacturer_ids: Vec::new(),
+            usage_limit: None,
+            usage_limit_per_customer: None,
+            starts_at: None,
+            ends_at: None,
+        }
+    }
+
+    fn line(total: i32, category: u128) -> CartLine {
+        CartLine {
+            product_id: Uuid::from_u128(total as u128),
+            name: "Soap".to_string(),
+            slug: "soap".to_string(),
+            quantity: 1,
+            regular_price: total,
+            unit_price: total,
+            total,
+            tax_rate: 21,
+            tax_breakdown: TaxAmounts::default(),
+            manufacturer_id: None,
+            category_ids: [Uuid::from_u128(category)]
+                .iter()
+                .copied()
+                .collect::<HashSet<_>>(),
+        }
+    }
+
+    #[test]
+    fn test_coupon_discount() {
+        let lines = [line(1995, 1), line(1000, 2)];
+        let usage = CouponUsage::default();
+        let discount = |coupon: &Coupon| coupon.evaluate(&lines, at(100), usage);
+
+        assert_eq!(discount(&coupon("PERCENTAGE", 10)).unwrap().discount, 300);
+        assert_eq!(discount(&coupon("FIXED", 5000)).unwrap().discount, 2995);
+        let free_shipping = discount(&coupon("FREE_SHIPPING", 0)).unwrap();
+        assert_eq!(free_shipping.discount, 0);
+        assert!(free_shipping.free_shipping);
+
+        let mut restricted = coupon("PERCENTAGE", 10);
+        restricted.category_ids = vec![Uuid::from_u128(2)];
+        assert_eq!(discount(&restricted).unwrap().per_line, vec![0, 100]);
+        restricted.category_ids = vec![Uuid::from_u128(3)];
+        assert_eq!(
+            discount(&restricted),
+            Err(CouponRejection::NoEligibleProducts)
+        );
+    }
+
+    #[test]
+    fn test_coupon_rejection() {
+        let lines = [line(1995, 1)];
+        let usage = CouponUsage {
+            total: 5,
+            by_customer: Some(1),
+        };
+
+        let mut minimum = coupon("FIXED", 500);
+        minimum.minimum_order_value = 2500;
+        let rejection = minimum.evaluate(&lines, at(100), usage).unwrap_err();
+        assert_eq!(
+            rejection.to_string(),
+            "Order value should be at least 25.00"
+        );
+
+        let mut window = coupon("FIXED", 500);
+        window.starts_at = Some(at(50));
+        window.ends_at = Some(at(100));
+        assert_eq!(
+            window.evaluate(&lines, at(10), usage),
+            Err(CouponRejection::NotStarted)
+        );
+        assert_eq!(
+            window.evaluate(&lines, at(100), usage),
+            Err(CouponRejection::Expired)
+        );
+
+        let mut limited = coupon("FIXED", 500);
+        limited.usage_limit_per_customer = Some(1);
+        assert_eq!(
+            limited.evaluate(&lines, at(100), usage),
+            Err(CouponRejection::CustomerLimitReached)
+        );
+        let anonymous = CouponUsage {
+            by_customer: None,
+            ..usage
+        };
+        assert!(limited.evaluate(&lines, at(100), anonymous).is_ok());
+        limited.usage_limit = Some(5);
+        assert_eq!(
+            limited.evaluate(&lines, at(100), anonymous),
+            Err(CouponRejection::UsageLimitReached)
+        );
+    }
+}
@@ -0,0 +1,287 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{validate_currency_code, validate_rounding_mode, ProductPrice, ValidationErrors};
+use crate::schema::exchange_rates;
+use crate::spreadsheet::Table;
+
+/// Allowed values for the rounding of converted prices
+pub const ROUNDING_MODES: [&str; 3] = ["NEAREST", "UP", "DOWN"];
+
+/// Rate to convert prices in the base currency for display. Orders are always
+/// settled in the base currency.
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+pub struct ExchangeRate {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+
+    /// ISO 4217 code of the currency
+    pub currency: String,
+
+    /// Units of the currency per unit of the base currency
+    pub rate: f64,
+
+    /// Number of decimals of the currency, e.g. 0 for JPY
+    pub decimals: i16,
+    pub rounding: String,
+
+    /// Converted prices are rounded to a multiple of this amount in the
+    /// smallest unit of the currency, e.g. 5 to round to 0.05 CHF
+    pub rounding_increment: i32,
+}
+
+impl ExchangeRate {
+    /// Converts an amount in cents of the base currency to the smallest unit
+    /// of the currency
+    pub fn convert(&self, cents: i32) -> i64 {
+        let units = f64::from(cents) / 100.0 * self.rate * 10f64.powi(self.decimals.into());
+        // Drop the floating point error, so exact amounts aren't rounded up
+        let units = (units * 1e6).round() / 1e6;
+        let increment = f64::from(self.rounding_increment.max(1));
+        let steps = units / increment;
+        let steps = match self.rounding.as_str() {
+            "UP" => steps.ceil(),
+            "DOWN" => steps.floor(),
+            _ => steps.round(),
+        };
+        (steps * increment) as i64
+    }
+}
+
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Validate)]
+#[table_name = "exchange_rates"]
+pub struct ExchangeRateData {
+    /// Taken from the path
+    #[serde(skip_deserializing)]
+    #[validate(custom = "validate_currency_code")]
+    pub currency: String,
+    #[validate(range(min = 0.000001, message = "Should be above 0"))]
+    pub rate: f64,
+    #[serde(default = "default_decimals")]
+    #[validate(range(min = 0, max = 4))]
+    pub decimals: i16,
+    #[serde(default = "default_rounding")]
+    #[validate(custom = "validate_rounding_mode")]
+    pub rounding: String,
+    #[serde(default = "default_rounding_increment")]
+    #[validate(range(min = 1))]
+    pub rounding_increment: i32,
+}
+
+fn default_decimals() -> i16 {
+    2
+}
+
+fn default_rounding() -> String {
+    ROUNDING_MODES[0].to_string()
+}
+
+fn default_rounding_increment() -> i32 {
+    1
+}
+
+impl ExchangeRateData {
+    /// Validates the fields and rejects rates of the base currency
+    pub fn check(&self, base_currency: &str) -> Result<(), ValidationErrors> {
+        let mut errors = match self.validate() {
+            Ok(()) => ValidationErrors::new(),
+            Err(errors) => errors.into(),
+        };
+        if self.currency == base_currency {
+            errors.add("currency", "Is the base currency");
+        }
+        errors.into_result()
+    }
+
+    /// Reads the rates from a table with the columns `currency` and `rate`,
+    /// and optionally `decimals`, `rounding` and `rounding_increment`. Errors
+    /// are reported per row number.
+    pub fn from_table(table: &Table, base_currency: &str) -> Result<Vec<Self>, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let column = |name: &str| table.headers.iter().position(|h| h.trim() == name);
+        let (currency, rate) = match (column("currency"), column("rate")) {
+            (Some(currency), Some(rate)) => (currency, rate),
+            _ => {
+                errors.add("file", "Should have the columns currency and rate");
+                return Err(errors);
+            }
+        };
+        let optional = [
+            column("decimals"),
+            column("rounding"),
+            column("rounding_increment"),
+        ];
+
+        let mut rates = Vec::new();
+        for (i, row) in table.rows.iter().enumerate() {
+            let field = format!("row {}", Table::row_number(i));
+            let cell = |index: usize| row.get(index).map_or("", |c| c.trim());
+            let optional_cell = |index: Option<usize>| index.map(cell).filter(|c| !c.is_empty());
+            let mut data = Self {
+                currency: cell(currency).to_uppercase(),
+                rate: 0.0,
+                decimals: default_decimals(),
+                rounding: default_rounding(),
+                rounding_increment: default_rounding_increment(),
+            };
+            let mut messages = Vec::new();
+            match cell(rate).replace(',', ".").parse() {
+                Ok(rate) => data.rate = rate,
+                Err(_) => messages.push(format!("rate: Invalid number {}", cell(rate))),
+            }
+            if let Some(decimals) = optional_cell(optional[0]) {
+                match decimals.parse() {
+                    Ok(decimals) => data.decimals = decimals,
+                    Err(_) => messages.push(format!("decimals: Invalid number {}", decimals)),
+                }
+            }
+            if let Some(rounding) = optional_cell(optional[1]) {
+                data.rounding = rounding.to_uppercase();
+            }
+            if let Some(increment) = optional_cell(optional[2]) {
+                match increment.parse() {
+                    Ok(increment) => data.rounding_increment = increment,
+                    Err(_) => {
+                        messages.push(format!("rounding_increment: Invalid number {}", increment))
+                    }
+                }
+            }
+            // Numbers which can't be read aren't validated again
+            if messages.is_empty() {
+                if let Err(row_errors) = data.check(base_currency) {
+                    messages = row_errors.messages();
+                }
+            }
+            for message in messages {
+                errors.add(&field, message);
+            }
+            rates.push(data);
+        }
+        errors.into_result()?;
+        Ok(rates)
+    }
+}
+
+/// Currency requested by a visitor with the `currency` query parameter, which
+/// takes precedence over the `Accept-Currency` header
+#[derive(Debug, Deserialize)]
+pub struct CurrencyQuery {
+    pub currency: Option<String>,
+}
+
+/// Reads the currencies of an `Accept-Currency` header, most preferred first
+pub fn parse_accept_currency(header: &str) -> Vec<String> {
+    let mut currencies: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let currency = parts.next()?.trim().to_uppercase();
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            Some((currency, quality))
+        })
+        .filter(|(currency, quality)| !currency.is_empty() && currency != "*" && *quality > 0.0)
+        .collect();
+    // Stable, so currencies with the same quality keep their order
+    currencies.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    currencies
+        .into_iter()
+        .map(|(currency, _)| currency)
+        .collect()
+}
+
+/// Prices of a product converted to another currency, in the smallest unit of
+/// that currency
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConvertedPrice {
+    pub currency: String,
+    pub rate: f64,
+    pub decimals: i16,
+    pub regular_price: i64,
+    pub effective_price: i64,
+}
+
+impl ProductPrice {
+    /// Adds the prices converted at the exchange rate
+    pub fn convert(&mut self, rate: &ExchangeRate) {
+        self.converted_price = Some(ConvertedPrice {
+            currency: rate.currency.clone(),
+            rate: rate.rate,
+            decimals: rate.decimals,
+            regular_price: rate.convert(self.regular_price),
+            effective_price: rate.convert(self.effective_price),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(rate: f64, decimals: i16, rounding: &str, rounding_increment: i32) -> ExchangeRate {
+        ExchangeRate {
+            id: Uuid::nil(),
+            created_at: NaiveDateTime::from_timestamp(0, 0),
+            updated_at: NaiveDateTime::from_timestamp(0, 0),
+            currency: "USD".to_string(),
+            rate,
+            decimals,
+            rounding: rounding.to_string(),
+            rounding_increment,
+        }
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(rate(1.1, 2, "NEAREST", 1).convert(1000), 1100);
+        assert_eq!(rate(1.1, 2, "UP", 1).convert(1000), 1100);
+        assert_eq!(rate(1.0834, 2, "NEAREST", 1).convert(1995), 2161);
+        assert_eq!(rate(0.9612, 2, "UP", 5).convert(1995), 1920);
+        assert_eq!(rate(0.9612, 2, "DOWN", 100).convert(1995), 1900);
+        assert_eq!(rate(162.35, 0, "NEAREST", 10).convert(1995), 3240);
+    }
+
+    #[test]
+    fn test_parse_accept_currency() {
+        assert_eq!(parse_accept_currency("USD"), vec!["USD"]);
+        assert_eq!(
+            parse_accept_currency("usd, EUR;q=0.5, GBP;q=0.8"),
+            vec!["USD", "GBP", "EUR"]
+        );
+        assert_eq!(parse_accept_currency("*, CHF;q=0, JPY"), vec!["JPY"]);
+        assert!(parse_accept_currency(" , ").is_empty());
+    }
+
+    #[test]
+    fn test_from_table() {
+        let table = Table {
+            headers: vec!["currency".to_string(), "rate".to_string()],
+            rows: vec![
+                vec!["usd".to_string(), "1,0834".to_string()],
+                vec!["EUR".to_string(), "1".to_string()],
+                vec!["GBP".to_string(), "high".to_string()],
+            ],
+        };
+        let errors = ExchangeRateData::from_table(&table, "EUR").unwrap_err();
+        assert_eq!(
+            errors.messages(),
+            vec![
+                "row 3: currency: Is the base currency",
+                "row 4: rate: Invalid number high",
+            ]
+        );
+
+        let table = Table {
+            rows: table.rows[..1].to_vec(),
+            ..table
+        };
+        let rates = ExchangeRateData::from_table(&table, "EUR").unwrap();
+        assert_eq!(rates[0].currency, "USD");
+        assert_eq!(rates[0].rate, 1.0834);
+    }
+}
@@ -0,0 +1,76 @@
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::ValidationErrors;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMode {
+    Restrict,
+    Detach,
+    Reassign,
+}
+
+/// Query of a delete request of a category or manufacturer
+#[derive(Debug, Deserialize)]
+pub struct DeleteQuery {
+    pub mode: Option<DeleteMode>,
+    pub reassign_to: Option<Uuid>,
+}
+
+/// What happens to the entities which refer to a deleted entity
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeletePolicy {
+    /// Refuse to delete while the entity is referenced
+    Restrict,
+
+    /// Remove the references
+    Detach,
+
+    /// Refer to another entity instead
+    Reassign(Uuid),
+}
+
+impl DeleteQuery {
+    /// Returns the requested policy. Defaults to restrict, or to reassign if
+    /// `reassign_to` is provided.
+    pub fn policy(&self) -> Result<DeletePolicy, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let policy = match (self.mode, self.reassign_to) {
+            (None, None) | (Some(DeleteMode::Restrict), None) => DeletePolicy::Restrict,
+            (Some(DeleteMode::Detach), None) => DeletePolicy::Detach,
+            (None, Some(id)) | (Some(DeleteMode::Reassign), Some(id)) => DeletePolicy::Reassign(id),
+            (Some(DeleteMode::Reassign), None) => {
+                errors.add("reassign_to", "Required to reassign");
+                return Err(errors);
+            }
+            (Some(_), Some(_)) => {
+                errors.add("reassign_to", "Only allowed to reassign");
+                return Err(errors);
+            }
+        };
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(mode: Option<DeleteMode>, reassign_to: Option<Uuid>) -> Option<DeletePolicy> {
+        DeleteQuery { mode, reassign_to }.policy().ok()
+    }
+
+    #[test]
+    fn test_delete_policy() {
+        let id = Uuid::nil();
+        assert_eq!(policy(None, None), Some(DeletePolicy::Restrict));
+        assert_eq!(
+            policy(Some(DeleteMode::Detach), None),
+            Some(DeletePolicy::Detach)
+        );
+        assert_eq!(policy(None, Some(id)), Some(DeletePolicy::Reassign(id)));
+        assert_eq!(policy(Some(DeleteMode::Reassign), None), None);
+        assert_eq!(policy(Some(DeleteMode::Detach), Some(id)), None);
+    }
+}
@@ -0,0 +1,94 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Xlsx,
+}
+
+impl ExportFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Xlsx => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Xlsx => "xlsx",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+}
+
+/// Product with the names of its manufacturer and categories
+#[derive(Debug, Serialize)]
+pub struct ExportedProduct {
+    pub id: Uuid,
+    pub sku: Option<String>,
+    pub name: String,
+    pub slug: String,
+    pub status: String,
+
+    /// Price in cents
+    pub price: i32,
+
+    /// VAT rate in percent
+    pub tax_rate: i16,
+    pub stock_count: i32,
+    pub manufacturer: Option<String>,
+    pub categories: Vec<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl ExportedProduct {
+    /// Column headers of tabular exports, in the order of `to_record`
+    pub const HEADERS: [&'static str; 12] = [
+        "id",
+        "sku",
+        "name",
+        "slug",
+        "status",
+        "price",
+        "tax_rate",
+        "stock_count",
+        "manufacturer",
+        "categories",
+        "created_at",
+        "updated_at",
+    ];
+
+    /// Returns the values for tabular exports. Prices are formatted in whole
+    /// currency units and categories are separated by "|".
+    pub fn to_record(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.sku.clone().unwrap_or_default(),
+            self.name.clone(),
+            self.slug.clone(),
+            self.status.clone(),
+            format!("{}.{:02}", self.price / 100, self.price % 100),
+            self.tax_rate.to_string(),
+            self.stock_count.to_string(),
+            self.manufacturer.clone().unwrap_or_default(),
+            self.categories.join("|"),
+            self.created_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+            self.updated_at.format("%Y-%m-%d %H:%M:%S").to_string(),
+        ]
+    }
+}
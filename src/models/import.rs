@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{ProductPatch, ValidationErrors};
+
+/// Product fields which can be read from an import file
+pub const IMPORT_FIELDS: [&str; 11] = [
+    "sku",
+    "slug",
+    "name",
+    "description_short",
+    "description_long",
+    "price",
+    "tax_rate",
+    "status",
+    "stock_count",
+    "manufacturer",
+    "categories",
+];
+
+/// Field used to find the existing product of a row
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMatch {
+    #[default]
+    Sku,
+    Slug,
+}
+
+impl ImportMatch {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ImportMatch::Sku => "sku",
+            ImportMatch::Slug => "slug",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportOptions {
+    /// Column header per product field. Fields which aren't mapped are read
+    /// from the column with the same name as the field, if any.
+    #[serde(default)]
+    pub mapping: HashMap<String, String>,
+
+    #[serde(default)]
+    pub match_by: ImportMatch,
+
+    /// Creates manufacturers and categories which don't exist yet
+    #[serde(default)]
+    pub create_missing: bool,
+
+    /// Rolls back all changes after building the report
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Delimiter of CSV files
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+
+    /// Separator of the category names in a single cell
+    #[serde(default = "default_category_separator")]
+    pub category_separator: String,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_category_separator() -> String {
+    "|".to_string()
+}
+
+impl Default for ImportOptions {
+    fn default() -> Self {
+        Self {
+            mapping: HashMap::new(),
+            match_by: ImportMatch::default(),
+            create_missing: false,
+            dry_run: false,
+            delimiter: default_delimiter(),
+            category_separator: default_category_separator(),
+        }
+    }
+}
+
+impl ImportOptions {
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        for field in self.mapping.keys() {
+            if !IMPORT_FIELDS.contains(&field.as_str()) {
+                errors.add("mapping", format!("Unknown field {}", field));
+            }
+        }
+        if !self.delimiter.is_ascii() {
+            errors.add("delimiter", "Should be an ASCII character");
+        }
+        if self.category_separator.is_empty() {
+            errors.add("category_separator", "Should not be empty");
+        }
+        errors.into_result()
+    }
+
+    /// Resolves the column index of each field based on the headers of the file
+    pub fn columns(&self, headers: &[String]) -> Result<ImportColumns, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let mut columns = HashMap::new();
+        for field in IMPORT_FIELDS.iter() {
+            let header = self.mapping.get(*field).map_or(*field, String::as_str);
+            match headers.iter().position(|h| h.eq_ignore_ascii_case(header)) {
+                Some(index) => {
+                    columns.insert(*field, index);
+                }
+                None if self.mapping.contains_key(*field) => {
+                    errors.add("mapping", format!("Column {} doesn't exist", header));
+                }
+                None => (),
+            }
+        }
+
+        let key = self.match_by.as_str();
+        if !columns.contains_key(key) {
+            errors.add("match_by", format!("No column is mapped to {}", key));
+        }
+        errors.into_result()?;
+        Ok(ImportColumns {
+            columns,
+            category_separator: self.category_separator.clone(),
+        })
+    }
+}
+
+/// Column index per field of an import file
+#[derive(Debug)]
+pub struct ImportColumns {
+    columns: HashMap<&'static str, usize>,
+    category_separator: String,
+}
+
+impl ImportColumns {
+    /// Parses a row of the file. Empty cells leave the field untouched.
+    pub fn parse(&self, row: &[String]) -> Result<ImportRow, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let cell = |field| {
+            self.columns
+                .get(field)
+                .and_then(|i| row.get(*i))
+                .filter(|v| !v.is_empty())
+                .cloned()
+        };
+        let mut number = |field, parse: fn(&str) -> Option<i32>| {
+            let value = cell(field)?;
+            let parsed = parse(&value);
+            if parsed.is_none() {
+                errors.add(field, format!("Invalid number {}", value));
+            }
+            parsed
+        };
+
+        let price = number("price", parse_price);
+        let stock_count = number("stock_count", |v| v.parse().ok());
+        // Accepts percentages like "21%", parsed as i16 so the cast is lossless
+        let tax_rate = number("tax_rate", |v| {
+            let rate = v.trim_end_matches('%').trim().parse::<i16>().ok()?;
+            Some(i32::from(rate))
+        });
+        let product = ProductPatch {
+            name: cell("name"),
+            slug: None,
+            description_short: cell("description_short"),
+            description_long: cell("description_long"),
+            price,
+            manufacturer_id: None,
+            status: cell("status").map(|s| s.to_uppercase()),
+            stock_count,
+            sku: cell("sku").map(Some),
+            tax_rate: tax_rate.map(|rate| rate as i16),
+            ..ProductPatch::default()
+        };
+        if let Err(e) = product.validate() {
+            errors.extend(e.into());
+        }
+
+        let categories = cell("categories").map(|names| {
+            names
+                .split(self.category_separator.as_str())
+                .map(str::trim)
+                .filter(|n| !n.is_empty())
+                .map(str::to_string)
+                .collect()
+        });
+        let row = ImportRow {
+            slug: cell("slug"),
+            product,
+            manufacturer: cell("manufacturer"),
+            categories,
+        };
+        errors.into_result().map(|_| row)
+    }
+}
+
+/// Parses a price in whole currency units like "12.95" or "12,95" into cents
+pub fn parse_price(value: &str) -> Option<i32> {
+    let value = value
+        .trim()
+        .trim_start_matches('€')
+        .trim()
+        .replace(',', ".");
+    let price = value.parse::<f64>().ok()?;
+    let cents = (price * 100.0).round();
+    if cents.is_finite() && cents >= 0.0 && cents <= f64::from(i32::MAX) {
+        Some(cents as i32)
+    } else {
+        None
+    }
+}
+
+/// Values of a single row in an import file
+#[derive(Debug)]
+pub struct ImportRow {
+    /// Only used to find the existing product, slugs are always generated
+    pub slug: Option<String>,
+
+    /// Values of the product, `sku` is set if provided
+    pub product: ProductPatch,
+
+    /// Name of the manufacturer
+    pub manufacturer: Option<String>,
+
+    /// Names of the categories, replacing the current categories
+    pub categories: Option<Vec<String>>,
+}
+
+/// Outcome of an import. Changes are only applied if all rows succeed.
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub applied: bool,
+    pub created: usize,
+    pub updated: usize,
+    pub created_manufacturers: Vec<String>,
+    pub created_categories: Vec<String>,
+    pub rows: Vec<ImportRowResult>,
+}
+
+impl ImportReport {
+    pub fn has_errors(&self) -> bool {
+        self.rows.iter().any(|r| r.errors.is_some())
+    }
+}
+
+/// Result of importing a single row
+#[derive(Debug, Serialize)]
+pub struct ImportRowResult {
+    /// Row number in the file, the header is on row 1
+    pub row: usize,
+
+    /// Either "create" or "update"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<&'static str>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub product_id: Option<Uuid>,
+
+    /// Changed fields as `{"field": {"old": ..., "new": ...}}`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Value>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<ValidationErrors>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_price() {
+        assert_eq!(parse_price("12.95"), Some(1295));
+        assert_eq!(parse_price("€ 3,5"), Some(350));
+        assert_eq!(parse_price("7"), Some(700));
+        assert_eq!(parse_price("-1"), None);
+        assert_eq!(parse_price("free"), None);
+    }
+
+    #[test]
+    fn test_parse_row() {
+        let mut options = ImportOptions::default();
+        options
+            .mapping
+            .insert("sku".to_string(), "Artikel".to_string());
+        options
+            .mapping
+            .insert("price".to_string(), "Prijs".to_string());
+        let headers = strings(&["Artikel", "name", "Prijs", "categories", "tax_rate"]);
+        let columns = options.columns(&headers).unwrap();
+
+        let row = columns
+            .parse(&strings(&["A1", "Zeep", "3,50", "Bad | Verzorging", "6%"]))
+            .unwrap();
+        assert_eq!(row.product.sku, Some(Some("A1".to_string())));
+        assert_eq!(row.product.name, Some("Zeep".to_string()));
+        assert_eq!(row.product.price, Some(350));
+        assert_eq!(row.product.stock_count, None);
+        assert_eq!(row.product.tax_rate, Some(6));
+        assert_eq!(row.categories, Some(strings(&["Bad", "Verzorging"])));
+
+        let errors = columns.parse(&strings(&["A2", "", "gratis", ""]));
+        assert!(errors.is_err());
+        let errors = columns.parse(&strings(&["A3", "", "", "", "7"]));
+        assert!(errors.is_err());
+
+        options.match_by = ImportMatch::Slug;
+        assert!(options.columns(&headers).is_err());
+    }
+}
@@ -1,5 +1,10 @@
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
+use validator::Validate;
+
+use super::{validate_not_blank, validate_website_url, MergePatch, ValidationErrors, Versioned};
 
 use crate::schema::manufacturers;
 
@@ -9,14 +14,61 @@ pub struct Manufacturer {
     pub name: String,
     pub slug: String,
     pub website_url: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub version: i32,
+
+    /// Moment the entity was moved to the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
 }
 
-#[derive(Debug, Insertable, AsChangeset, Deserialize)]
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Validate)]
 #[table_name = "manufacturers"]
 pub struct ManufacturerData {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
     pub name: String,
+    #[validate(length(max = 500), custom = "validate_website_url")]
     pub website_url: String,
 
     #[serde(skip_deserializing)]
     pub slug: String,
 }
+
+/// Partial update of a manufacturer. Only the provided columns are updated.
+#[derive(Debug, Default, AsChangeset, Validate)]
+#[table_name = "manufacturers"]
+pub struct ManufacturerPatch {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
+    pub name: Option<String>,
+    #[validate(length(max = 500), custom = "validate_website_url")]
+    pub website_url: Option<String>,
+    pub slug: Option<String>,
+}
+
+impl ManufacturerPatch {
+    /// Parses and validates a JSON Merge Patch of a manufacturer
+    pub fn from_merge_patch(patch: &Value) -> Result<Versioned<Self>, ValidationErrors> {
+        let mut reader = MergePatch::new(patch)?;
+        let manufacturer = Self {
+            name: reader.field("name"),
+            website_url: reader.field("website_url"),
+            slug: None,
+        };
+        let version = reader.field("version");
+
+        if let Err(errors) = manufacturer.validate() {
+            reader.errors(errors.into());
+        }
+
+        reader.finish()?;
+        Ok(Versioned {
+            data: manufacturer,
+            version,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.website_url.is_none() && self.slug.is_none()
+    }
+}
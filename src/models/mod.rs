@@ -1,6 +1,36 @@
+// Derives of diesel 1.x predate this lint
+#![allow(non_local_definitions)]
+
+mod api_key;
+mod audit;
+mod bulk;
+mod cart;
 mod category;
+mod category_tree;
 mod config;
+mod coupon;
+mod currency;
+mod delete;
+mod export;
+mod import;
 mod manufacturer;
+mod order;
+mod page;
+mod patch;
+mod pricing;
 mod product;
+mod promotion;
+mod revision;
+mod sitemap;
+mod tax;
+mod translation;
+mod trash;
+mod validation;
+mod versioned;
 
-pub use self::{category::*, config::*, manufacturer::*, product::*};
+pub use self::{
+    api_key::*, audit::*, bulk::*, cart::*, category::*, category_tree::*, config::*, coupon::*,
+    currency::*, delete::*, export::*, import::*, manufacturer::*, order::*, page::*, patch::*,
+    pricing::*, product::*, promotion::*, revision::*, sitemap::*, tax::*, translation::*,
+    trash::*, validation::*, versioned::*,
+};
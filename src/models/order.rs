@@ -0,0 +1,86 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::OrderTax;
+use crate::schema::{order_items, orders};
+
+#[derive(Debug, Identifiable, Queryable, Serialize)]
+pub struct Order {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub customer_email: String,
+    pub coupon_id: Option<Uuid>,
+    pub coupon_code: Option<String>,
+    pub subtotal: i32,
+    pub discount: i32,
+    pub shipping: i32,
+
+    /// Amount paid including tax
+    pub total: i32,
+
+    /// Whether the subtotal, discount and shipping include tax
+    pub prices_include_tax: bool,
+    pub net: i32,
+    pub tax: i32,
+
+    /// Base currency in which the order is settled
+    pub currency: String,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "orders"]
+pub struct OrderData {
+    pub customer_email: String,
+    pub coupon_id: Option<Uuid>,
+    pub coupon_code: Option<String>,
+    pub subtotal: i32,
+    pub discount: i32,
+    pub shipping: i32,
+    pub total: i32,
+    pub prices_include_tax: bool,
+    pub net: i32,
+    pub tax: i32,
+    pub currency: String,
+}
+
+/// Product in an order, with the name and price at the moment of the order
+#[derive(Debug, Associations, Identifiable, Queryable, Insertable, Serialize)]
+#[belongs_to(Order)]
+#[primary_key(order_id, line)]
+#[table_name = "order_items"]
+pub struct OrderItem {
+    #[serde(skip)]
+    pub order_id: Uuid,
+    pub line: i32,
+
+    /// Not set if the product has been deleted permanently
+    pub product_id: Option<Uuid>,
+    pub name: String,
+    pub quantity: i32,
+    pub unit_price: i32,
+    pub total: i32,
+
+    /// VAT rate in percent, the net and tax amounts are those of the total
+    /// before the discount
+    pub tax_rate: i16,
+    pub net: i32,
+    pub tax: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderWithItems {
+    #[serde(flatten)]
+    pub order: Order,
+    pub items: Vec<OrderItem>,
+
+    /// Amounts per tax rate after the discount
+    pub taxes: Vec<OrderTax>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct CheckoutData {
+    #[validate(email(message = "Should be a valid email address"))]
+    pub customer_email: String,
+}
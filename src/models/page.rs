@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+
+use super::ProductWithMeta;
+
+/// Default number of items on a page
+pub const DEFAULT_PAGE_SIZE: i64 = 20;
+
+/// Max number of items on a page
+pub const MAX_PAGE_SIZE: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PageRequest {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+impl PageRequest {
+    pub fn new(limit: Option<i64>, offset: Option<i64>) -> Self {
+        Self {
+            limit: limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE),
+            offset: offset.unwrap_or(0).max(0),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+
+    /// Number of items on all pages
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+/// Query of a lookup which can embed the visible products of the entity
+#[derive(Debug, Deserialize)]
+pub struct EmbedProductsQuery {
+    #[serde(default)]
+    pub products: bool,
+
+    /// Includes the products of subcategories, only used for categories
+    #[serde(default)]
+    pub descendants: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+impl EmbedProductsQuery {
+    /// Returns the requested page of products, if products should be embedded
+    pub fn page(&self) -> Option<PageRequest> {
+        Some(PageRequest::new(self.limit, self.offset)).filter(|_| self.products)
+    }
+}
+
+/// Query of the products of a category
+#[derive(Debug, Deserialize)]
+pub struct CategoryProductsQuery {
+    /// Includes the products of subcategories
+    #[serde(default)]
+    pub descendants: bool,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Entity with a page of its visible products, if requested
+#[derive(Debug, Serialize)]
+pub struct WithProducts<T> {
+    #[serde(flatten)]
+    pub entity: T,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub products: Option<Page<ProductWithMeta>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_request() {
+        assert_eq!(
+            PageRequest::new(None, None),
+            PageRequest::new(Some(20), Some(0))
+        );
+        assert_eq!(PageRequest::new(Some(1000), Some(-5)).limit, MAX_PAGE_SIZE);
+        assert_eq!(
+            PageRequest::new(Some(0), Some(-5)),
+            PageRequest::new(Some(1), None)
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::HashSet;
+
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+
+use super::ValidationErrors;
+
+/// Reader for a JSON Merge Patch (RFC 7396) document. Absent fields are left
+/// untouched and `null` removes the value, which is only allowed for nullable
+/// fields. Type errors, invalid nulls and unknown fields are collected per field.
+pub struct MergePatch<'a> {
+    fields: &'a Map<String, Value>,
+    known: HashSet<&'static str>,
+    errors: ValidationErrors,
+}
+
+impl<'a> MergePatch<'a> {
+    pub fn new(patch: &'a Value) -> Result<Self, ValidationErrors> {
+        match patch.as_object() {
+            Some(fields) => Ok(Self {
+                fields,
+                known: HashSet::new(),
+                errors: ValidationErrors::new(),
+            }),
+            None => {
+                let mut errors = ValidationErrors::new();
+                errors.add("", "Patch should be a JSON object");
+                Err(errors)
+            }
+        }
+    }
+
+    /// Reads a field which can't be null
+    pub fn field<T: DeserializeOwned>(&mut self, name: &'static str) -> Option<T> {
+        match self.nullable_field(name) {
+            Some(Some(value)) => Some(value),
+            Some(None) => {
+                self.errors.add(name, "Field can't be null");
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Reads a nullable field. Returns `Some(None)` if the field is set to null.
+    pub fn nullable_field<T: DeserializeOwned>(&mut self, name: &'static str) -> Option<Option<T>> {
+        self.known.insert(name);
+        match self.fields.get(name)? {
+            Value::Null => Some(None),
+            value => match serde_json::from_value(value.clone()) {
+                Ok(value) => Some(Some(value)),
+                Err(e) => {
+                    self.errors.add(name, e.to_string());
+                    None
+                }
+            },
+        }
+    }
+
+    /// Adds validation errors of the read values
+    pub fn errors(&mut self, errors: ValidationErrors) {
+        self.errors.extend(errors);
+    }
+
+    /// Returns the collected errors, including fields which were never read
+    pub fn finish(mut self) -> Result<(), ValidationErrors> {
+        for name in self.fields.keys() {
+            if !self.known.contains(name.as_str()) {
+                self.errors.add(name, "Unknown or read-only field");
+            }
+        }
+        self.errors.into_result()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_patch() {
+        let patch = json!({"a": 1, "b": null, "c": null, "d": "x", "e": true});
+        let mut reader = MergePatch::new(&patch).unwrap();
+        assert_eq!(reader.field::<i32>("a"), Some(1));
+        assert_eq!(reader.nullable_field::<i32>("b"), Some(None));
+        assert_eq!(reader.field::<i32>("c"), None);
+        assert_eq!(reader.field::<i32>("d"), None);
+        assert_eq!(reader.field::<i32>("missing"), None);
+
+        let errors = serde_json::to_value(reader.finish().unwrap_err()).unwrap();
+        let fields: Vec<_> = errors.as_object().unwrap().keys().cloned().collect();
+        assert_eq!(fields, vec!["c", "d", "e"]);
+    }
+}
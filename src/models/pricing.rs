@@ -0,0 +1,254 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{ConvertedPrice, Product, Promotion, TaxAmounts};
+
+/// Prices of a product after its sale and the running promotions
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProductPrice {
+    pub regular_price: i32,
+
+    /// Lowest of the regular price, the sale price and the promotion prices
+    pub effective_price: i32,
+
+    /// Promotion which determines the effective price, if any
+    pub promotion: Option<AppliedPromotion>,
+
+    /// Moment the effective price ends, if it's temporary
+    pub effective_until: Option<NaiveDateTime>,
+
+    /// Effective price split into net, tax and gross at the tax rate of the
+    /// product
+    pub tax_breakdown: TaxAmounts,
+
+    /// Prices in the currency requested by the visitor, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub converted_price: Option<ConvertedPrice>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AppliedPromotion {
+    pub id: Uuid,
+    pub name: String,
+    pub discount_type: String,
+    pub discount_value: i32,
+}
+
+/// Sales and promotions at a moment, used to price listings, carts and feeds
+/// the same way
+#[derive(Debug)]
+pub struct PriceRules {
+    now: NaiveDateTime,
+    promotions: Vec<Promotion>,
+
+    /// Parent of each category, as promotions apply to subcategories too
+    parents: HashMap<Uuid, Option<Uuid>>,
+
+    /// Whether the prices of products include tax
+    prices_include_tax: bool,
+}
+
+impl PriceRules {
+    /// Keeps the promotions which run at the provided moment
+    pub fn new(
+        now: NaiveDateTime,
+        promotions: Vec<Promotion>,
+        parents: HashMap<Uuid, Option<Uuid>>,
+        prices_include_tax: bool,
+    ) -> Self {
+        let promotions = promotions
+            .into_iter()
+            .filter(|p| p.is_active(now))
+            .collect();
+        Self {
+            now,
+            promotions,
+            parents,
+            prices_include_tax,
+        }
+    }
+
+    /// Returns the prices of the product, which is in the provided categories
+    pub fn price(&self, product: &Product, category_ids: &[Uuid]) -> ProductPrice {
+        let mut price = ProductPrice {
+            regular_price: product.price,
+            effective_price: product.price,
+            promotion: None,
+            effective_until: None,
+            tax_breakdown: TaxAmounts::default(),
+            converted_price: None,
+        };
+        let on_sale = product.sale_starts_at.is_none_or(|at| at <= self.now)
+            && product.sale_ends_at.is_none_or(|at| at > self.now);
+        if let Some(sale_price) = product.sale_price.filter(|_| on_sale) {
+            if sale_price < price.effective_price {
+                price.effective_price = sale_price;
+                price.effective_until = product.sale_ends_at;
+            }
+        }
+
+        let categories = self.with_ancestors(category_ids);
+        let promotions = self.promotions.iter().filter(|promotion| {
+            promotion.product_ids.contains(&product.id)
+                || promotion
+                    .manufacturer_id
+                    .is_some_and(|id| product.manufacturer_id == Some(id))
+                || promotion
+                    .category_id
+                    .is_some_and(|id| categories.contains(&id))
+        });
+        for promotion in promotions {
+            let promotion_price = promotion.apply(product.price);
+            if promotion_price < price.effective_price {
+                price.effective_price = promotion_price;
+                price.effective_until = promotion.ends_at;
+                price.promotion = Some(AppliedPromotion {
+                    id: promotion.id,
+                    name: promotion.name.clone(),
+                    discount_type: promotion.discount_type.clone(),
+                    discount_value: promotion.discount_value,
+                });
+            }
+        }
+        price.tax_breakdown = TaxAmounts::new(
+            price.effective_price,
+            product.tax_rate,
+            self.prices_include_tax,
+        );
+        price
+    }
+
+    pub fn prices_include_tax(&self) -> bool {
+        self.prices_include_tax
+    }
+
+    /// Returns the categories and all categories above them
+    pub fn with_ancestors(&self, category_ids: &[Uuid]) -> HashSet<Uuid> {
+        let mut ids = HashSet::new();
+        for &id in category_ids {
+            let mut current = Some(id);
+            while let Some(id) = current {
+                if !ids.insert(id) {
+                    break;
+                }
+                current = self.parents.get(&id).copied().flatten();
+            }
+        }
+        ids
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(secs: i64) -> NaiveDateTime {
+        NaiveDateTime::from_timestamp(secs, 0)
+    }
+
+    fn product(price: i32) -> Product {
+        Product {
+            id: Uuid::from_u128(1),
+            created_at: at(0),
+            updated_at: at(0),
+            name: "Soap".to_string(),
+            slug: "soap".to_string(),
+            description_short: String::new(),
+            description_long: String::new(),
+            price,
+            manufacturer_id: Some(Uuid::from_u128(20)),
+            status: "AVAILABLE".to_string(),
+            stock_count: 0,
+            version: 1,
+            sku: None,
+            meta_title: None,
+            meta_description: None,
+            canonical_url: None,
+            noindex: false,
+            deleted_at: None,
+            publish_at: None,
+            unpublish_at: None,
+            sale_price: None,
+            sale_starts_at: None,
+            sale_ends_at: None,
+            tax_rate: 21,
+        }
+    }
+
+    fn promotion(n: u128, discount_type: &str, discount_value: i32) -> Promotion {
+        Promotion {
+            id: Uuid::from_u128(n),
+            created_at: at(0),
+            updated_at: at(0),
+            version: 1,
+            name: format!("Promotion {}", n),
+            discount_type: discount_type.to_string(),
+            discount_value,
+            category_id: None,
+            manufacturer_id: None,
+            product_ids: Vec::new(),
+            starts_at: None,
+            ends_at: None,
+        }
+    }
+
+    #[test]
+    fn test_promotion_apply() {
+        assert_eq!(promotion(1, "PERCENTAGE", 15).apply(1995), 1696);
+        assert_eq!(promotion(1, "PERCENTAGE", 100).apply(1995), 0);
+        assert_eq!(promotion(1, "FIXED", 500).apply(1995), 1495);
+        assert_eq!(promotion(1, "FIXED", 5000).apply(1995), 0);
+    }
+
+    #[test]
+    fn test_sale_price() {
+        let mut soap = product(1000);
+        soap.sale_price = Some(800);
+        soap.sale_starts_at = Some(at(100));
+        soap.sale_ends_at = Some(at(200));
+        let price =
+            |now| PriceRules::new(at(now), Vec::new(), HashMap::new(), true).price(&soap, &[]);
+
+        assert_eq!(price(50).effective_price, 1000);
+        assert_eq!(price(100).effective_price, 800);
+        assert_eq!(price(100).effective_until, Some(at(200)));
+        assert_eq!(price(200).effective_price, 1000);
+    }
+
+    #[test]
+    fn test_best_promotion() {
+        // Category 11 is a subcategory of 10
+        let parents = [
+            (Uuid::from_u128(10), None),
+            (Uuid::from_u128(11), Some(Uuid::from_u128(10))),
+        ];
+        let mut by_category = promotion(2, "PERCENTAGE", 25);
+        by_category.category_id = Some(Uuid::from_u128(10));
+        let mut by_manufacturer = promotion(3, "FIXED", 100);
+        by_manufacturer.manufacturer_id = Some(Uuid::from_u128(20));
+        let mut expired = promotion(4, "PERCENTAGE", 90);
+        expired.product_ids = vec![Uuid::from_u128(1)];
+        expired.ends_at = Some(at(10));
+        let rules = PriceRules::new(
+            at(100),
+            vec![by_category, by_manufacturer, expired],
+            parents.iter().copied().collect(),
+            false,
+        );
+
+        let mut soap = product(1000);
+        soap.sale_price = Some(800);
+        let price = rules.price(&soap, &[Uuid::from_u128(11)]);
+        assert_eq!(price.regular_price, 1000);
+        assert_eq!(price.effective_price, 750);
+        assert_eq!(price.promotion.map(|p| p.id), Some(Uuid::from_u128(2)));
+        assert_eq!(price.tax_breakdown.gross, 908);
+
+        let price = rules.price(&soap, &[]);
+        assert_eq!(price.effective_price, 800);
+        assert!(price.promotion.is_none());
+    }
+}
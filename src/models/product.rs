@@ -1,9 +1,18 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use uuid::Uuid;
+use validator::Validate;
 
+use super::{
+    validate_not_blank, validate_product_status, validate_slug, validate_tax_rate,
+    validate_website_url, MergePatch, ProductPrice, ValidationErrors, Versioned, TAX_RATES,
+};
 use crate::schema::products;
 
+/// Allowed values for the status of a product
+pub const PRODUCT_STATUSES: [&str; 2] = ["AVAILABLE", "ARCHIVED"];
+
 #[derive(Debug, Identifiable, Queryable, Serialize)]
 pub struct Product {
     pub id: Uuid,
@@ -17,6 +26,37 @@ pub struct Product {
     pub manufacturer_id: Option<Uuid>,
     pub status: String,
     pub stock_count: i32,
+    pub version: i32,
+    pub sku: Option<String>,
+    pub meta_title: Option<String>,
+    pub meta_description: Option<String>,
+    pub canonical_url: Option<String>,
+    pub noindex: bool,
+
+    /// Moment the entity was moved to the trash
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<NaiveDateTime>,
+
+    /// Start of the period in which the product is shown to visitors
+    pub publish_at: Option<NaiveDateTime>,
+
+    /// End of the period in which the product is shown to visitors
+    pub unpublish_at: Option<NaiveDateTime>,
+
+    /// Reduced price in cents, used between the sale start and end
+    pub sale_price: Option<i32>,
+    pub sale_starts_at: Option<NaiveDateTime>,
+    pub sale_ends_at: Option<NaiveDateTime>,
+
+    /// VAT rate in percent
+    pub tax_rate: i16,
+}
+
+impl Product {
+    /// Checks if the product is shown to visitors at the provided moment
+    pub fn is_published(&self, now: NaiveDateTime) -> bool {
+        self.publish_at.is_none_or(|at| at <= now) && self.unpublish_at.is_none_or(|at| at > now)
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -24,25 +64,205 @@ pub struct ProductWithMeta {
     #[serde(flatten)]
     pub product: Product,
     pub category_ids: Vec<Uuid>,
+
+    /// Prices after sales and promotions, only set when reading products
+    #[serde(flatten)]
+    pub pricing: Option<ProductPrice>,
 }
 
-#[derive(Debug, Insertable, AsChangeset, Deserialize)]
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Serialize, Validate)]
 #[table_name = "products"]
 #[changeset_options(treat_none_as_null = "true")]
 pub struct ProductData {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
     pub name: String,
+
+    /// Custom slug. Generated from the name if empty or if it matches the
+    /// slug generated from the current name.
+    #[serde(default)]
+    #[validate(length(max = 100), custom = "validate_slug")]
     pub slug: String,
+    #[validate(length(max = 1000))]
     pub description_short: String,
+    #[validate(length(max = 20000))]
     pub description_long: String,
+    #[validate(range(min = 0))]
     pub price: i32,
     pub manufacturer_id: Option<Uuid>,
+    #[validate(custom = "validate_product_status")]
     pub status: String,
+    #[validate(range(min = 0))]
     pub stock_count: i32,
+
+    /// Stock keeping unit, unique if set
+    #[serde(default)]
+    #[validate(length(min = 1, max = 64))]
+    pub sku: Option<String>,
+
+    /// Title for search engines, the name is used if not set
+    #[serde(default)]
+    #[validate(length(min = 1, max = 200))]
+    pub meta_title: Option<String>,
+    #[serde(default)]
+    #[validate(length(min = 1, max = 500))]
+    pub meta_description: Option<String>,
+
+    /// URL of the preferred page if this product is a duplicate
+    #[serde(default)]
+    #[validate(length(min = 1, max = 500), custom = "validate_website_url")]
+    pub canonical_url: Option<String>,
+
+    /// Hides the product from search engines and the sitemap
+    #[serde(default)]
+    pub noindex: bool,
+
+    /// Moment the product becomes visible. The status is set to AVAILABLE at this moment.
+    #[serde(default)]
+    pub publish_at: Option<NaiveDateTime>,
+
+    /// Moment the product is hidden again. The status is set to ARCHIVED at this moment.
+    #[serde(default)]
+    pub unpublish_at: Option<NaiveDateTime>,
+
+    /// Reduced price in cents, should be below the price
+    #[serde(default)]
+    #[validate(range(min = 0))]
+    pub sale_price: Option<i32>,
+
+    /// Start of the sale, the sale starts immediately if not set
+    #[serde(default)]
+    pub sale_starts_at: Option<NaiveDateTime>,
+
+    /// End of the sale, the sale doesn't end if not set
+    #[serde(default)]
+    pub sale_ends_at: Option<NaiveDateTime>,
+
+    /// VAT rate in percent: 21, 12, 6 or 0
+    #[serde(default = "default_tax_rate")]
+    #[validate(custom = "validate_tax_rate")]
+    pub tax_rate: i16,
+}
+
+fn default_tax_rate() -> i16 {
+    TAX_RATES[0]
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct ProductDataWithMeta {
     #[serde(flatten)]
     pub product: ProductData,
     pub category_ids: Vec<Uuid>,
 }
+
+/// Partial update of a product. Only the provided columns are updated.
+#[derive(Debug, Default, AsChangeset, Validate)]
+#[table_name = "products"]
+pub struct ProductPatch {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
+    pub name: Option<String>,
+
+    /// Custom slug, an empty slug is generated from the name
+    #[validate(length(max = 100), custom = "validate_slug")]
+    pub slug: Option<String>,
+    #[validate(length(max = 1000))]
+    pub description_short: Option<String>,
+    #[validate(length(max = 20000))]
+    pub description_long: Option<String>,
+    #[validate(range(min = 0))]
+    pub price: Option<i32>,
+    pub manufacturer_id: Option<Option<Uuid>>,
+    #[validate(custom = "validate_product_status")]
+    pub status: Option<String>,
+    #[validate(range(min = 0))]
+    pub stock_count: Option<i32>,
+    #[validate(length(min = 1, max = 64))]
+    pub sku: Option<Option<String>>,
+    #[validate(length(min = 1, max = 200))]
+    pub meta_title: Option<Option<String>>,
+    #[validate(length(min = 1, max = 500))]
+    pub meta_description: Option<Option<String>>,
+    #[validate(length(min = 1, max = 500), custom = "validate_website_url")]
+    pub canonical_url: Option<Option<String>>,
+    pub noindex: Option<bool>,
+    pub publish_at: Option<Option<NaiveDateTime>>,
+    pub unpublish_at: Option<Option<NaiveDateTime>>,
+    #[validate(range(min = 0))]
+    pub sale_price: Option<Option<i32>>,
+    pub sale_starts_at: Option<Option<NaiveDateTime>>,
+    pub sale_ends_at: Option<Option<NaiveDateTime>>,
+    #[validate(custom = "validate_tax_rate")]
+    pub tax_rate: Option<i16>,
+}
+
+impl ProductPatch {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.slug.is_none()
+            && self.description_short.is_none()
+            && self.description_long.is_none()
+            && self.price.is_none()
+            && self.manufacturer_id.is_none()
+            && self.status.is_none()
+            && self.stock_count.is_none()
+            && self.sku.is_none()
+            && self.meta_title.is_none()
+            && self.meta_description.is_none()
+            && self.canonical_url.is_none()
+            && self.noindex.is_none()
+            && self.publish_at.is_none()
+            && self.unpublish_at.is_none()
+            && self.sale_price.is_none()
+            && self.sale_starts_at.is_none()
+            && self.sale_ends_at.is_none()
+            && self.tax_rate.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ProductPatchWithMeta {
+    pub product: ProductPatch,
+    pub category_ids: Option<Vec<Uuid>>,
+}
+
+impl ProductPatchWithMeta {
+    /// Parses and validates a JSON Merge Patch of a product
+    pub fn from_merge_patch(patch: &Value) -> Result<Versioned<Self>, ValidationErrors> {
+        let mut reader = MergePatch::new(patch)?;
+        let product = ProductPatch {
+            name: reader.field("name"),
+            slug: reader.nullable_field("slug").map(Option::unwrap_or_default),
+            description_short: reader.field("description_short"),
+            description_long: reader.field("description_long"),
+            price: reader.field("price"),
+            manufacturer_id: reader.nullable_field("manufacturer_id"),
+            status: reader.field("status"),
+            stock_count: reader.field("stock_count"),
+            sku: reader.nullable_field("sku"),
+            meta_title: reader.nullable_field("meta_title"),
+            meta_description: reader.nullable_field("meta_description"),
+            canonical_url: reader.nullable_field("canonical_url"),
+            noindex: reader.field("noindex"),
+            publish_at: reader.nullable_field("publish_at"),
+            unpublish_at: reader.nullable_field("unpublish_at"),
+            sale_price: reader.nullable_field("sale_price"),
+            sale_starts_at: reader.nullable_field("sale_starts_at"),
+            sale_ends_at: reader.nullable_field("sale_ends_at"),
+            tax_rate: reader.field("tax_rate"),
+        };
+        let category_ids = reader.field("category_ids");
+        let version = reader.field("version");
+
+        if let Err(errors) = product.validate() {
+            reader.errors(errors.into());
+        }
+
+        reader.finish()?;
+        Ok(Versioned {
+            data: Self {
+                product,
+                category_ids,
+            },
+            version,
+        })
+    }
+}
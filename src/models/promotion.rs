@@ -0,0 +1,99 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{validate_discount_type, validate_not_blank, ValidationErrors};
+use crate::schema::promotions;
+
+/// Allowed values for the discount type of a promotion
+pub const DISCOUNT_TYPES: [&str; 2] = ["PERCENTAGE", "FIXED"];
+
+/// Discount on the products of a category, a manufacturer or a set of products
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+pub struct Promotion {
+    pub id: Uuid,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub version: i32,
+    pub name: String,
+    pub discount_type: String,
+
+    /// Percentage for PERCENTAGE discounts, cents for FIXED discounts
+    pub discount_value: i32,
+
+    /// Applies to the products in this category or its subcategories
+    pub category_id: Option<Uuid>,
+    pub manufacturer_id: Option<Uuid>,
+    pub product_ids: Vec<Uuid>,
+    pub starts_at: Option<NaiveDateTime>,
+    pub ends_at: Option<NaiveDateTime>,
+}
+
+impl Promotion {
+    /// Checks if the promotion runs at the provided moment
+    pub fn is_active(&self, now: NaiveDateTime) -> bool {
+        self.starts_at.is_none_or(|at| at <= now) && self.ends_at.is_none_or(|at| at > now)
+    }
+
+    /// Returns the price after the discount, rounded to whole cents
+    pub fn apply(&self, price: i32) -> i32 {
+        let discount = match self.discount_type.as_str() {
+            "PERCENTAGE" => (i64::from(price) * i64::from(self.discount_value) + 50) / 100,
+            _ => i64::from(self.discount_value),
+        };
+        (i64::from(price) - discount).clamp(0, i64::from(price)) as i32
+    }
+}
+
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Validate)]
+#[table_name = "promotions"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct PromotionData {
+    #[validate(length(max = 100), custom = "validate_not_blank")]
+    pub name: String,
+    #[validate(custom = "validate_discount_type")]
+    pub discount_type: String,
+    #[validate(range(min = 1))]
+    pub discount_value: i32,
+    #[serde(default)]
+    pub category_id: Option<Uuid>,
+    #[serde(default)]
+    pub manufacturer_id: Option<Uuid>,
+    #[serde(default)]
+    pub product_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub starts_at: Option<NaiveDateTime>,
+    #[serde(default)]
+    pub ends_at: Option<NaiveDateTime>,
+}
+
+impl PromotionData {
+    /// Validates the fields and the rules which involve multiple fields
+    pub fn check(&self) -> Result<(), ValidationErrors> {
+        let mut errors = match self.validate() {
+            Ok(()) => ValidationErrors::new(),
+            Err(errors) => errors.into(),
+        };
+        if self.discount_type == "PERCENTAGE" && self.discount_value > 100 {
+            errors.add("discount_value", "Should be at most 100 for a percentage");
+        }
+        let targets = [
+            self.category_id.is_some(),
+            self.manufacturer_id.is_some(),
+            !self.product_ids.is_empty(),
+        ];
+        if targets.iter().filter(|&&t| t).count() != 1 {
+            errors.add(
+                "category_id",
+                "Exactly one of category_id, manufacturer_id or product_ids should be set",
+            );
+        }
+        if let (Some(starts_at), Some(ends_at)) = (self.starts_at, self.ends_at) {
+            if ends_at <= starts_at {
+                errors.add("ends_at", "Should be after starts_at");
+            }
+        }
+        errors.into_result()
+    }
+}
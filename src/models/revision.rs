@@ -0,0 +1,69 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use super::{ProductData, ProductDataWithMeta, ProductWithMeta};
+use crate::schema::product_revisions;
+
+/// Snapshot of a product after a change. The revision matches the version of
+/// the product.
+#[derive(Debug, Queryable, Serialize)]
+pub struct ProductRevision {
+    pub product_id: Uuid,
+    pub revision: i32,
+    pub created_at: NaiveDateTime,
+    pub user_issuer: String,
+    pub user_subject: String,
+    pub user_name: Option<String>,
+
+    /// Editable fields and category IDs of the product
+    pub data: Value,
+}
+
+#[derive(Debug, Insertable)]
+#[table_name = "product_revisions"]
+pub struct ProductRevisionData {
+    pub product_id: Uuid,
+    pub revision: i32,
+    pub user_issuer: String,
+    pub user_subject: String,
+    pub user_name: Option<String>,
+    pub data: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiffQuery {
+    pub from: i32,
+    pub to: i32,
+}
+
+impl From<&ProductWithMeta> for ProductDataWithMeta {
+    fn from(product: &ProductWithMeta) -> Self {
+        let p = &product.product;
+        Self {
+            product: ProductData {
+                name: p.name.clone(),
+                slug: p.slug.clone(),
+                description_short: p.description_short.clone(),
+                description_long: p.description_long.clone(),
+                price: p.price,
+                manufacturer_id: p.manufacturer_id,
+                status: p.status.clone(),
+                stock_count: p.stock_count,
+                sku: p.sku.clone(),
+                meta_title: p.meta_title.clone(),
+                meta_description: p.meta_description.clone(),
+                canonical_url: p.canonical_url.clone(),
+                noindex: p.noindex,
+                publish_at: p.publish_at,
+                unpublish_at: p.unpublish_at,
+                sale_price: p.sale_price,
+                sale_starts_at: p.sale_starts_at,
+                sale_ends_at: p.sale_ends_at,
+                tax_rate: p.tax_rate,
+            },
+            category_ids: product.category_ids.clone(),
+        }
+    }
+}
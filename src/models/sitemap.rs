@@ -0,0 +1,18 @@
+use chrono::NaiveDateTime;
+
+/// Type of page listed in the sitemap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SitemapKind {
+    Product,
+    Category,
+    Manufacturer,
+}
+
+/// Entity which has a page in the storefront
+#[derive(Debug)]
+pub struct SitemapRecord {
+    pub kind: SitemapKind,
+    pub slug: String,
+    pub updated_at: NaiveDateTime,
+    pub canonical_url: Option<String>,
+}
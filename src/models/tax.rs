@@ -0,0 +1,155 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::schema::order_taxes;
+
+/// Belgian VAT rates in percent which can be assigned to products
+pub const TAX_RATES: [i16; 4] = [21, 12, 6, 0];
+
+/// Rate of the shipping cost, which is taxed like most products
+pub const SHIPPING_TAX_RATE: i16 = 21;
+
+/// Amount in cents split into the part without and the part of the tax
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TaxAmounts {
+    pub net: i32,
+    pub tax: i32,
+    pub gross: i32,
+}
+
+impl TaxAmounts {
+    /// Splits an amount at the rate. The amount is the gross amount if prices
+    /// include tax, the net amount otherwise. The tax is rounded half up to
+    /// whole cents.
+    pub fn new(amount: i32, rate: i16, prices_include_tax: bool) -> Self {
+        let amount = i64::from(amount);
+        let rate = i64::from(rate);
+        if prices_include_tax {
+            let net = div_round(amount * 100, 100 + rate);
+            Self {
+                net: net as i32,
+                tax: (amount - net) as i32,
+                gross: amount as i32,
+            }
+        } else {
+            let tax = div_round(amount * rate, 100);
+            Self {
+                net: amount as i32,
+                tax: tax as i32,
+                gross: (amount + tax) as i32,
+            }
+        }
+    }
+}
+
+/// Divides and rounds half away from zero
+fn div_round(dividend: i64, divisor: i64) -> i64 {
+    let quotient = (dividend.abs() * 2 + divisor) / (divisor * 2);
+    quotient * dividend.signum()
+}
+
+/// Amounts of an order at a single rate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TaxLine {
+    pub rate: i16,
+    #[serde(flatten)]
+    pub amounts: TaxAmounts,
+}
+
+/// Adds up the amounts per rate and calculates the tax on the sum of each
+/// rate, which is how it's declared. Rates are sorted from high to low.
+pub fn tax_summary(
+    amounts: impl IntoIterator<Item = (i16, i32)>,
+    prices_include_tax: bool,
+) -> Vec<TaxLine> {
+    let mut by_rate: BTreeMap<i16, i32> = BTreeMap::new();
+    for (rate, amount) in amounts {
+        *by_rate.entry(rate).or_default() += amount;
+    }
+    by_rate
+        .into_iter()
+        .rev()
+        .map(|(rate, amount)| TaxLine {
+            rate,
+            amounts: TaxAmounts::new(amount, rate, prices_include_tax),
+        })
+        .collect()
+}
+
+/// Splits the amount over the weights proportionally, handing out the cents
+/// which are left after rounding down to the largest remainders. The parts add
+/// up to the amount if the weights are positive.
+pub fn allocate(amount: i32, weights: &[i32]) -> Vec<i32> {
+    let total: i64 = weights.iter().map(|&w| i64::from(w.max(0))).sum();
+    if total == 0 {
+        return vec![0; weights.len()];
+    }
+    let shares: Vec<(i64, i64)> = weights
+        .iter()
+        .map(|&w| {
+            let share = i64::from(amount) * i64::from(w.max(0));
+            (share / total, share % total)
+        })
+        .collect();
+    let mut parts: Vec<i32> = shares.iter().map(|&(part, _)| part as i32).collect();
+    let left = i64::from(amount) - shares.iter().map(|&(part, _)| part).sum::<i64>();
+    let mut by_remainder: Vec<usize> = (0..weights.len()).collect();
+    by_remainder.sort_by_key(|&i| std::cmp::Reverse(shares[i].1));
+    for &i in by_remainder.iter().take(left as usize) {
+        parts[i] += 1;
+    }
+    parts
+}
+
+/// Tax summary line of an order, stored for the accounts
+#[derive(Debug, Associations, Identifiable, Queryable, Insertable, Serialize)]
+#[belongs_to(super::Order)]
+#[primary_key(order_id, rate)]
+#[table_name = "order_taxes"]
+pub struct OrderTax {
+    #[serde(skip)]
+    pub order_id: Uuid,
+    pub rate: i16,
+    pub net: i32,
+    pub tax: i32,
+    pub gross: i32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tax_amounts() {
+        assert_eq!(
+            TaxAmounts::new(1210, 21, true),
+            TaxAmounts {
+                net: 1000,
+                tax: 210,
+                gross: 1210
+            }
+        );
+        // 995 / 1.06 = 938.68
+        assert_eq!(TaxAmounts::new(995, 6, true).net, 939);
+        // 995 * 0.21 = 208.95
+        assert_eq!(TaxAmounts::new(995, 21, false).tax, 209);
+        assert_eq!(TaxAmounts::new(995, 0, false).gross, 995);
+        assert_eq!(TaxAmounts::new(-1210, 21, true).tax, -210);
+    }
+
+    #[test]
+    fn test_tax_summary() {
+        let summary = tax_summary(vec![(6, 500), (21, 1000), (6, 560), (0, 300)], true);
+        let rates: Vec<_> = summary.iter().map(|l| (l.rate, l.amounts.tax)).collect();
+        assert_eq!(rates, vec![(21, 174), (6, 60), (0, 0)]);
+    }
+
+    #[test]
+    fn test_allocate() {
+        assert_eq!(allocate(100, &[1, 1, 1]), vec![34, 33, 33]);
+        assert_eq!(allocate(250, &[1000, 0, 500]), vec![167, 0, 83]);
+        assert_eq!(allocate(10, &[0, 0]), vec![0, 0]);
+    }
+}
@@ -0,0 +1,280 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{validate_not_blank, validate_slug, Category, Product};
+use crate::schema::{category_translations, product_translations};
+
+/// Locale requested by a visitor with the `lang` query parameter, which takes
+/// precedence over the `Accept-Language` header
+#[derive(Debug, Deserialize)]
+pub struct LocaleQuery {
+    pub lang: Option<String>,
+}
+
+/// Reads the languages of an `Accept-Language` header, most preferred first.
+/// Regions are dropped, so `fr-BE` is read as `fr`.
+pub fn parse_accept_language(header: &str) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = header
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let quality = parts
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse().ok())
+                .unwrap_or(1.0);
+            let language = tag.split('-').next()?.to_lowercase();
+            Some((language, quality))
+        })
+        .filter(|(language, quality)| !language.is_empty() && language != "*" && *quality > 0.0)
+        .collect();
+    // Stable, so languages with the same quality keep their order
+    languages.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    languages
+        .into_iter()
+        .map(|(language, _)| language)
+        .collect()
+}
+
+/// Orders the supported locales in which content is looked up: the requested
+/// locales in order of preference, ending with the default locale, which is
+/// the first supported locale. Unsupported locales are skipped.
+pub fn locale_chain(requested: &[String], supported: &[String]) -> Vec<String> {
+    let default = &supported[0];
+    let mut chain: Vec<String> = Vec::new();
+    for locale in requested {
+        if locale == default {
+            break;
+        }
+        if supported.contains(locale) && !chain.contains(locale) {
+            chain.push(locale.clone());
+        }
+    }
+    chain.push(default.clone());
+    chain
+}
+
+/// Returns the first value which is set and not empty
+fn first_set<'a>(values: impl IntoIterator<Item = &'a Option<String>>) -> Option<&'a String> {
+    values
+        .into_iter()
+        .flatten()
+        .find(|value| !value.trim().is_empty())
+}
+
+/// Checks if a field has content in the default locale which isn't translated
+fn is_missing(original: &str, translation: Option<&Option<String>>) -> bool {
+    !original.trim().is_empty() && first_set(translation).is_none()
+}
+
+/// Content of a product in a locale other than the default locale
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+#[primary_key(product_id, locale)]
+#[table_name = "product_translations"]
+pub struct ProductTranslation {
+    pub product_id: Uuid,
+    pub locale: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub name: Option<String>,
+    pub slug: Option<String>,
+    pub description_short: Option<String>,
+    pub description_long: Option<String>,
+}
+
+/// Translated fields of a product. Fields which aren't set fall back to the
+/// next locale.
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Serialize, Validate)]
+#[table_name = "product_translations"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct ProductTranslationData {
+    /// Taken from the path
+    #[serde(skip_deserializing)]
+    pub product_id: Uuid,
+
+    /// Taken from the path
+    #[serde(skip_deserializing)]
+    pub locale: String,
+    #[serde(default)]
+    #[validate(length(max = 100), custom = "validate_not_blank")]
+    pub name: Option<String>,
+
+    /// Custom slug. Generated from the translated name if empty.
+    #[serde(default)]
+    #[validate(length(max = 100), custom = "validate_slug")]
+    pub slug: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 1000))]
+    pub description_short: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 20000))]
+    pub description_long: Option<String>,
+}
+
+impl Product {
+    /// Replaces the content by its translations, which are ordered by the
+    /// preference of their locale. Fields without translation are kept.
+    pub fn translate(&mut self, translations: &[&ProductTranslation]) {
+        if let Some(name) = first_set(translations.iter().map(|t| &t.name)) {
+            self.name = name.clone();
+        }
+        if let Some(slug) = first_set(translations.iter().map(|t| &t.slug)) {
+            self.slug = slug.clone();
+        }
+        if let Some(short) = first_set(translations.iter().map(|t| &t.description_short)) {
+            self.description_short = short.clone();
+        }
+        if let Some(long) = first_set(translations.iter().map(|t| &t.description_long)) {
+            self.description_long = long.clone();
+        }
+    }
+
+    /// Lists the fields with content which the translation lacks
+    pub fn untranslated_fields(
+        &self,
+        translation: Option<&ProductTranslation>,
+    ) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if is_missing(&self.name, translation.map(|t| &t.name)) {
+            fields.push("name");
+        }
+        if is_missing(
+            &self.description_short,
+            translation.map(|t| &t.description_short),
+        ) {
+            fields.push("description_short");
+        }
+        if is_missing(
+            &self.description_long,
+            translation.map(|t| &t.description_long),
+        ) {
+            fields.push("description_long");
+        }
+        fields
+    }
+}
+
+/// Content of a category in a locale other than the default locale
+#[derive(Debug, Clone, Identifiable, Queryable, Serialize)]
+#[primary_key(category_id, locale)]
+#[table_name = "category_translations"]
+pub struct CategoryTranslation {
+    pub category_id: Uuid,
+    pub locale: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+    pub name: Option<String>,
+    pub slug: Option<String>,
+    pub description: Option<String>,
+}
+
+/// Translated fields of a category. Fields which aren't set fall back to the
+/// next locale.
+#[derive(Debug, Insertable, AsChangeset, Deserialize, Serialize, Validate)]
+#[table_name = "category_translations"]
+#[changeset_options(treat_none_as_null = "true")]
+pub struct CategoryTranslationData {
+    /// Taken from the path
+    #[serde(skip_deserializing)]
+    pub category_id: Uuid,
+
+    /// Taken from the path
+    #[serde(skip_deserializing)]
+    pub locale: String,
+    #[serde(default)]
+    #[validate(length(max = 100), custom = "validate_not_blank")]
+    pub name: Option<String>,
+
+    /// Custom slug. Generated from the translated name if empty.
+    #[serde(default)]
+    #[validate(length(max = 100), custom = "validate_slug")]
+    pub slug: Option<String>,
+    #[serde(default)]
+    #[validate(length(max = 5000))]
+    pub description: Option<String>,
+}
+
+impl Category {
+    /// Replaces the content by its translations, which are ordered by the
+    /// preference of their locale. Fields without translation are kept.
+    pub fn translate(&mut self, translations: &[&CategoryTranslation]) {
+        if let Some(name) = first_set(translations.iter().map(|t| &t.name)) {
+            self.name = name.clone();
+        }
+        if let Some(slug) = first_set(translations.iter().map(|t| &t.slug)) {
+            self.slug = slug.clone();
+        }
+        if let Some(description) = first_set(translations.iter().map(|t| &t.description)) {
+            self.description = description.clone();
+        }
+    }
+
+    /// Lists the fields with content which the translation lacks
+    pub fn untranslated_fields(
+        &self,
+        translation: Option<&CategoryTranslation>,
+    ) -> Vec<&'static str> {
+        let mut fields = Vec::new();
+        if is_missing(&self.name, translation.map(|t| &t.name)) {
+            fields.push("name");
+        }
+        if is_missing(&self.description, translation.map(|t| &t.description)) {
+            fields.push("description");
+        }
+        fields
+    }
+}
+
+/// Product or category of which fields aren't translated to a locale
+#[derive(Debug, Serialize)]
+pub struct MissingTranslation {
+    pub entity_type: &'static str,
+    pub entity_id: Uuid,
+
+    /// Name in the default locale
+    pub name: String,
+    pub locale: String,
+    pub fields: Vec<&'static str>,
+}
+
+/// Query of the missing translations, of all translated locales if no locale
+/// is provided
+#[derive(Debug, Deserialize)]
+pub struct MissingTranslationsQuery {
+    pub locale: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locales(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_accept_language() {
+        assert_eq!(
+            parse_accept_language("en;q=0.5, fr-BE, nl;q=0.8, *;q=0.1, de;q=0"),
+            locales(&["fr", "nl", "en"])
+        );
+        assert!(parse_accept_language("").is_empty());
+    }
+
+    #[test]
+    fn test_locale_chain() {
+        let supported = locales(&["nl", "fr", "en"]);
+        assert_eq!(
+            locale_chain(&locales(&["de", "fr", "en", "fr"]), &supported),
+            locales(&["fr", "en", "nl"])
+        );
+        // Content of the default locale is always complete
+        assert_eq!(
+            locale_chain(&locales(&["nl", "en"]), &supported),
+            locales(&["nl"])
+        );
+        assert_eq!(locale_chain(&[], &supported), locales(&["nl"]));
+    }
+}
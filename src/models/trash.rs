@@ -0,0 +1,29 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Type of entity which can be moved to the trash
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashEntityType {
+    Product,
+    Category,
+    Manufacturer,
+}
+
+/// Entity in the trash
+#[derive(Debug, Serialize)]
+pub struct TrashItem {
+    pub entity_type: TrashEntityType,
+    pub id: Uuid,
+    pub name: String,
+    pub deleted_at: NaiveDateTime,
+
+    /// Moment the entity will be deleted permanently
+    pub purge_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TrashFilter {
+    pub entity_type: Option<TrashEntityType>,
+}
@@ -0,0 +1,252 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use failure::Fail;
+use serde::Serialize;
+use serde_json::Value;
+use url::Url;
+use validator::ValidationError;
+
+use super::{COUPON_TYPES, DISCOUNT_TYPES, PRODUCT_STATUSES, ROUNDING_MODES, TAX_RATES};
+
+/// Validation errors per field, serialized as `{"field": ["message", ...]}`
+#[derive(Debug, Default, Serialize, Fail)]
+#[serde(transparent)]
+pub struct ValidationErrors(BTreeMap<String, Vec<String>>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0
+            .entry(field.to_string())
+            .or_default()
+            .push(message.into());
+    }
+
+    /// Adds all errors of another instance
+    pub fn extend(&mut self, other: ValidationErrors) {
+        for (field, messages) in other.0 {
+            self.0.entry(field).or_default().extend(messages);
+        }
+    }
+
+    /// Returns all messages prefixed with their field
+    pub fn messages(&self) -> Vec<String> {
+        self.0
+            .iter()
+            .flat_map(|(field, messages)| messages.iter().map(move |m| format!("{}: {}", field, m)))
+            .collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns an error if any validation error was added
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl From<validator::ValidationErrors> for ValidationErrors {
+    fn from(errors: validator::ValidationErrors) -> Self {
+        let mut result = Self::new();
+        for (field, errors) in errors.field_errors() {
+            for error in errors {
+                result.add(field, describe(error));
+            }
+        }
+        result
+    }
+}
+
+/// Builds a human readable message for an error of a built-in validator
+fn describe(error: &ValidationError) -> String {
+    if let Some(message) = &error.message {
+        return message.to_string();
+    }
+    let param = |name| error.params.get(name).map(format_number);
+    let (unit, min, max) = match error.code.as_ref() {
+        "length" => (" characters", param("min"), param("max")),
+        "range" => ("", param("min"), param("max")),
+        code => return code.to_string(),
+    };
+    match (min, max) {
+        (Some(min), Some(max)) => format!("Should be between {} and {}{}", min, max, unit),
+        (Some(min), None) => format!("Should be at least {}{}", min, unit),
+        (None, Some(max)) => format!("Should be at most {}{}", max, unit),
+        (None, None) => error.code.to_string(),
+    }
+}
+
+/// Formats numeric parameters, which are stored as floats, without trailing ".0"
+fn format_number(value: &Value) -> String {
+    match value.as_f64() {
+        Some(n) if n.fract() == 0.0 => format!("{}", n as i64),
+        _ => value.to_string(),
+    }
+}
+
+fn error(code: &'static str, message: &'static str) -> ValidationError {
+    ValidationError {
+        message: Some(message.into()),
+        ..ValidationError::new(code)
+    }
+}
+
+/// Rejects values which are empty or only contain whitespace
+pub fn validate_not_blank(value: &str) -> Result<(), ValidationError> {
+    if value.trim().is_empty() {
+        return Err(error("blank", "Should not be empty"));
+    }
+    Ok(())
+}
+
+pub fn validate_product_status(value: &str) -> Result<(), ValidationError> {
+    if !PRODUCT_STATUSES.contains(&value) {
+        return Err(error("status", "Should be AVAILABLE or ARCHIVED"));
+    }
+    Ok(())
+}
+
+pub fn validate_discount_type(value: &str) -> Result<(), ValidationError> {
+    if !DISCOUNT_TYPES.contains(&value) {
+        return Err(error("discount_type", "Should be PERCENTAGE or FIXED"));
+    }
+    Ok(())
+}
+
+pub fn validate_coupon_type(value: &str) -> Result<(), ValidationError> {
+    if !COUPON_TYPES.contains(&value) {
+        return Err(error(
+            "discount_type",
+            "Should be PERCENTAGE, FIXED or FREE_SHIPPING",
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_tax_rate(value: i16) -> Result<(), ValidationError> {
+    if !TAX_RATES.contains(&value) {
+        return Err(error("tax_rate", "Should be 21, 12, 6 or 0"));
+    }
+    Ok(())
+}
+
+/// Accepts ISO 4217 codes like EUR
+pub fn validate_currency_code(value: &str) -> Result<(), ValidationError> {
+    if value.len() != 3 || !value.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(error("currency", "Should be a code of 3 letters like USD"));
+    }
+    Ok(())
+}
+
+pub fn validate_rounding_mode(value: &str) -> Result<(), ValidationError> {
+    if !ROUNDING_MODES.contains(&value) {
+        return Err(error("rounding", "Should be NEAREST, UP or DOWN"));
+    }
+    Ok(())
+}
+
+/// Accepts uppercase letters, digits and hyphens
+pub fn validate_coupon_code(value: &str) -> Result<(), ValidationError> {
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(error(
+            "code",
+            "Should only contain letters, digits and hyphens",
+        ));
+    }
+    Ok(())
+}
+
+/// Accepts an empty value or lowercase words of letters and digits separated by hyphens
+pub fn validate_slug(value: &str) -> Result<(), ValidationError> {
+    let valid = value.split('-').all(|word| {
+        !word.is_empty()
+            && word
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+    });
+    if !value.is_empty() && !valid {
+        return Err(error(
+            "slug",
+            "Should only contain lowercase letters, digits and hyphens",
+        ));
+    }
+    Ok(())
+}
+
+/// Accepts an empty value or an absolute http(s) URL
+pub fn validate_website_url(value: &str) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        return Ok(());
+    }
+    match Url::parse(value) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => Ok(()),
+        _ => Err(error("url", "Should be a valid http(s) URL")),
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields: Vec<_> = self.0.keys().map(String::as_str).collect();
+        write!(f, "Validation failed for: {}", fields.join(", "))
+    }
+}
+
+impl ResponseError for ValidationErrors {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::UNPROCESSABLE_ENTITY
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code()).json(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use validator::Validate;
+
+    #[derive(Validate)]
+    struct Form {
+        #[validate(length(min = 1, max = 5), custom = "validate_not_blank")]
+        name: String,
+        #[validate(range(min = 0))]
+        price: i32,
+        #[validate(custom = "validate_website_url")]
+        website_url: String,
+        #[validate(custom = "validate_slug")]
+        slug: String,
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let form = Form {
+            name: "  ".to_string(),
+            price: -1,
+            website_url: "ftp://example.com".to_string(),
+            slug: "Zeep--1".to_string(),
+        };
+        let errors = ValidationErrors::from(form.validate().unwrap_err());
+        let expected = serde_json::json!({
+            "name": ["Should not be empty"],
+            "price": ["Should be at least 0"],
+            "website_url": ["Should be a valid http(s) URL"],
+            "slug": ["Should only contain lowercase letters, digits and hyphens"],
+        });
+        assert_eq!(serde_json::to_value(errors).unwrap(), expected);
+    }
+}
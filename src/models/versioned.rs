@@ -0,0 +1,12 @@
+use serde::Deserialize;
+
+/// Form data with the version of the entity the changes are based on
+#[derive(Debug, Deserialize)]
+pub struct Versioned<T> {
+    #[serde(flatten)]
+    pub data: T,
+
+    /// Update is rejected if the entity has a different version
+    #[serde(default)]
+    pub version: Option<i32>,
+}
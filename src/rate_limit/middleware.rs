@@ -0,0 +1,113 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::Error;
+use futures::future::{ok, LocalBoxFuture, Ready};
+
+use super::{client_ip, LimitClass, RateLimitStore, RateLimits, TooManyRequests};
+use crate::models::IpNetwork;
+
+/// Header containing the chain of client and proxy addresses
+const FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+
+/// Middleware which limits the request rate per client IP and class of
+/// endpoints. Requests exceeding the limit are rejected with status 429.
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    limits: Arc<RateLimits>,
+    trusted_proxies: Arc<Vec<IpNetwork>>,
+}
+
+impl RateLimiter {
+    pub fn new(store: Arc<dyn RateLimitStore>, limits: RateLimits) -> Self {
+        Self {
+            store,
+            limits: Arc::new(limits),
+            trusted_proxies: Arc::new(vec![]),
+        }
+    }
+
+    /// Proxies which are trusted to provide the client IP in `X-Forwarded-For`
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpNetwork>) -> Self {
+        self.trusted_proxies = Arc::new(trusted_proxies);
+        self
+    }
+}
+
+impl<S, B> Transform<S> for RateLimiter
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = RateLimiterMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(RateLimiterMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            config: self.clone(),
+        })
+    }
+}
+
+pub struct RateLimiterMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    config: RateLimiter,
+}
+
+impl<S, B> Service for RateLimiterMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let class = LimitClass::of(req.path());
+        let forwarded_for = req
+            .headers()
+            .get(FORWARDED_FOR_HEADER)
+            .and_then(|v| v.to_str().ok());
+        let client = client_ip(
+            req.peer_addr().map(|a| a.ip()),
+            forwarded_for,
+            &self.config.trusted_proxies,
+        );
+        let acquired = match (self.config.limits.get(class), client) {
+            (Some(limit), Some(client)) => {
+                let key = format!("{}:{}", class.as_str(), client);
+                Some(self.config.store.acquire(&key, limit))
+            }
+            _ => None,
+        };
+
+        Box::pin(async move {
+            if let Some(acquired) = acquired {
+                acquired.await.map_err(|retry_after| {
+                    log::debug!("Rate limit of class {} exceeded", class.as_str());
+                    TooManyRequests::new(retry_after)
+                })?;
+            }
+            let res = service.borrow_mut().call(req);
+            res.await
+        })
+    }
+}
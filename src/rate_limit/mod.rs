@@ -0,0 +1,144 @@
+// Derives of failure predate this lint
+#![allow(non_local_definitions)]
+
+mod middleware;
+mod store;
+
+pub use self::{middleware::*, store::*};
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use actix_web::{http::header, http::StatusCode, HttpResponse, ResponseError};
+use failure::Fail;
+
+use crate::models::{IpNetwork, RateLimit};
+
+/// Group of endpoints sharing a rate limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LimitClass {
+    Listing,
+    Cart,
+}
+
+impl LimitClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitClass::Listing => "listing",
+            LimitClass::Cart => "cart",
+        }
+    }
+
+    /// Classifies a request on the public API based on the scope of its route
+    pub fn of(path: &str) -> Self {
+        let scope = path.trim_start_matches("/public/").split('/').next();
+        match scope {
+            Some("carts") => LimitClass::Cart,
+            _ => LimitClass::Listing,
+        }
+    }
+}
+
+/// Rate limits per class of endpoints. `None` disables limiting for the class.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    pub listing: Option<RateLimit>,
+    pub cart: Option<RateLimit>,
+}
+
+impl RateLimits {
+    pub fn get(&self, class: LimitClass) -> Option<&RateLimit> {
+        match class {
+            LimitClass::Listing => self.listing.as_ref(),
+            LimitClass::Cart => self.cart.as_ref(),
+        }
+    }
+}
+
+/// Resolves the IP address of the client. If the peer is a trusted proxy, the
+/// `X-Forwarded-For` header is walked from right to left and the first address
+/// which isn't a trusted proxy is returned.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpNetwork],
+) -> Option<IpAddr> {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|n| n.contains(ip));
+    let mut client = peer?;
+    if !is_trusted(client) {
+        return Some(client);
+    }
+
+    let hops = forwarded_for.unwrap_or_default().rsplit(',').map(str::trim);
+    for hop in hops.filter(|h| !h.is_empty()) {
+        match hop.parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    Some(client)
+}
+
+#[derive(Debug, Fail)]
+#[fail(display = "Too many requests, retry after {} seconds", _0)]
+pub struct TooManyRequests(u64);
+
+impl TooManyRequests {
+    pub fn new(retry_after: Duration) -> Self {
+        let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+        Self(secs.max(1))
+    }
+}
+
+impl ResponseError for TooManyRequests {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .header(header::RETRY_AFTER, self.0.to_string())
+            .body(self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_class() {
+        assert_eq!(LimitClass::of("/public/products"), LimitClass::Listing);
+        assert_eq!(
+            LimitClass::of("/public/products/slug/carts"),
+            LimitClass::Listing
+        );
+        assert_eq!(LimitClass::of("/public/carts"), LimitClass::Cart);
+        assert_eq!(LimitClass::of("/public/carts/0/items/1"), LimitClass::Cart);
+    }
+
+    #[test]
+    fn test_client_ip() {
+        let trusted = vec![IpNetwork {
+            addr: "10.0.0.0".parse().unwrap(),
+            prefix_len: 8,
+        }];
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // Untrusted peer can't spoof its address
+        let result = client_ip(Some(ip("1.2.3.4")), Some("5.6.7.8"), &trusted);
+        assert_eq!(result, Some(ip("1.2.3.4")));
+
+        // Trusted proxies are skipped
+        let xff = "5.6.7.8, 9.9.9.9, 10.0.0.2";
+        let result = client_ip(Some(ip("10.0.0.1")), Some(xff), &trusted);
+        assert_eq!(result, Some(ip("9.9.9.9")));
+
+        // Trusted proxy without header
+        let result = client_ip(Some(ip("10.0.0.1")), None, &trusted);
+        assert_eq!(result, Some(ip("10.0.0.1")));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use futures::future::{ready, LocalBoxFuture};
+
+use crate::models::RateLimit;
+
+/// Interval at which buckets which are completely refilled are removed
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Storage of the token buckets. Implement this trait to share buckets between
+/// multiple instances, e.g. in Redis.
+pub trait RateLimitStore: Send + Sync {
+    /// Takes a token from the bucket identified by `key`. If the bucket is empty,
+    /// the duration until the next token is available is returned as error.
+    fn acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> LocalBoxFuture<'static, Result<(), Duration>>;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn full(limit: &RateLimit, now: Instant) -> Self {
+        Self {
+            tokens: f64::from(limit.capacity),
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &RateLimit, now: Instant) {
+        let rate = f64::from(limit.capacity) / limit.period.as_secs_f64();
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(f64::from(limit.capacity));
+        self.updated_at = now;
+    }
+
+    fn take(&mut self, limit: &RateLimit, now: Instant) -> Result<(), Duration> {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            let rate = f64::from(limit.capacity) / limit.period.as_secs_f64();
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / rate))
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MemoryState {
+    buckets: HashMap<String, (Bucket, RateLimit)>,
+    pruned_at: Instant,
+}
+
+/// Store which keeps the buckets in memory of the current process
+#[derive(Debug)]
+pub struct MemoryStore {
+    state: Mutex<MemoryState>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MemoryState {
+                buckets: HashMap::new(),
+                pruned_at: Instant::now(),
+            }),
+        }
+    }
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RateLimitStore for MemoryStore {
+    fn acquire(
+        &self,
+        key: &str,
+        limit: &RateLimit,
+    ) -> LocalBoxFuture<'static, Result<(), Duration>> {
+        let now = Instant::now();
+        let mut state = self.state.lock().expect("Rate limit store is poisoned");
+
+        // Full buckets are equal to missing buckets
+        if now.saturating_duration_since(state.pruned_at) >= PRUNE_INTERVAL {
+            state.buckets.retain(|_, (bucket, limit)| {
+                now.saturating_duration_since(bucket.updated_at) < limit.period
+            });
+            state.pruned_at = now;
+        }
+
+        let (bucket, _) = state
+            .buckets
+            .entry(key.to_string())
+            .or_insert_with(|| (Bucket::full(limit, now), *limit));
+        Box::pin(ready(bucket.take(limit, now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket() {
+        let limit = RateLimit {
+            capacity: 2,
+            period: Duration::from_secs(10),
+        };
+        let start = Instant::now();
+        let mut bucket = Bucket::full(&limit, start);
+
+        assert!(bucket.take(&limit, start).is_ok());
+        assert!(bucket.take(&limit, start).is_ok());
+        assert_eq!(bucket.take(&limit, start), Err(Duration::from_secs(5)));
+
+        // Refilled with one token after 5 seconds
+        let later = start + Duration::from_secs(5);
+        assert!(bucket.take(&limit, later).is_ok());
+        assert!(bucket.take(&limit, later).is_err());
+    }
+}
@@ -1,3 +1,48 @@
+table! {
+    api_keys (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Array<Text>,
+        expires_at -> Nullable<Timestamp>,
+        last_used_at -> Nullable<Timestamp>,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    audit_log (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        user_issuer -> Text,
+        user_subject -> Text,
+        user_name -> Nullable<Text>,
+        entity_type -> Text,
+        entity_id -> Uuid,
+        action -> Text,
+        diff -> Jsonb,
+    }
+}
+
+table! {
+    cart_items (cart_id, product_id) {
+        cart_id -> Uuid,
+        product_id -> Uuid,
+        quantity -> Int4,
+    }
+}
+
+table! {
+    carts (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        coupon_id -> Nullable<Uuid>,
+    }
+}
+
 table! {
     categories (id) {
         id -> Uuid,
@@ -5,6 +50,17 @@ table! {
         slug -> Text,
         description -> Text,
         sort_order -> Int2,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        version -> Int4,
+        meta_title -> Nullable<Text>,
+        meta_description -> Nullable<Text>,
+        canonical_url -> Nullable<Text>,
+        noindex -> Bool,
+        parent_id -> Nullable<Uuid>,
+        deleted_at -> Nullable<Timestamp>,
+        publish_at -> Nullable<Timestamp>,
+        unpublish_at -> Nullable<Timestamp>,
     }
 }
 
@@ -15,12 +71,128 @@ table! {
     }
 }
 
+table! {
+    category_translations (category_id, locale) {
+        category_id -> Uuid,
+        locale -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        name -> Nullable<Text>,
+        slug -> Nullable<Text>,
+        description -> Nullable<Text>,
+    }
+}
+
+table! {
+    coupons (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        version -> Int4,
+        code -> Text,
+        discount_type -> Text,
+        discount_value -> Int4,
+        minimum_order_value -> Int4,
+        category_ids -> Array<Uuid>,
+        manufacturer_ids -> Array<Uuid>,
+        usage_limit -> Nullable<Int4>,
+        usage_limit_per_customer -> Nullable<Int4>,
+        starts_at -> Nullable<Timestamp>,
+        ends_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    exchange_rates (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        currency -> Text,
+        rate -> Float8,
+        decimals -> Int2,
+        rounding -> Text,
+        rounding_increment -> Int4,
+    }
+}
+
 table! {
     manufacturers (id) {
         id -> Uuid,
         name -> Text,
         slug -> Text,
         website_url -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        version -> Int4,
+        deleted_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    order_items (order_id, line) {
+        order_id -> Uuid,
+        line -> Int4,
+        product_id -> Nullable<Uuid>,
+        name -> Text,
+        quantity -> Int4,
+        unit_price -> Int4,
+        total -> Int4,
+        tax_rate -> Int2,
+        net -> Int4,
+        tax -> Int4,
+    }
+}
+
+table! {
+    order_taxes (order_id, rate) {
+        order_id -> Uuid,
+        rate -> Int2,
+        net -> Int4,
+        tax -> Int4,
+        gross -> Int4,
+    }
+}
+
+table! {
+    orders (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        customer_email -> Text,
+        coupon_id -> Nullable<Uuid>,
+        coupon_code -> Nullable<Text>,
+        subtotal -> Int4,
+        discount -> Int4,
+        shipping -> Int4,
+        total -> Int4,
+        prices_include_tax -> Bool,
+        net -> Int4,
+        tax -> Int4,
+        currency -> Text,
+    }
+}
+
+table! {
+    product_revisions (product_id, revision) {
+        product_id -> Uuid,
+        revision -> Int4,
+        created_at -> Timestamp,
+        user_issuer -> Text,
+        user_subject -> Text,
+        user_name -> Nullable<Text>,
+        data -> Jsonb,
+    }
+}
+
+table! {
+    product_translations (product_id, locale) {
+        product_id -> Uuid,
+        locale -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        name -> Nullable<Text>,
+        slug -> Nullable<Text>,
+        description_short -> Nullable<Text>,
+        description_long -> Nullable<Text>,
     }
 }
 
@@ -37,16 +209,81 @@ table! {
         manufacturer_id -> Nullable<Uuid>,
         status -> Text,
         stock_count -> Int4,
+        version -> Int4,
+        sku -> Nullable<Text>,
+        meta_title -> Nullable<Text>,
+        meta_description -> Nullable<Text>,
+        canonical_url -> Nullable<Text>,
+        noindex -> Bool,
+        deleted_at -> Nullable<Timestamp>,
+        publish_at -> Nullable<Timestamp>,
+        unpublish_at -> Nullable<Timestamp>,
+        sale_price -> Nullable<Int4>,
+        sale_starts_at -> Nullable<Timestamp>,
+        sale_ends_at -> Nullable<Timestamp>,
+        tax_rate -> Int2,
+    }
+}
+
+table! {
+    promotions (id) {
+        id -> Uuid,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+        version -> Int4,
+        name -> Text,
+        discount_type -> Text,
+        discount_value -> Int4,
+        category_id -> Nullable<Uuid>,
+        manufacturer_id -> Nullable<Uuid>,
+        product_ids -> Array<Uuid>,
+        starts_at -> Nullable<Timestamp>,
+        ends_at -> Nullable<Timestamp>,
+    }
+}
+
+table! {
+    slug_history (slug) {
+        slug -> Text,
+        product_id -> Uuid,
+        retired_at -> Timestamp,
     }
 }
 
+joinable!(cart_items -> carts (cart_id));
+joinable!(cart_items -> products (product_id));
+joinable!(carts -> coupons (coupon_id));
 joinable!(category_products -> categories (category_id));
 joinable!(category_products -> products (product_id));
+joinable!(category_translations -> categories (category_id));
+joinable!(order_items -> orders (order_id));
+joinable!(order_items -> products (product_id));
+joinable!(order_taxes -> orders (order_id));
+joinable!(orders -> coupons (coupon_id));
+joinable!(product_revisions -> products (product_id));
+joinable!(product_translations -> products (product_id));
 joinable!(products -> manufacturers (manufacturer_id));
+joinable!(promotions -> categories (category_id));
+joinable!(promotions -> manufacturers (manufacturer_id));
+joinable!(slug_history -> products (product_id));
 
 allow_tables_to_appear_in_same_query!(
+    api_keys,
+    audit_log,
+    cart_items,
+    carts,
     categories,
     category_products,
+    category_translations,
+    coupons,
+    exchange_rates,
     manufacturers,
+    order_items,
+    order_taxes,
+    orders,
+    product_revisions,
+    product_translations,
     products,
+    promotions,
+    slug_history,
 );
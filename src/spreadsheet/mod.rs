@@ -0,0 +1,178 @@
+use std::fs::File;
+use std::io::{Cursor, Seek, SeekFrom};
+
+use calamine::{open_workbook_from_rs, Reader, Xlsx};
+use failure::{format_err, Error};
+use rust_xlsxwriter::{Format, Workbook};
+
+/// Magic bytes of a ZIP archive, which is the container format of XLSX
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// Sheet with a header row. All cells are read as text.
+#[derive(Debug, Default, PartialEq)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Row number as shown in a spreadsheet application, with the header on row 1
+    pub fn row_number(index: usize) -> usize {
+        index + 2
+    }
+}
+
+/// Reads the first sheet of an XLSX file or a CSV file with the given delimiter.
+/// The format is detected from the content. Empty rows are skipped.
+pub fn read_table(data: &[u8], delimiter: u8) -> Result<Table, Error> {
+    let cells = if data.starts_with(ZIP_MAGIC) {
+        read_xlsx(data)?
+    } else {
+        read_csv(data, delimiter)?
+    };
+
+    let mut rows = cells.into_iter().map(|row| {
+        row.into_iter()
+            .map(|cell| cell.trim().to_string())
+            .collect::<Vec<_>>()
+    });
+    let mut headers = rows.next().ok_or_else(|| format_err!("File is empty"))?;
+    if let Some(first) = headers.first_mut() {
+        *first = first.trim_start_matches('\u{feff}').to_string();
+    }
+
+    let rows = rows
+        .map(|mut row| {
+            row.resize(headers.len().max(row.len()), String::new());
+            row
+        })
+        .collect::<Vec<_>>();
+    Ok(Table { headers, rows })
+}
+
+fn read_csv(data: &[u8], delimiter: u8) -> Result<Vec<Vec<String>>, Error> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(data);
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        if record.iter().any(|cell| !cell.trim().is_empty()) {
+            rows.push(record.iter().map(str::to_string).collect());
+        }
+    }
+    Ok(rows)
+}
+
+fn read_xlsx(data: &[u8]) -> Result<Vec<Vec<String>>, Error> {
+    let mut workbook: Xlsx<_> = open_workbook_from_rs(Cursor::new(data))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| format_err!("Workbook doesn't contain a sheet"))??;
+    let rows = range
+        .rows()
+        .map(|row| row.iter().map(|cell| cell.to_string()).collect::<Vec<_>>())
+        .filter(|row| row.iter().any(|cell| !cell.trim().is_empty()))
+        .collect();
+    Ok(rows)
+}
+
+/// Encodes rows as CSV, preceded by the headers if provided
+pub fn write_csv(headers: Option<&[&str]>, rows: &[Vec<String>]) -> Result<Vec<u8>, Error> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    if let Some(headers) = headers {
+        writer.write_record(headers)?;
+    }
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+/// Writes a single sheet XLSX file. Rows are flushed to temporary files, so
+/// only the current row is kept in memory.
+pub struct XlsxWriter {
+    workbook: Workbook,
+    numeric_columns: Vec<usize>,
+    row: u32,
+}
+
+impl XlsxWriter {
+    /// Creates a sheet with a bold header row. Cells in `numeric_columns` are
+    /// written as numbers if they can be parsed.
+    pub fn new(headers: &[&str], numeric_columns: &[usize]) -> Result<Self, Error> {
+        let mut workbook = Workbook::new();
+        let sheet = workbook.add_worksheet_with_constant_memory();
+        let bold = Format::new().set_bold();
+        for (col, header) in headers.iter().enumerate() {
+            sheet.write_string_with_format(0, col as u16, *header, &bold)?;
+        }
+        sheet.set_freeze_panes(1, 0)?;
+        Ok(Self {
+            workbook,
+            numeric_columns: numeric_columns.to_vec(),
+            row: 1,
+        })
+    }
+
+    pub fn write_rows(&mut self, rows: &[Vec<String>]) -> Result<(), Error> {
+        let numeric_columns = &self.numeric_columns;
+        let sheet = self.workbook.worksheet_from_index(0)?;
+        for row in rows {
+            for (col, value) in row.iter().enumerate() {
+                let number = Some(col)
+                    .filter(|c| numeric_columns.contains(c))
+                    .and_then(|_| value.parse::<f64>().ok());
+                match number {
+                    Some(number) => sheet.write_number(self.row, col as u16, number)?,
+                    None => sheet.write_string(self.row, col as u16, value)?,
+                };
+            }
+            self.row += 1;
+        }
+        Ok(())
+    }
+
+    /// Assembles the file in an anonymous temporary file, positioned at the start
+    pub fn finish(mut self) -> Result<File, Error> {
+        let mut file = tempfile::tempfile()?;
+        self.workbook.save_to_writer(&mut file)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_csv() {
+        let data = "\u{feff}SKU;Naam;Prijs\nA1; Zeep ;\"3,50\"\n;;\nA2;Shampoo\n";
+        let table = read_table(data.as_bytes(), b';').unwrap();
+        assert_eq!(table.headers, vec!["SKU", "Naam", "Prijs"]);
+        assert_eq!(
+            table.rows,
+            vec![vec!["A1", "Zeep", "3,50"], vec!["A2", "Shampoo", ""]]
+        );
+    }
+
+    #[test]
+    fn test_write_read_roundtrip() {
+        let headers = ["sku", "price"];
+        let rows = vec![vec!["007".to_string(), "3.5".to_string()]];
+
+        let csv = write_csv(Some(&headers), &rows).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "sku,price\n007,3.5\n");
+
+        let mut writer = XlsxWriter::new(&headers, &[1]).unwrap();
+        writer.write_rows(&rows).unwrap();
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut writer.finish().unwrap(), &mut data).unwrap();
+        let table = read_table(&data, b',').unwrap();
+        assert_eq!(table.headers, headers);
+        assert_eq!(table.rows, rows);
+    }
+}